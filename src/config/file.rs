@@ -0,0 +1,624 @@
+// User settings from $XDG_CONFIG_HOME/findi/config.toml (falling back to
+// ~/.config/findi/config.toml). Every key is optional. Named profiles under
+// [profiles.<name>] override the top level sections and are picked with
+// --profile. Command line flags override both.
+
+use super::AppConfig;
+use crate::network::host::parse_resolvers;
+use crate::network::probe::parse_ping_methods;
+use crate::network::service_probes::ServiceProbes;
+use crate::network::tcp_ping::parse_portlist;
+use crate::network::timing::{Timing, TimingTemplate};
+use crate::ui::event::Key;
+use crate::ui::keymap::{Command, Keymap};
+use crate::ui::theme::{parse_color, Theme, BUILTIN_THEMES};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use toml::value::{Table, Value};
+use toml_edit::{Document, Item, TableLike};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub scan: ScanSettings,
+    pub timing: TimingSettings,
+    pub ports: PortSettings,
+    pub resolve: ResolveSettings,
+    pub output: OutputSettings,
+    pub ui: UiSettings,
+    pub themes: BTreeMap<String, ThemeSettings>,
+    pub keys: BTreeMap<String, KeyList>,
+}
+
+// Probe selection and what's gathered from live hosts
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanSettings {
+    pub workers: Option<usize>,
+    pub ping: Option<Vec<String>>,
+    pub ping_mode: Option<String>,
+    pub ping_policy: Option<String>,
+    pub tcp_ping_ports: Option<String>,
+    pub max_rate: Option<u32>,
+    pub max_connections: Option<usize>,
+    pub randomize: Option<bool>,
+    pub interleave: Option<bool>,
+    pub arp: Option<bool>,
+    pub banners: Option<bool>,
+    pub http: Option<bool>,
+    pub ssh: Option<bool>,
+    pub os: Option<bool>,
+    pub service_probes: Option<String>,
+}
+
+// A template plus overrides of single values, times in ms
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimingSettings {
+    pub template: Option<String>,
+    pub max_rtt_timeout: Option<u64>,
+    pub ping_timeout: Option<u64>,
+    pub max_retries: Option<u32>,
+    pub scan_delay: Option<u64>,
+}
+
+// Port lists scanned on live hosts, as given to -p and -u
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PortSettings {
+    pub tcp: Option<String>,
+    pub udp: Option<String>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResolveSettings {
+    pub resolvers: Option<Vec<String>>,
+    pub all: Option<bool>,
+    pub snmp: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputSettings {
+    pub file: Option<String>,
+    pub no_ui: Option<bool>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiSettings {
+    pub tick_len: Option<usize>,
+    pub theme: Option<String>,
+}
+
+// Keys of a command under [keys], replacing its default ones
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+// A custom theme under [themes.<name>], colors it doesn't set come from its
+// base theme
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeSettings {
+    pub base: Option<String>,
+    pub border: Option<String>,
+    pub focus: Option<String>,
+    pub selected_bg: Option<String>,
+    pub header_bg: Option<String>,
+    pub ok: Option<String>,
+    pub warn: Option<String>,
+    pub alert: Option<String>,
+    pub info: Option<String>,
+    pub accent: Option<String>,
+    pub muted: Option<String>,
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("findi")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("findi")),
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(CONFIG_FILE))
+}
+
+impl Settings {
+    // Without a path, a missing default config file is the same as an empty one
+    pub fn load(path: Option<&str>, profile: Option<&str>) -> Result<Settings> {
+        let path = match path.map(PathBuf::from) {
+            Some(path) => path,
+            None => match config_path().filter(|path| path.exists()) {
+                Some(path) => path,
+                None if profile.is_some() => {
+                    return Err(anyhow!("No config file found to take the profile from"))
+                }
+                None => return Ok(Settings::default()),
+            },
+        };
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        Settings::parse(&contents, profile)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn parse(contents: &str, profile: Option<&str>) -> Result<Settings> {
+        let mut root: Table = toml::from_str(contents)?;
+        let profiles = match root.remove("profiles") {
+            Some(Value::Table(profiles)) => profiles,
+            Some(_) => return Err(anyhow!("profiles must be a table of named profiles")),
+            None => Table::new(),
+        };
+
+        let settings: Settings = Value::Table(root.clone()).try_into()?;
+        for (name, table) in &profiles {
+            table
+                .clone()
+                .try_into::<Settings>()
+                .with_context(|| format!("In profile {}", name))?;
+        }
+
+        match profile {
+            Some(name) => {
+                let table = match profiles.get(name) {
+                    Some(Value::Table(table)) => table.clone(),
+                    _ => {
+                        let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                        return Err(anyhow!(
+                            "Unknown profile {}, the config file has: {}",
+                            name,
+                            match names.len() {
+                                0 => "none".to_owned(),
+                                _ => names.join(", "),
+                            }
+                        ));
+                    }
+                };
+                merge(&mut root, table);
+                Ok(Value::Table(root).try_into()?)
+            }
+            None => Ok(settings),
+        }
+    }
+
+    // Sets the config values given in the file, errors name the bad key
+    pub fn apply(&self, config: &mut AppConfig) -> Result<()> {
+        let scan = &self.scan;
+        match scan.workers {
+            Some(0) => return Err(anyhow!("scan.workers: At least one worker is needed")),
+            Some(workers) => config.nworkers = workers,
+            None => {}
+        }
+        if let Some(methods) = &scan.ping {
+            config.ping_methods = parse_ping_methods(&methods.join(",")).context("scan.ping")?;
+        }
+        if let Some(mode) = &scan.ping_mode {
+            config.ping_mode = mode.parse().context("scan.ping_mode")?;
+        }
+        if let Some(policy) = &scan.ping_policy {
+            config.ping_policy = policy.parse().context("scan.ping_policy")?;
+        }
+        if let Some(ports) = &scan.tcp_ping_ports {
+            config.tcp_ping_ports = match parse_portlist(ports) {
+                Ok(ports) if !ports.is_empty() => ports,
+                _ => return Err(anyhow!("scan.tcp_ping_ports: Could not parse {}", ports)),
+            };
+        }
+        // 0 is no limit, like leaving them out
+        if let Some(rate) = scan.max_rate {
+            config.max_rate = Some(rate).filter(|&rate| rate > 0);
+        }
+        if let Some(max) = scan.max_connections {
+            config.max_connections = Some(max).filter(|&max| max > 0);
+        }
+        let flags = [
+            (scan.randomize, &mut config.randomize_targets),
+            (scan.interleave, &mut config.interleave_ports),
+            (scan.arp, &mut config.arp_sweep),
+            (scan.banners, &mut config.banner_grab),
+            (scan.http, &mut config.http_fingerprint),
+            (scan.ssh, &mut config.ssh_host_keys),
+            (scan.os, &mut config.os_fingerprint),
+            (self.resolve.all, &mut config.resolve_unresponsive),
+        ];
+        for (value, flag) in flags {
+            if let Some(value) = value {
+                *flag = value;
+            }
+        }
+        if let Some(path) = &scan.service_probes {
+            let probes = ServiceProbes::load(path).context("scan.service_probes")?;
+            config.service_probes = Some(Arc::new(probes));
+        }
+
+        config.timing = self.timing.timing(None)?;
+
+        // The lists themselves are taken by main, as if given to -p and -u.
+        // An empty list, as saved from the settings page, scans no ports.
+        for (key, ports) in [
+            ("ports.tcp", &self.ports.tcp),
+            ("ports.udp", &self.ports.udp),
+        ] {
+            match ports.as_deref() {
+                None | Some("") => {}
+                Some(ports) => {
+                    parse_portlist(ports)
+                        .map_err(|_| anyhow!("{}: Could not parse {}", key, ports))?;
+                }
+            }
+        }
+
+        if let Some(resolvers) = &self.resolve.resolvers {
+            config.resolvers =
+                parse_resolvers(&resolvers.join(",")).context("resolve.resolvers")?;
+        }
+        if let Some(communities) = &self.resolve.snmp {
+            config.snmp_communities = communities
+                .iter()
+                .filter(|c| !c.is_empty())
+                .cloned()
+                .collect();
+        }
+
+        if let Some(tick_len) = self.ui.tick_len {
+            config.tick_len = tick_len;
+        }
+        if let Some(name) = &self.ui.theme {
+            config.theme = self.theme(name).context("ui.theme")?;
+        }
+        if !self.keys.is_empty() {
+            config.keymap = self.keymap()?;
+        }
+        Ok(())
+    }
+
+    pub fn keymap(&self) -> Result<Keymap> {
+        let mut bindings = vec![];
+        for (name, keys) in &self.keys {
+            let command: Command = name.parse().with_context(|| format!("keys.{}", name))?;
+            let keys = match keys {
+                KeyList::One(key) => vec![key.clone()],
+                KeyList::Many(keys) => keys.clone(),
+            };
+            let keys = keys
+                .iter()
+                .map(|key| key.parse::<Key>())
+                .collect::<Result<Vec<Key>>>()
+                .with_context(|| format!("keys.{}", name))?;
+            bindings.push((command, keys));
+        }
+        Keymap::with_bindings(bindings).context("keys")
+    }
+
+    // A custom theme from the file, or a built-in one
+    pub fn theme(&self, name: &str) -> Result<Theme> {
+        let mut chain = vec![];
+        let mut name = name;
+        while let Some(custom) = self.themes.get(name) {
+            if chain.iter().any(|&(seen, _)| seen == name) {
+                return Err(anyhow!("Theme {} is its own base", name));
+            }
+            chain.push((name, custom));
+            name = custom.base.as_deref().unwrap_or("default");
+        }
+
+        let mut theme = match Theme::builtin(name) {
+            Some(theme) => theme,
+            None => {
+                let custom: Vec<&str> = self.themes.keys().map(String::as_str).collect();
+                return Err(anyhow!(
+                    "Unknown theme {}, the choices are: {}",
+                    name,
+                    BUILTIN_THEMES
+                        .iter()
+                        .copied()
+                        .chain(custom)
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                ));
+            }
+        };
+        // Apply from the base up
+        for (name, custom) in chain.into_iter().rev() {
+            custom
+                .apply(&mut theme)
+                .with_context(|| format!("themes.{}", name))?;
+        }
+        Ok(theme)
+    }
+}
+
+impl ThemeSettings {
+    fn apply(&self, theme: &mut Theme) -> Result<()> {
+        let colors = [
+            ("border", &self.border),
+            ("focus", &self.focus),
+            ("selected_bg", &self.selected_bg),
+            ("header_bg", &self.header_bg),
+            ("ok", &self.ok),
+            ("warn", &self.warn),
+            ("alert", &self.alert),
+            ("info", &self.info),
+            ("accent", &self.accent),
+            ("muted", &self.muted),
+        ];
+        for (role, color) in colors {
+            if let Some(color) = color {
+                let color = parse_color(color).with_context(|| role.to_owned())?;
+                theme.set(role, color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TimingSettings {
+    // The file's overrides on top of a template, the given one or the file's
+    pub fn timing(&self, template: Option<TimingTemplate>) -> Result<Timing> {
+        let template = match (template, &self.template) {
+            (Some(template), _) => template,
+            (None, Some(name)) => name.parse().context("timing.template")?,
+            (None, None) => TimingTemplate::Normal,
+        };
+        let mut timing = template.timing();
+        if let Some(ms) = self.max_rtt_timeout {
+            timing.max_rtt_timeout = Duration::from_millis(ms);
+        }
+        if let Some(ms) = self.ping_timeout {
+            timing.ping_timeout = Duration::from_millis(ms);
+        }
+        if let Some(retries) = self.max_retries {
+            timing.ping_retries = retries;
+        }
+        if let Some(ms) = self.scan_delay {
+            timing.port_delay = Duration::from_millis(ms);
+        }
+        Ok(timing)
+    }
+}
+
+// Sets `section.key` for each value, keeping the rest of the file, its
+// comments and profiles as they are
+pub fn write_settings(
+    path: &Path,
+    profile: Option<&str>,
+    values: Vec<(&str, &str, toml_edit::Value)>,
+) -> Result<()> {
+    let mut doc = match fs::read_to_string(path) {
+        Ok(contents) => contents.parse::<Document>()?,
+        Err(_) => Document::new(),
+    };
+
+    for (section, key, mut value) in values {
+        let mut table: &mut dyn TableLike = doc.as_table_mut();
+        // Written as [profiles.<name>.<section>], without empty parent headers
+        if let Some(profile) = profile {
+            for parent in ["profiles", profile] {
+                table = table
+                    .entry(parent)
+                    .or_insert_with(|| {
+                        let mut parent = toml_edit::Table::new();
+                        parent.set_implicit(true);
+                        Item::Table(parent)
+                    })
+                    .as_table_like_mut()
+                    .ok_or_else(|| anyhow!("{} is not a table", parent))?;
+            }
+        }
+        let table = table
+            .entry(section)
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("{} is not a table", section))?;
+        if let Some(old) = table.get(key).and_then(Item::as_value) {
+            *value.decor_mut() = old.decor().clone();
+        }
+        table.insert(key, Item::Value(value));
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, doc.to_string())?;
+    Ok(())
+}
+
+// Profile sections are merged key by key into the top level ones
+fn merge(base: &mut Table, over: Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::network::host::PingType;
+    use tui::style::Color;
+
+    static CONFIG: &str = r#"
+        [scan]
+        workers = 50
+        ping = ["udp", "icmp"]
+
+        [timing]
+        template = "aggressive"
+        max_retries = 5
+
+        [ports]
+        tcp = "22,80"
+
+        [profiles.quick.scan]
+        workers = 200
+
+        [profiles.quick.timing]
+        template = "insane"
+    "#;
+
+    #[test]
+    fn test_profile_overrides() {
+        let base = Settings::parse(CONFIG, None).unwrap();
+        assert_eq!(base.scan.workers, Some(50));
+
+        let quick = Settings::parse(CONFIG, Some("quick")).unwrap();
+        assert_eq!(quick.scan.workers, Some(200));
+        // Keys the profile doesn't set come from the top level
+        assert_eq!(quick.scan.ping, base.scan.ping);
+        assert_eq!(quick.ports.tcp.as_deref(), Some("22,80"));
+
+        let mut config = AppConfig::default();
+        quick.apply(&mut config).unwrap();
+        assert_eq!(config.nworkers, 200);
+        assert_eq!(config.ping_methods, vec![PingType::UDP, PingType::ICMP]);
+        assert_eq!(config.timing.ping_retries, 5);
+        assert_eq!(
+            config.timing.max_rtt_timeout,
+            TimingTemplate::Insane.timing().max_rtt_timeout
+        );
+
+        assert!(Settings::parse(CONFIG, Some("slow")).is_err());
+    }
+
+    #[test]
+    fn test_write_settings() {
+        let path = env::temp_dir().join(format!("findi-config-{}.toml", std::process::id()));
+        fs::write(&path, CONFIG).unwrap();
+
+        write_settings(
+            &path,
+            None,
+            vec![
+                ("scan", "workers", 75.into()),
+                ("ui", "tick_len", 250.into()),
+            ],
+        )
+        .unwrap();
+        write_settings(&path, Some("lan"), vec![("timing", "scan_delay", 5.into())]).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(contents.contains("[profiles.lan.timing]"), "{}", contents);
+        assert!(!contents.contains("[profiles.lan]\n"), "{}", contents);
+        let lan = Settings::parse(&contents, Some("lan")).unwrap();
+        assert_eq!(lan.timing.scan_delay, Some(5));
+        assert_eq!(lan.timing.template.as_deref(), Some("aggressive"));
+
+        let settings = Settings::parse(&contents, Some("quick")).unwrap();
+        assert_eq!(settings.scan.ping, Some(vec!["udp".into(), "icmp".into()]));
+        assert_eq!(settings.ui.tick_len, Some(250));
+        // The profile still wins over the new top level value
+        assert_eq!(settings.scan.workers, Some(200));
+        let settings = Settings::parse(&contents, None).unwrap();
+        assert_eq!(settings.scan.workers, Some(75));
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        let err = Settings::parse("[scan]\nworker = 5\n", None).unwrap_err();
+        assert!(format!("{:#}", err).contains("worker"), "{:#}", err);
+
+        let err = Settings::parse("[profiles.a.ui]\ntick = 5\n", None).unwrap_err();
+        assert!(format!("{:#}", err).contains("profile a"), "{:#}", err);
+
+        for (contents, key) in [
+            ("[scan]\nping = [\"smoke\"]\n", "scan.ping"),
+            ("[scan]\nworkers = 0\n", "scan.workers"),
+            ("[ports]\ntcp = \"22,http\"\n", "ports.tcp"),
+            ("[ports]\nudp = \"53-\"\n", "ports.udp"),
+        ] {
+            let settings = Settings::parse(contents, None).unwrap();
+            let err = settings.apply(&mut AppConfig::default()).unwrap_err();
+            assert!(format!("{:#}", err).contains(key), "{:#}", err);
+        }
+    }
+
+    #[test]
+    fn test_custom_themes() {
+        let settings = Settings::parse(
+            r##"
+            [ui]
+            theme = "night"
+
+            [themes.dusk]
+            base = "high-contrast"
+            focus = "magenta"
+
+            [themes.night]
+            base = "dusk"
+            header_bg = "#102030"
+            "##,
+            None,
+        )
+        .unwrap();
+
+        let mut config = AppConfig::default();
+        settings.apply(&mut config).unwrap();
+        let high_contrast = Theme::builtin("high-contrast").unwrap();
+        assert_eq!(config.theme.focus, Color::Magenta);
+        assert_eq!(config.theme.header_bg, Color::Rgb(16, 32, 48));
+        assert_eq!(config.theme.ok, high_contrast.ok);
+
+        assert_eq!(
+            settings.theme("monochrome").unwrap(),
+            Theme::builtin("monochrome").unwrap()
+        );
+        let err = settings.theme("neon").unwrap_err();
+        assert!(format!("{:#}", err).contains("night"), "{:#}", err);
+
+        let looped = Settings::parse("[themes.a]\nbase = \"b\"\n[themes.b]\nbase = \"a\"\n", None);
+        assert!(looped.unwrap().theme("a").is_err());
+        let bad_color = Settings::parse("[themes.a]\nok = \"greenish\"\n", None);
+        let err = bad_color.unwrap().theme("a").unwrap_err();
+        assert!(format!("{:#}", err).contains("themes.a: ok"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_keys() {
+        let settings = Settings::parse(
+            r#"
+            [keys]
+            down = ["ctrl-n", "down"]
+            up = "ctrl-p"
+            quit = []
+            "#,
+            None,
+        )
+        .unwrap();
+        let mut config = AppConfig::default();
+        settings.apply(&mut config).unwrap();
+        assert_eq!(
+            config.keymap.keys(Command::Down),
+            vec![Key::Ctrl('n'), Key::Down]
+        );
+        assert_eq!(config.keymap.keys(Command::Up), vec![Key::Ctrl('p')]);
+        assert!(config.keymap.keys(Command::Quit).is_empty());
+
+        let settings = Settings::parse("[keys]\njump = \"x\"\n", None).unwrap();
+        let err = settings.apply(&mut AppConfig::default()).unwrap_err();
+        assert!(format!("{:#}", err).contains("keys.jump"), "{:#}", err);
+        let settings = Settings::parse("[keys]\nhelp = \"hyper-h\"\n", None).unwrap();
+        assert!(settings.apply(&mut AppConfig::default()).is_err());
+    }
+}
@@ -1,9 +1,58 @@
+pub mod file;
+
+use crate::network::host::{NameResolver, PingType};
+use crate::network::probe::{PingMode, PingPolicy};
+use crate::network::service_probes::ServiceProbes;
+use crate::network::tcp_ping::TCP_PING_PORTS;
+use crate::network::timing::Timing;
+use crate::ui::keymap::Keymap;
+use crate::ui::theme::Theme;
+
+use std::sync::Arc;
+
 #[derive(Clone)]
 pub struct AppConfig {
     // Number of workers for network scans
     pub nworkers: usize,
     // UI tick length (ms)
     pub tick_len: usize,
+    // Send ARP requests to every host and listen for ARP traffic (requires root)
+    pub arp_sweep: bool,
+    // SNMP communities to try against live hosts, the probe is skipped if empty
+    pub snmp_communities: Vec<String>,
+    // Read service banners from open TCP ports
+    pub banner_grab: bool,
+    // Fetch title, server and favicon of open web ports, adds the Web column
+    pub http_fingerprint: bool,
+    // Fetch SSH host keys from hosts with port 22 open
+    pub ssh_host_keys: bool,
+    // Guess the OS of hosts from sniffed replies (requires root)
+    pub os_fingerprint: bool,
+    // Liveness probes to run, and how
+    pub ping_methods: Vec<PingType>,
+    pub ping_mode: PingMode,
+    pub ping_policy: PingPolicy,
+    // Ports the TCP ping connects to, all at once
+    pub tcp_ping_ports: Vec<u16>,
+    // Timeouts, retries and delays, from a template (-T) plus overrides
+    pub timing: Timing,
+    // Probe packets per second and open TCP connections, over every probe path
+    pub max_rate: Option<u32>,
+    pub max_connections: Option<usize>,
+    // Scan hosts and ports in random order
+    pub randomize_targets: bool,
+    // Probe each port across all hosts before the next, instead of host by host
+    pub interleave_ports: bool,
+    // Look up names of hosts that don't answer pings, an answer marks them alive
+    pub resolve_unresponsive: bool,
+    // Reverse name lookups, in the order they're tried
+    pub resolvers: Vec<NameResolver>,
+    // Probes from --service-probes used to identify open ports
+    pub service_probes: Option<Arc<ServiceProbes>>,
+    // Colors of the UI, from --theme or [ui] theme
+    pub theme: Theme,
+    // Keys bound to UI commands, from [keys]
+    pub keymap: Keymap,
 }
 
 impl Default for AppConfig {
@@ -11,6 +60,26 @@ impl Default for AppConfig {
         AppConfig {
             nworkers: 100,
             tick_len: 100,
+            arp_sweep: false,
+            snmp_communities: vec![],
+            banner_grab: false,
+            http_fingerprint: false,
+            ssh_host_keys: false,
+            os_fingerprint: false,
+            ping_methods: vec![PingType::UDP, PingType::TCP],
+            ping_mode: PingMode::Sequential,
+            ping_policy: PingPolicy::Any,
+            tcp_ping_ports: TCP_PING_PORTS.to_vec(),
+            timing: Timing::default(),
+            max_rate: None,
+            max_connections: None,
+            randomize_targets: false,
+            interleave_ports: false,
+            resolve_unresponsive: false,
+            resolvers: vec![NameResolver::MDNS, NameResolver::DNS, NameResolver::NBNS],
+            service_probes: None,
+            theme: Theme::default(),
+            keymap: Keymap::default(),
         }
     }
 }
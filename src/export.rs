@@ -0,0 +1,314 @@
+// Write scan results to a file, the format is picked from the extension
+// (csv, json or txt). Only live hosts are exported.
+
+use crate::network::host::Host;
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Txt,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &str) -> Result<ExportFormat> {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        match ext.as_deref() {
+            Some("csv") => Ok(ExportFormat::Csv),
+            Some("json") => Ok(ExportFormat::Json),
+            Some("txt") => Ok(ExportFormat::Txt),
+            _ => Err(anyhow!(
+                "Unknown output format for {}, use a .csv, .json or .txt file",
+                path
+            )),
+        }
+    }
+}
+
+// One flat record per host, columns are the same for every host
+fn host_record(host: &Host) -> Vec<(&'static str, Value)> {
+    let mut tcp_ports: Vec<u16> = host.tcp_ports.iter().copied().collect();
+    tcp_ports.sort_unstable();
+
+    let udp_ports: Map<String, Value> = host
+        .udp_ports
+        .iter()
+        .map(|(port, port_state)| (port.to_string(), json!(port_state.to_string())))
+        .collect();
+
+    let banners: Map<String, Value> = host
+        .banners
+        .iter()
+        .map(|(port, banner)| (port.to_string(), json!(banner)))
+        .collect();
+
+    let services: Map<String, Value> = host
+        .services
+        .iter()
+        .map(|((protocol, port), service)| {
+            (format!("{}/{}", port, protocol), json!(service.to_string()))
+        })
+        .collect();
+
+    let tls_certs: Map<String, Value> = host
+        .tls_certs
+        .iter()
+        .map(|(port, tls_info)| {
+            (
+                port.to_string(),
+                json!({
+                    "subject": tls_info.subject,
+                    "sans": tls_info.sans,
+                    "issuer": tls_info.issuer,
+                    "not_before": tls_info.not_before,
+                    "not_after": tls_info.not_after,
+                    "expired": tls_info.expired,
+                    "not_yet_valid": tls_info.not_yet_valid,
+                    "self_signed": tls_info.self_signed,
+                    "key_type": tls_info.key_type,
+                    "protocol": tls_info.protocol,
+                    "cipher": tls_info.cipher,
+                }),
+            )
+        })
+        .collect();
+
+    let http: Map<String, Value> = host
+        .http
+        .iter()
+        .map(|(port, http_info)| {
+            (
+                port.to_string(),
+                json!({
+                    "https": http_info.https,
+                    "status": http_info.status,
+                    "server": http_info.server,
+                    "title": http_info.title,
+                    "location": http_info.location,
+                    "favicon_hash": http_info.favicon_hash,
+                }),
+            )
+        })
+        .collect();
+
+    vec![
+        ("ip", json!(host.ip.to_string())),
+        (
+            "latency_ms",
+            json!(host.ping_res.map(|dur| dur.as_millis() as u64)),
+        ),
+        (
+            "host_name",
+            json!(match &host.host_name {
+                Some(Ok(name)) => Some(name),
+                _ => None,
+            }),
+        ),
+        (
+            "resolution",
+            json!(host.res_type.map(|res_type| res_type.to_string())),
+        ),
+        ("alive_reason", json!(host.alive_reason())),
+        ("mac", json!(host.mac.map(|mac| mac.to_string()))),
+        (
+            "os",
+            json!(host.os.as_ref().map(|os| os.family.to_string())),
+        ),
+        (
+            "os_confidence",
+            json!(host.os.as_ref().map(|os| os.confidence)),
+        ),
+        ("tcp_ports", json!(tcp_ports)),
+        ("udp_ports", Value::Object(udp_ports)),
+        ("banners", Value::Object(banners)),
+        ("services", Value::Object(services)),
+        ("tls_certs", Value::Object(tls_certs)),
+        ("http", Value::Object(http)),
+        (
+            "ssh",
+            json!(host.ssh.as_ref().map(|ssh| json!({
+                "banner": ssh.banner,
+                "host_keys": ssh
+                    .host_keys
+                    .iter()
+                    .map(|key| format!("{} {}", key.key_type, key.fingerprint))
+                    .collect::<Vec<String>>(),
+                "key_changed": ssh.key_changed,
+            }))),
+        ),
+        (
+            "snmp_name",
+            json!(host.snmp.as_ref().and_then(|snmp| snmp.name.clone())),
+        ),
+    ]
+}
+
+// Flatten a JSON value into a single csv/txt cell
+fn value_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.to_owned(),
+        Value::Array(vals) => vals
+            .iter()
+            .map(value_cell)
+            .collect::<Vec<String>>()
+            .join(";"),
+        // Nested objects stay JSON so the cell remains unambiguous
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, val)| match val {
+                Value::Object(_) => format!("{}={}", key, val),
+                _ => format!("{}={}", key, value_cell(val)),
+            })
+            .collect::<Vec<String>>()
+            .join(";"),
+        other => other.to_string(),
+    }
+}
+
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_owned()
+    }
+}
+
+pub fn render(hosts: &[&Host], format: ExportFormat) -> String {
+    let records: Vec<Vec<(&str, Value)>> = hosts.iter().map(|host| host_record(host)).collect();
+
+    match format {
+        ExportFormat::Json => {
+            let objects: Vec<Value> = records
+                .into_iter()
+                .map(|record| {
+                    Value::Object(
+                        record
+                            .into_iter()
+                            .map(|(key, val)| (key.to_owned(), val))
+                            .collect(),
+                    )
+                })
+                .collect();
+            serde_json::to_string_pretty(&objects).unwrap_or_default()
+        }
+
+        ExportFormat::Csv => {
+            let header: Vec<&str> = host_record(&Host::new(Ipv4Addr::UNSPECIFIED))
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect();
+            let mut out = header.join(",");
+            out.push('\n');
+
+            for record in records {
+                let row: Vec<String> = record
+                    .iter()
+                    .map(|(_, val)| csv_escape(&value_cell(val)))
+                    .collect();
+                out.push_str(&row.join(","));
+                out.push('\n');
+            }
+            out
+        }
+
+        ExportFormat::Txt => records
+            .iter()
+            .map(|record| {
+                record
+                    .iter()
+                    .map(|(key, val)| format!("{}: {}", key, value_cell(val)))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+                    + "\n\n"
+            })
+            .collect(),
+    }
+}
+
+pub fn export_hosts(path: &str, hosts: &[Host]) -> Result<()> {
+    let format = ExportFormat::from_path(path)?;
+    let live: Vec<&Host> = hosts.iter().filter(|h| h.is_alive()).collect();
+    fs::write(path, render(&live, format))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::network::host::PingType;
+    use crate::network::probe::{LivenessEvidence, ResponseKind};
+    use crate::network::udp_scan::UdpPortState;
+    use std::time::Duration;
+
+    fn test_host() -> Host {
+        let mut host = Host::new(Ipv4Addr::new(192, 168, 0, 2));
+        host.ping_res = Some(Duration::from_millis(12));
+        host.host_name = Some(Ok("nas.local".to_owned()));
+        host.tcp_ports.insert(22);
+        host.tcp_ports.insert(21);
+        host.banners.insert(21, "220 FTP, ready".to_owned());
+        host.udp_ports.insert(53, UdpPortState::Open);
+        host.liveness = Some(LivenessEvidence {
+            ping_type: PingType::TCP,
+            port: Some(22),
+            reason: ResponseKind::TcpSynAck,
+            ttl: Some(64),
+        });
+        host
+    }
+
+    #[test]
+    fn test_export_format_from_path() {
+        assert_eq!(
+            ExportFormat::from_path("out.CSV").unwrap(),
+            ExportFormat::Csv
+        );
+        assert_eq!(
+            ExportFormat::from_path("a/b.json").unwrap(),
+            ExportFormat::Json
+        );
+        assert!(ExportFormat::from_path("out.xml").is_err());
+        assert!(ExportFormat::from_path("out").is_err());
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let host = test_host();
+        let csv = render(&[&host], ExportFormat::Csv);
+        let mut lines = csv.lines();
+
+        assert!(lines.next().unwrap().starts_with("ip,latency_ms,host_name"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("192.168.0.2,12,nas.local,,\"port 22/tcp: TCP SYN/ACK, TTL 64\",,,,21;22,53=open,\"21=220 FTP, ready\","));
+    }
+
+    #[test]
+    fn test_render_json() {
+        let host = test_host();
+        let parsed: Value = serde_json::from_str(&render(&[&host], ExportFormat::Json)).unwrap();
+
+        assert_eq!(parsed[0]["ip"], "192.168.0.2");
+        assert_eq!(parsed[0]["tcp_ports"], json!([21, 22]));
+        assert_eq!(parsed[0]["banners"]["21"], "220 FTP, ready");
+        assert_eq!(parsed[0]["udp_ports"]["53"], "open");
+        assert_eq!(
+            parsed[0]["alive_reason"],
+            "port 22/tcp: TCP SYN/ACK, TTL 64"
+        );
+        assert_eq!(parsed[0]["mac"], Value::Null);
+    }
+}
@@ -10,23 +10,33 @@ Notes:
 */
 
 mod config;
+mod export;
 mod network;
 mod state;
 mod ui;
 
+use config::file::{config_path, Settings};
 use config::AppConfig;
-use network::init_host_search;
+use network::host::parse_resolvers;
 use network::input_parse;
+use network::probe::parse_ping_methods;
+use network::service_probes::ServiceProbes;
+use network::tcp_ping::parse_portlist;
+use network::timing::TimingTemplate;
+use network::{dhcp, wol};
+use network::{dispatch_dhcp_probe, init_host_search, start_arp_monitor, start_os_sniffer};
 use state::actions::AppAction;
 use state::store::AppStateStore;
 use ui::ui_loop;
 
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
 use std::process::exit;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use clap::{crate_authors, crate_version, App, Arg, ArgMatches};
+use clap::{crate_authors, crate_version, App, Arg, ArgMatches, SubCommand};
 use colored::Colorize;
 use pnet::{datalink, ipnetwork::IpNetwork};
 
@@ -37,12 +47,31 @@ fn parse_args<'a>() -> ArgMatches<'a> {
         .version(crate_version!())
         .author(crate_authors!())
         .about("A local network discovery tool")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .help("Config file (default $XDG_CONFIG_HOME/findi/config.toml)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .help("Named profile from the config file, overriding its top level settings")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("disable_ui")
                 .short("n")
                 .long("no-ui")
+                .overrides_with("enable_ui")
                 .help("Disable the TUI app"),
         )
+        .arg(
+            Arg::with_name("enable_ui")
+                .long("ui")
+                .overrides_with("disable_ui")
+                .help("Show the TUI app, even if the config file disables it"),
+        )
         .arg(
             Arg::with_name("custom_cidr")
                 .short("c")
@@ -64,6 +93,13 @@ fn parse_args<'a>() -> ArgMatches<'a> {
                 .help("TCP port scan list/range (e.g. -p 22 or -p 22,443 or -p 80-90)")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("scan_udp_ports")
+                .short("u")
+                .long("udpports")
+                .help("UDP port scan list/range (e.g. -u 53 or -u 53,161 or -u 500-510)")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("output_file")
                 .short("o")
@@ -78,6 +114,73 @@ fn parse_args<'a>() -> ArgMatches<'a> {
                 .help("UI timer tick length in ms. If no key events, UI redraws at this interval.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("theme")
+                .long("theme")
+                .help("UI colors, default, light, high-contrast, monochrome or a theme from the config file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("timing")
+                .short("T")
+                .long("timing")
+                .help("Timing template, 0-5 or paranoid, sneaky, polite, normal (default), aggressive, insane")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_rtt_timeout")
+                .long("max-rtt-timeout")
+                .help("Longest wait in ms for a TCP connect or name lookup, overrides the timing template")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_retries")
+                .long("max-retries")
+                .help("UDP ping resends, overrides the timing template")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("scan_delay")
+                .long("scan-delay")
+                .help("Pause in ms between ports of a host, overrides the timing template")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_rate")
+                .long("max-rate")
+                .help("Send at most this many probe packets per second, over all workers")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_connections")
+                .long("max-connections")
+                .help("Keep at most this many TCP connections open at once")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("randomize")
+                .long("randomize")
+                .overrides_with("no_randomize")
+                .help("Scan hosts and ports in random order, the table stays sorted"),
+        )
+        .arg(
+            Arg::with_name("no_randomize")
+                .long("no-randomize")
+                .overrides_with("randomize")
+                .help("Scan in order, even if the config file randomizes"),
+        )
+        .arg(
+            Arg::with_name("interleave")
+                .long("interleave")
+                .overrides_with("no_interleave")
+                .help("Ping every host first, then probe each port across all hosts before the next"),
+        )
+        .arg(
+            Arg::with_name("no_interleave")
+                .long("no-interleave")
+                .overrides_with("interleave")
+                .help("Scan each host's ports together, even if the config file interleaves"),
+        )
         .arg(
             Arg::with_name("nworkers")
                 .short("w")
@@ -85,6 +188,136 @@ fn parse_args<'a>() -> ArgMatches<'a> {
                 .help("Number of workers for network scanning.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("arp_sweep")
+                .long("arp")
+                .overrides_with("no_arp_sweep")
+                .help("ARP sweep the query and listen for ARP traffic to flag IP address conflicts (requires root)"),
+        )
+        .arg(
+            Arg::with_name("no_arp_sweep")
+                .long("no-arp")
+                .overrides_with("arp_sweep")
+                .help("Don't ARP sweep, even if the config file enables it"),
+        )
+        .arg(
+            Arg::with_name("snmp_communities")
+                .long("snmp")
+                .help("Query live hosts for SNMP system info with these communities (e.g. --snmp public,private)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ping_methods")
+                .long("ping")
+                .help("Liveness probes to run, in order (udp,tcp,icmp,arp,mdns; default udp,tcp)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tcp_ping_ports")
+                .long("tcp-ping-ports")
+                .help("Ports the TCP ping connects to (default 22,80,443,445,3389)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ping_mode")
+                .long("ping-mode")
+                .help("Run the ping probes one after the other or all at once")
+                .possible_values(&["sequential", "parallel"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ping_policy")
+                .long("ping-policy")
+                .help("Whether any answer or every probe answering marks a host alive")
+                .possible_values(&["any", "all"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("resolve_all")
+                .long("resolve-all")
+                .overrides_with("no_resolve_all")
+                .help("Look up names of hosts that don't answer pings, and re-ping those that answer"),
+        )
+        .arg(
+            Arg::with_name("no_resolve_all")
+                .long("no-resolve-all")
+                .overrides_with("resolve_all")
+                .help("Only look up names of hosts that answer a ping, even if the config file resolves all"),
+        )
+        .arg(
+            Arg::with_name("resolvers")
+                .long("resolvers")
+                .help("Name lookups to try, in order (mdns,dns,nbns; default all three)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("service_probes")
+                .long("service-probes")
+                .help("File of service probes and match rules used to identify open ports")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("banners")
+                .long("banners")
+                .overrides_with("no_banners")
+                .help("Read service banners from open TCP ports"),
+        )
+        .arg(
+            Arg::with_name("no_banners")
+                .long("no-banners")
+                .overrides_with("banners")
+                .help("Don't read service banners, even if the config file enables it"),
+        )
+        .arg(
+            Arg::with_name("http_fingerprint")
+                .long("http")
+                .overrides_with("no_http_fingerprint")
+                .help("Fetch title, server header and favicon hash of open web ports, shown in a Web column"),
+        )
+        .arg(
+            Arg::with_name("no_http_fingerprint")
+                .long("no-http")
+                .overrides_with("http_fingerprint")
+                .help("Don't fingerprint web ports, even if the config file enables it"),
+        )
+        .arg(
+            Arg::with_name("ssh_host_keys")
+                .long("ssh")
+                .overrides_with("no_ssh_host_keys")
+                .help("Fetch SSH host key fingerprints from hosts with port 22 open (found by the TCP ping or -p), warns if they changed since the last scan"),
+        )
+        .arg(
+            Arg::with_name("no_ssh_host_keys")
+                .long("no-ssh")
+                .overrides_with("ssh_host_keys")
+                .help("Don't fetch SSH host keys, even if the config file enables it"),
+        )
+        .arg(
+            Arg::with_name("os_fingerprint")
+                .long("os")
+                .overrides_with("no_os_fingerprint")
+                .help("Guess each host's OS from the TTL and TCP options of its replies (requires root)"),
+        )
+        .arg(
+            Arg::with_name("no_os_fingerprint")
+                .long("no-os")
+                .overrides_with("os_fingerprint")
+                .help("Don't guess OSes, even if the config file enables it"),
+        )
+        .arg(
+            Arg::with_name("dhcp_probe")
+                .long("dhcp")
+                .help("Look for DHCP servers on the interface, warns if more than one answers (requires root)"),
+        )
+        .subcommand(
+            SubCommand::with_name("wake")
+                .about("Send a Wake-on-LAN magic packet")
+                .arg(
+                    Arg::with_name("target")
+                        .help("MAC address, or IPv4 address of a host seen in a previous scan")
+                        .required(true),
+                ),
+        )
         .get_matches()
 }
 
@@ -104,7 +337,36 @@ fn main() {
             && matches.value_of("interface").unwrap_or(&e.name) == e.name
     });
 
+    if let Some(wake_matches) = matches.subcommand_matches("wake") {
+        let iface_name = default_iface.map(|iface| iface.name.as_str());
+        let res = wol::resolve_wake_target(wake_matches.value_of("target").unwrap())
+            .and_then(|mac| wol::wake(mac, iface_name).map(|_| mac));
+
+        match res {
+            Ok(mac) => {
+                println!("Magic packet sent to {}", mac);
+                exit(0)
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1)
+            }
+        }
+    }
+
+    // Loaded after the wake subcommand, which doesn't use it
+    let settings = match Settings::load(matches.value_of("config"), matches.value_of("profile")) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            exit(1)
+        }
+    };
+
     let mut store = AppStateStore::new();
+    store.dispatch(AppAction::SetInterface(
+        default_iface.map(|iface| iface.name.to_owned()),
+    ));
 
     let hosts: Vec<Ipv4Addr>;
     let query: String;
@@ -138,14 +400,31 @@ fn main() {
         exit(1);
     }
 
-    // Get port list from args, ignore a malformed port list
+    // Get port list from args, ignore a malformed port list. An empty list
+    // in the config file scans no ports.
     // TODO: constrain this to at most 10 ports?
-    if let Some(port_list) = matches.value_of("scan_ports") {
+    if let Some(port_list) = matches
+        .value_of("scan_ports")
+        .or(settings.ports.tcp.as_deref())
+        .filter(|ports| !ports.is_empty())
+    {
         store.dispatch(AppAction::SetPortQuery(Some(port_list.to_owned())))
     }
 
-    // Setup user config
+    if let Some(port_list) = matches
+        .value_of("scan_udp_ports")
+        .or(settings.ports.udp.as_deref())
+        .filter(|ports| !ports.is_empty())
+    {
+        store.dispatch(AppAction::SetUdpPortQuery(Some(port_list.to_owned())))
+    }
+
+    // Setup user config, from the config file then overridden by flags
     let mut config = AppConfig::default();
+    if let Err(err) = settings.apply(&mut config) {
+        eprintln!("Invalid config file: {:#}", err);
+        exit(1);
+    }
 
     if let Some(nworkers) = matches.value_of("nworkers").and_then(|nw| nw.parse().ok()) {
         config.nworkers = nworkers;
@@ -155,7 +434,144 @@ fn main() {
         config.tick_len = tick_len;
     }
 
+    if let Some(theme) = matches.value_of("theme") {
+        match settings.theme(theme) {
+            Ok(theme) => config.theme = theme,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                exit(1)
+            }
+        }
+    }
+
+    if let Some(template) = matches.value_of("timing") {
+        match template
+            .parse::<TimingTemplate>()
+            .and_then(|template| settings.timing.timing(Some(template)))
+        {
+            Ok(timing) => config.timing = timing,
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1)
+            }
+        }
+    }
+
+    // Overrides of single template values
+    let parse_arg = |name: &str| -> Option<u64> {
+        matches.value_of(name).map(|val| match val.parse() {
+            Ok(val) => val,
+            Err(_) => {
+                eprintln!("Could not parse --{} {}", name.replace('_', "-"), val);
+                exit(1)
+            }
+        })
+    };
+    if let Some(ms) = parse_arg("max_rtt_timeout") {
+        config.timing.max_rtt_timeout = Duration::from_millis(ms);
+    }
+    if let Some(retries) = parse_arg("max_retries") {
+        config.timing.ping_retries = retries as u32;
+    }
+    if let Some(ms) = parse_arg("scan_delay") {
+        config.timing.port_delay = Duration::from_millis(ms);
+    }
+    // 0 is no limit, like leaving them out
+    if let Some(rate) = parse_arg("max_rate") {
+        config.max_rate = Some(rate as u32).filter(|&rate| rate > 0);
+    }
+    if let Some(max) = parse_arg("max_connections") {
+        config.max_connections = Some(max as usize).filter(|&max| max > 0);
+    }
+
+    // Each flag has a --no- counterpart to turn off what the config file
+    // turns on, the last one given wins
+    let flags = [
+        ("arp_sweep", &mut config.arp_sweep),
+        ("banners", &mut config.banner_grab),
+        ("http_fingerprint", &mut config.http_fingerprint),
+        ("ssh_host_keys", &mut config.ssh_host_keys),
+        ("os_fingerprint", &mut config.os_fingerprint),
+        ("resolve_all", &mut config.resolve_unresponsive),
+        ("randomize", &mut config.randomize_targets),
+        ("interleave", &mut config.interleave_ports),
+    ];
+    for (name, flag) in flags {
+        if matches.is_present(name) {
+            *flag = true;
+        } else if matches.is_present(format!("no_{}", name)) {
+            *flag = false;
+        }
+    }
+
+    if let Some(resolvers) = matches.value_of("resolvers") {
+        match parse_resolvers(resolvers) {
+            Ok(resolvers) => config.resolvers = resolvers,
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1)
+            }
+        }
+    }
+
+    if let Some(methods) = matches.value_of("ping_methods") {
+        match parse_ping_methods(methods) {
+            Ok(methods) => config.ping_methods = methods,
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1)
+            }
+        }
+    }
+
+    if let Some(ports) = matches.value_of("tcp_ping_ports") {
+        match parse_portlist(ports) {
+            Ok(ports) if !ports.is_empty() => config.tcp_ping_ports = ports,
+            _ => {
+                eprintln!("Could not parse the TCP ping ports {}", ports);
+                exit(1)
+            }
+        }
+    }
+
+    // Both are restricted to valid values by clap
+    if let Some(mode) = matches.value_of("ping_mode").and_then(|m| m.parse().ok()) {
+        config.ping_mode = mode;
+    }
+
+    if let Some(policy) = matches.value_of("ping_policy").and_then(|p| p.parse().ok()) {
+        config.ping_policy = policy;
+    }
+
+    if let Some(path) = matches.value_of("service_probes") {
+        match ServiceProbes::load(path) {
+            Ok(probes) => config.service_probes = Some(Arc::new(probes)),
+            Err(err) => {
+                eprintln!("{:#}", err);
+                exit(1)
+            }
+        }
+    }
+
+    if let Some(communities) = matches.value_of("snmp_communities") {
+        config.snmp_communities = communities
+            .split(',')
+            .filter(|c| !c.is_empty())
+            .map(str::to_owned)
+            .collect();
+    }
+
+    let (arp_sweep, os_fingerprint) = (config.arp_sweep, config.os_fingerprint);
     store.dispatch(AppAction::SetConfig(config));
+    store.dispatch(AppAction::SetConfigFile(
+        matches
+            .value_of("config")
+            .map(PathBuf::from)
+            .or_else(config_path),
+    ));
+    store.dispatch(AppAction::SetConfigProfile(
+        matches.value_of("profile").map(str::to_owned),
+    ));
 
     let num_hosts = hosts.len();
 
@@ -165,20 +581,43 @@ fn main() {
 
     let shared_store = Arc::new(Mutex::new(store));
 
+    // Listen before the sweep goes out so replies aren't missed
+    if arp_sweep {
+        start_arp_monitor(shared_store.clone());
+    }
+    if os_fingerprint {
+        start_os_sniffer(shared_store.clone());
+    }
+
     // TODO find router first
     init_host_search(shared_store.clone());
 
+    let dhcp_probe = matches.is_present("dhcp_probe");
+
     #[cfg(feature = "ui")]
-    if !matches.is_present("disable_ui") {
+    let disable_ui = match matches.is_present("enable_ui") {
+        true => false,
+        false => matches.is_present("disable_ui") || settings.output.no_ui == Some(true),
+    };
+    #[cfg(feature = "ui")]
+    if !disable_ui {
+        if dhcp_probe {
+            dispatch_dhcp_probe(shared_store.clone());
+        }
+
         // Run UI on main thread
-        let _ = ui_loop(shared_store);
+        let _ = ui_loop(shared_store.clone());
     } else {
         // TODO: move this elsewhere and accept an argument for different types out output
         // ie stdout, csv, json, etc
 
-        let lstore = shared_store;
+        let lstore = shared_store.clone();
         let mut hostidx: usize = 0;
 
+        if dhcp_probe {
+            print_dhcp_offers(default_iface.map(|iface| iface.name.as_str()));
+        }
+
         println!("Scanning {} hosts...", num_hosts);
 
         loop {
@@ -189,13 +628,14 @@ fn main() {
 
             let host = &hstore.state.hosts[hostidx];
             if host.ping_done {
-                if let Some(dur) = host.ping_res {
+                if host.is_alive() {
+                    let rtt = match host.ping_res {
+                        Some(dur) => format!("({:.2?}ms)", dur.as_millis()),
+                        None => "(by name)".to_owned(),
+                    };
                     println!(
                         "Live host {} {}{}",
-                        format!(
-                            "{:<28}",
-                            format!("{:<15?} ({:.2?}ms)", host.ip, dur.as_millis())
-                        ),
+                        format!("{:<28}", format!("{:<15?} {}", host.ip, rtt)),
                         format!(
                             "{:<30}",
                             match &host.host_name {
@@ -213,11 +653,121 @@ fn main() {
                                     .collect::<Vec<String>>()
                                     .join(",")
                             ),
-                        }
-                    )
+                        },
+                    );
+                    if !host.udp_ports.is_empty() {
+                        println!(
+                            "    UDP ports: {}",
+                            host.udp_ports
+                                .iter()
+                                .map(|(port, port_state)| format!("{} ({})", port, port_state))
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        );
+                    }
                 }
                 hostidx += 1;
             }
         }
+
+        let hstore = lstore.lock().unwrap();
+        for host in hstore
+            .state
+            .hosts
+            .iter()
+            .filter(|h| hstore.state.arp_table.is_conflict(&h.ip))
+        {
+            let macs: Vec<String> = hstore
+                .state
+                .arp_table
+                .macs_for(&host.ip)
+                .iter()
+                .map(|mac| mac.to_string())
+                .collect();
+            println!(
+                "{}",
+                format!(
+                    "Warning: {} is claimed by more than one device ({})",
+                    host.ip,
+                    macs.join(", ")
+                )
+                .red()
+            );
+        }
+        for mac in hstore.state.arp_table.moved_macs() {
+            let ips: Vec<String> = hstore
+                .state
+                .arp_table
+                .ips_for(&mac)
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect();
+            println!(
+                "{}",
+                format!(
+                    "Warning: {} is seen on more than one address ({})",
+                    mac,
+                    ips.join(", ")
+                )
+                .red()
+            );
+        }
+    }
+
+    if let Some(path) = matches
+        .value_of("output_file")
+        .or(settings.output.file.as_deref())
+    {
+        let hstore = shared_store.lock().unwrap();
+        match export::export_hosts(path, &hstore.state.hosts) {
+            Ok(_) => println!("Results written to {}", path),
+            Err(err) => eprintln!("Could not write {}: {}", path, err),
+        }
+    }
+}
+
+fn print_dhcp_offers(iface_name: Option<&str>) {
+    println!("Looking for DHCP servers...");
+
+    let offers = match iface_name
+        .ok_or_else(|| anyhow::anyhow!("No interface available for the DHCP probe"))
+        .and_then(network::find_interface)
+        .and_then(|iface| dhcp::dhcp_discover(&iface, dhcp::DHCP_OFFER_WINDOW))
+    {
+        Ok(offers) => offers,
+        Err(err) => return eprintln!("DHCP probe failed: {}", err),
+    };
+
+    for offer in &offers {
+        println!(
+            "DHCP server {} ({}) offers {} lease {} router {} DNS {}",
+            offer.server_ip,
+            offer
+                .server_mac
+                .map_or_else(|| "--".to_owned(), |mac| mac.to_string()),
+            offer.offered_ip,
+            offer
+                .lease_secs
+                .map_or_else(|| "--".to_owned(), |secs| format!("{}s", secs)),
+            join_addrs(&offer.routers),
+            join_addrs(&offer.dns_servers),
+        );
+    }
+
+    match dhcp::server_count(&offers) {
+        0 => println!("{}", "No DHCP servers answered".yellow()),
+        1 => {}
+        n => println!("{}", format!("Warning: {} DHCP servers answered!", n).red()),
+    }
+}
+
+fn join_addrs(addrs: &[Ipv4Addr]) -> String {
+    match addrs.len() {
+        0 => "--".to_owned(),
+        _ => addrs
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<String>>()
+            .join(","),
     }
 }
@@ -0,0 +1,338 @@
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use pnet::datalink::{self, Channel, DataLinkSender, NetworkInterface};
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::Packet;
+use pnet::util::MacAddr;
+
+use super::rate_limit::RATE_LIMITER;
+use super::sniff::listen_frames;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "linux")]
+const PROC_ARP_PATH: &str = "/proc/net/arp";
+
+// ATF_COM, the kernel has a completed entry for this address
+const ARP_FLAG_COMPLETE: u32 = 0x02;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const ARP_PACKET_LEN: usize = 28;
+
+// Every IP -> MAC pairing we have seen, from the ARP cache, sweeps and passive listening.
+// Used to spot two devices claiming the same address, or one device hopping addresses.
+#[derive(Clone, Default, Debug)]
+pub struct ArpTable {
+    by_ip: BTreeMap<Ipv4Addr, BTreeSet<MacAddr>>,
+    by_mac: BTreeMap<MacAddr, BTreeSet<Ipv4Addr>>,
+}
+
+// What a new pairing reveals
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ArpConflict {
+    // Another device already claims the IP
+    Ip,
+    // The device was already seen on another IP
+    Mac,
+}
+
+impl ArpTable {
+    // Returns the conflict if this pairing is new and causes one
+    pub fn record(&mut self, ip: Ipv4Addr, mac: MacAddr) -> Option<ArpConflict> {
+        let macs = self.by_ip.entry(ip).or_default();
+        let new_mac = macs.insert(mac);
+        let mac_count = macs.len();
+        let ips = self.by_mac.entry(mac).or_default();
+        let new_ip = ips.insert(ip);
+
+        if new_mac && mac_count > 1 {
+            Some(ArpConflict::Ip)
+        } else if new_ip && ips.len() > 1 {
+            Some(ArpConflict::Mac)
+        } else {
+            None
+        }
+    }
+
+    pub fn macs_for(&self, ip: &Ipv4Addr) -> Vec<MacAddr> {
+        self.by_ip
+            .get(ip)
+            .map(|macs| macs.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    // More than one device answering for this address
+    pub fn is_conflict(&self, ip: &Ipv4Addr) -> bool {
+        matches!(self.by_ip.get(ip), Some(macs) if macs.len() > 1)
+    }
+
+    pub fn ips_for(&self, mac: &MacAddr) -> Vec<Ipv4Addr> {
+        self.by_mac
+            .get(mac)
+            .map(|ips| ips.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    // Devices seen on more than one address
+    pub fn moved_macs(&self) -> Vec<MacAddr> {
+        self.by_mac
+            .iter()
+            .filter(|(_, ips)| ips.len() > 1)
+            .map(|(mac, _)| *mac)
+            .collect()
+    }
+
+    // Other addresses that a MAC of this IP has also been seen on
+    pub fn other_ips(&self, ip: &Ipv4Addr) -> Vec<Ipv4Addr> {
+        let mut others: BTreeSet<Ipv4Addr> = BTreeSet::new();
+        for mac in self.macs_for(ip) {
+            if let Some(ips) = self.by_mac.get(&mac) {
+                others.extend(ips.iter().filter(|other| *other != ip));
+            }
+        }
+        others.into_iter().collect()
+    }
+}
+
+// Look up the MAC address of a host in the kernel's ARP cache.
+// A successful ping to a local host will have populated the cache,
+// so this is cheap to call right after a ping without needing raw sockets.
+#[cfg(target_os = "linux")]
+pub fn arp_cache_lookup(ip: Ipv4Addr) -> Option<MacAddr> {
+    let contents = std::fs::read_to_string(PROC_ARP_PATH).ok()?;
+    parse_proc_arp(&contents)
+        .into_iter()
+        .find(|(addr, _)| *addr == ip)
+        .map(|(_, mac)| mac)
+}
+
+// TODO: parse `arp -an` output on other platforms
+#[cfg(not(target_os = "linux"))]
+pub fn arp_cache_lookup(_ip: Ipv4Addr) -> Option<MacAddr> {
+    None
+}
+
+// Parse the contents of /proc/net/arp, skipping the header and incomplete entries
+pub fn parse_proc_arp(contents: &str) -> Vec<(Ipv4Addr, MacAddr)> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if cols.len() < 4 {
+                return None;
+            }
+
+            let flags = u32::from_str_radix(cols[2].trim_start_matches("0x"), 16).ok()?;
+            if flags & ARP_FLAG_COMPLETE == 0 {
+                return None;
+            }
+
+            let ip = cols[0].parse::<Ipv4Addr>().ok()?;
+            let mac = cols[3].parse::<MacAddr>().ok()?;
+            if mac == MacAddr::zero() {
+                return None;
+            }
+            Some((ip, mac))
+        })
+        .collect()
+}
+
+// Sender IP/MAC of an ARP request or reply.
+// ARP probes (sender 0.0.0.0) don't claim an address so they are skipped.
+pub fn parse_arp_frame(frame: &[u8]) -> Option<(Ipv4Addr, MacAddr)> {
+    let eth_pkt = EthernetPacket::new(frame)?;
+    if eth_pkt.get_ethertype() != EtherTypes::Arp {
+        return None;
+    }
+
+    let arp_pkt = ArpPacket::new(eth_pkt.payload())?;
+    let ip = arp_pkt.get_sender_proto_addr();
+    let mac = arp_pkt.get_sender_hw_addr();
+    if ip.is_unspecified() || mac == MacAddr::zero() {
+        return None;
+    }
+    Some((ip, mac))
+}
+
+pub fn build_arp_request(src_mac: MacAddr, src_ip: Ipv4Addr, target: Ipv4Addr) -> Vec<u8> {
+    let mut buf = vec![0u8; ETHERNET_HEADER_LEN + ARP_PACKET_LEN];
+
+    {
+        let mut arp_pkt = MutableArpPacket::new(&mut buf[ETHERNET_HEADER_LEN..]).unwrap();
+        arp_pkt.set_hardware_type(ArpHardwareTypes::Ethernet);
+        arp_pkt.set_protocol_type(EtherTypes::Ipv4);
+        arp_pkt.set_hw_addr_len(6);
+        arp_pkt.set_proto_addr_len(4);
+        arp_pkt.set_operation(ArpOperations::Request);
+        arp_pkt.set_sender_hw_addr(src_mac);
+        arp_pkt.set_sender_proto_addr(src_ip);
+        arp_pkt.set_target_hw_addr(MacAddr::zero());
+        arp_pkt.set_target_proto_addr(target);
+    }
+
+    let mut eth_pkt = MutableEthernetPacket::new(&mut buf).unwrap();
+    eth_pkt.set_destination(MacAddr::broadcast());
+    eth_pkt.set_source(src_mac);
+    eth_pkt.set_ethertype(EtherTypes::Arp);
+
+    buf
+}
+
+fn ethernet_sender(iface: &NetworkInterface) -> Result<Box<dyn DataLinkSender>> {
+    match datalink::channel(iface, Default::default())? {
+        Channel::Ethernet(tx, _) => Ok(tx),
+        _ => Err(anyhow!("Unsupported datalink channel on {}", iface.name)),
+    }
+}
+
+fn interface_addrs(iface: &NetworkInterface) -> Result<(MacAddr, Ipv4Addr)> {
+    let src_mac = iface
+        .mac
+        .ok_or_else(|| anyhow!("Interface {} has no MAC address", iface.name))?;
+    let src_ip = iface
+        .ips
+        .iter()
+        .find_map(|ip| match ip.ip() {
+            std::net::IpAddr::V4(ip4) => Some(ip4),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("Interface {} has no IPv4 address", iface.name))?;
+    Ok((src_mac, src_ip))
+}
+
+// Broadcast an ARP request for every target, replies are picked up by `arp_listen`.
+// Requires privileges to open a raw datalink channel.
+pub fn arp_sweep(iface: &NetworkInterface, targets: &[Ipv4Addr]) -> Result<()> {
+    let (src_mac, src_ip) = interface_addrs(iface)?;
+
+    info!(
+        "Sending ARP sweep of {} hosts on {}",
+        targets.len(),
+        iface.name
+    );
+    let mut tx = ethernet_sender(iface)?;
+    for target in targets {
+        RATE_LIMITER.packet();
+        if let Some(Err(err)) = tx.send_to(&build_arp_request(src_mac, src_ip, *target), None) {
+            warn!("ARP request to {} failed: {}", target, err);
+        }
+    }
+    Ok(())
+}
+
+// Ask a single host for its MAC and wait for the answer (requires root)
+pub fn arp_ping(
+    iface: &NetworkInterface,
+    target: Ipv4Addr,
+    timeout: Duration,
+) -> Result<(Duration, MacAddr)> {
+    let (src_mac, src_ip) = interface_addrs(iface)?;
+    let config = datalink::Config {
+        read_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let (mut tx, mut rx) = match datalink::channel(iface, config)? {
+        Channel::Ethernet(tx, rx) => (tx, rx),
+        _ => return Err(anyhow!("Unsupported datalink channel on {}", iface.name)),
+    };
+
+    RATE_LIMITER.packet();
+    let now = Instant::now();
+    if let Some(res) = tx.send_to(&build_arp_request(src_mac, src_ip, target), None) {
+        res?;
+    }
+
+    while now.elapsed() < timeout {
+        if let Some((ip, mac)) = rx.next().ok().and_then(parse_arp_frame) {
+            if ip == target {
+                return Ok((now.elapsed(), mac));
+            }
+        }
+    }
+    Err(anyhow!("No ARP reply from {}", target))
+}
+
+// Passively listen for ARP traffic on the interface until the app exits,
+// calling `on_seen` with every sender pairing
+pub fn arp_listen<F: FnMut(Ipv4Addr, MacAddr)>(
+    iface: &NetworkInterface,
+    mut on_seen: F,
+) -> Result<()> {
+    listen_frames(iface, |frame| {
+        if let Some((ip, mac)) = parse_arp_frame(frame) {
+            on_seen(ip, mac);
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_arp() {
+        let contents = "IP address       HW type     Flags       HW address            Mask     Device\n\
+                        10.0.0.1         0x1         0x2         02:fc:00:00:00:05     *        eth0\n\
+                        10.0.0.7         0x1         0x0         00:00:00:00:00:00     *        eth0\n\
+                        10.0.0.9         0x1         0x6         aa:bb:cc:dd:ee:ff     *        eth0\n";
+
+        let entries = parse_proc_arp(contents);
+
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    Ipv4Addr::new(10, 0, 0, 1),
+                    MacAddr::new(0x02, 0xfc, 0, 0, 0, 0x05)
+                ),
+                (
+                    Ipv4Addr::new(10, 0, 0, 9),
+                    MacAddr::new(0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_arp_frame() {
+        let mac = MacAddr::new(0x02, 0, 0, 0, 0, 0x01);
+        let ip = Ipv4Addr::new(10, 0, 0, 5);
+        let frame = build_arp_request(mac, ip, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(parse_arp_frame(&frame), Some((ip, mac)));
+
+        // ARP probes don't claim an address
+        let probe = build_arp_request(mac, Ipv4Addr::UNSPECIFIED, ip);
+        assert_eq!(parse_arp_frame(&probe), None);
+    }
+
+    #[test]
+    fn test_arp_table_conflicts() {
+        let ip = Ipv4Addr::new(10, 0, 0, 5);
+        let other_ip = Ipv4Addr::new(10, 0, 0, 6);
+        let mac = MacAddr::new(0x02, 0, 0, 0, 0, 0x01);
+        let other_mac = MacAddr::new(0x02, 0, 0, 0, 0, 0x02);
+
+        let mut table = ArpTable::default();
+        assert_eq!(table.record(ip, mac), None);
+        assert_eq!(table.record(ip, mac), None);
+        assert!(!table.is_conflict(&ip));
+
+        // A second device claims the address
+        assert_eq!(table.record(ip, other_mac), Some(ArpConflict::Ip));
+        assert!(table.is_conflict(&ip));
+        assert_eq!(table.macs_for(&ip), vec![mac, other_mac]);
+        assert!(table.moved_macs().is_empty());
+
+        // The first device also shows up on another address
+        assert_eq!(table.record(other_ip, mac), Some(ArpConflict::Mac));
+        assert_eq!(table.record(other_ip, mac), None);
+        assert_eq!(table.moved_macs(), vec![mac]);
+        assert_eq!(table.ips_for(&mac), vec![ip, other_ip]);
+        assert_eq!(table.other_ips(&ip), vec![other_ip]);
+        assert_eq!(table.other_ips(&other_ip), vec![ip]);
+    }
+}
@@ -0,0 +1,152 @@
+// Service banner grabbing on open TCP ports
+// Most line based services (SSH, SMTP, FTP, POP3, IMAP) greet the client first,
+// the rest get a small protocol appropriate probe.
+
+use log::trace;
+
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::time::Duration;
+
+const BANNER_TIMEOUT: Duration = Duration::from_millis(1500);
+const MAX_BANNER_LEN: usize = 120;
+const GENERIC_PROBE: &[u8] = b"\r\n";
+
+// Services that wait for the client to speak first
+fn probe_for(port: u16) -> Option<&'static [u8]> {
+    match port {
+        80 | 8000 | 8008 | 8080 | 8888 => Some(b"HEAD / HTTP/1.0\r\n\r\n"),
+        6379 => Some(b"PING\r\n"),
+        11211 => Some(b"version\r\n"),
+        _ => None,
+    }
+}
+
+fn read_some(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut buf = [0u8; 1024];
+    match stream.read(&mut buf) {
+        Ok(n) if n > 0 => Some(buf[..n].to_vec()),
+        _ => None,
+    }
+}
+
+pub fn grab_banner(stream: &mut TcpStream, port: u16) -> Option<String> {
+    stream.set_read_timeout(Some(BANNER_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(BANNER_TIMEOUT)).ok()?;
+
+    let bytes = match probe_for(port) {
+        Some(probe) => {
+            stream.write_all(probe).ok()?;
+            read_some(stream)
+        }
+        // Wait for a greeting, then nudge the service if there wasn't one
+        None => read_some(stream).or_else(|| {
+            stream.write_all(GENERIC_PROBE).ok()?;
+            read_some(stream)
+        }),
+    }?;
+
+    trace!("Banner on port {}: {:?}", port, bytes);
+    parse_banner(&bytes)
+}
+
+// MySQL sends a binary handshake packet: 3 byte length, sequence id,
+// protocol version 10 and a NUL terminated server version
+fn parse_mysql_handshake(bytes: &[u8]) -> Option<String> {
+    if bytes.len() < 6 || bytes[3] != 0 || bytes[4] != 0x0a {
+        return None;
+    }
+
+    let payload_len = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) as usize;
+    if payload_len + 4 > bytes.len() {
+        return None;
+    }
+
+    let version = bytes[5..].split(|b| *b == 0).next()?;
+    Some(format!("MySQL {}", String::from_utf8_lossy(version)))
+}
+
+fn clean_line(line: &str) -> String {
+    line.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_BANNER_LEN)
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+pub fn parse_banner(bytes: &[u8]) -> Option<String> {
+    if let Some(mysql) = parse_mysql_handshake(bytes) {
+        return Some(mysql);
+    }
+
+    let text = String::from_utf8_lossy(bytes);
+    let mut lines = text.lines().map(clean_line).filter(|l| !l.is_empty());
+    let first = lines.next()?;
+
+    // Status line alone says little about a web server
+    if first.starts_with("HTTP/") {
+        if let Some(server) = lines.find_map(|l| {
+            l.split_once(':')
+                .filter(|(name, _)| name.eq_ignore_ascii_case("server"))
+                .map(|(_, val)| val.trim().to_owned())
+        }) {
+            return Some(format!("{} ({})", first, server));
+        }
+    }
+
+    Some(first)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_line_banners() {
+        assert_eq!(
+            parse_banner(b"SSH-2.0-OpenSSH_8.9p1 Ubuntu-3\r\n").unwrap(),
+            "SSH-2.0-OpenSSH_8.9p1 Ubuntu-3"
+        );
+        assert_eq!(
+            parse_banner(b"\r\n220 mail.example.com ESMTP Postfix\r\n").unwrap(),
+            "220 mail.example.com ESMTP Postfix"
+        );
+        assert_eq!(
+            parse_banner(b"HTTP/1.1 200 OK\r\nDate: today\r\nServer: nginx/1.18.0\r\n\r\n")
+                .unwrap(),
+            "HTTP/1.1 200 OK (nginx/1.18.0)"
+        );
+        assert_eq!(parse_banner(b"\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_mysql_banner() {
+        let mut handshake = vec![0x0a];
+        handshake.extend(b"8.0.32\0");
+        handshake.extend(&[0x01, 0x02, 0x03, 0x04]);
+        let mut packet = vec![handshake.len() as u8, 0, 0, 0];
+        packet.extend(handshake);
+
+        assert_eq!(parse_banner(&packet).unwrap(), "MySQL 8.0.32");
+    }
+
+    #[test]
+    fn test_grab_banner_local() {
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            conn.write_all(b"+OK POP3 ready\r\n").unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        assert_eq!(
+            grab_banner(&mut stream, addr.port()).unwrap(),
+            "+OK POP3 ready"
+        );
+    }
+}
@@ -0,0 +1,397 @@
+// Rogue DHCP server detection
+// Broadcast a DHCPDISCOVER and collect every DHCPOFFER that comes back.
+// https://datatracker.ietf.org/doc/html/rfc2131
+// https://datatracker.ietf.org/doc/html/rfc2132 (options)
+//
+// Raw frames are used for both sending and receiving so we don't need to
+// bind to port 68 (usually taken by the system DHCP client) and so we can
+// see the ethernet source of each offer.
+
+use anyhow::{anyhow, Result};
+use log::{info, trace};
+use pnet::datalink::{self, Channel, NetworkInterface};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::{self, Ipv4Packet, MutableIpv4Packet};
+use pnet::packet::udp::{self, MutableUdpPacket, UdpPacket};
+use pnet::packet::Packet;
+use pnet::util::MacAddr;
+
+use std::convert::TryInto;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+pub const DHCP_OFFER_WINDOW: Duration = Duration::from_secs(3);
+
+const DHCP_MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+const BOOTP_HEADER_LEN: usize = 236;
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+const FLAG_BROADCAST: u16 = 0x8000;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+
+// DHCP option codes
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAM_REQUEST: u8 = 55;
+const OPT_END: u8 = 255;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DhcpOffer {
+    pub server_ip: Ipv4Addr,
+    pub server_mac: Option<MacAddr>,
+    pub offered_ip: Ipv4Addr,
+    pub subnet_mask: Option<Ipv4Addr>,
+    pub lease_secs: Option<u32>,
+    pub routers: Vec<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+}
+
+pub fn build_discover(xid: u32, chaddr: MacAddr) -> Vec<u8> {
+    let mut bytes = vec![0u8; BOOTP_HEADER_LEN];
+    bytes[0] = BOOTREQUEST;
+    bytes[1] = HTYPE_ETHERNET;
+    bytes[2] = 6;
+    bytes[4..8].copy_from_slice(&xid.to_be_bytes());
+    // Ask for a broadcast reply since we have no address yet
+    bytes[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+    bytes[28..34].copy_from_slice(&chaddr.octets());
+
+    bytes.extend_from_slice(&DHCP_MAGIC_COOKIE);
+    bytes.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, DHCPDISCOVER]);
+    bytes.extend_from_slice(&[
+        OPT_PARAM_REQUEST,
+        5,
+        OPT_SUBNET_MASK,
+        OPT_ROUTER,
+        OPT_DNS,
+        OPT_LEASE_TIME,
+        OPT_SERVER_ID,
+    ]);
+    bytes.push(OPT_END);
+    bytes
+}
+
+fn ipv4_list(data: &[u8]) -> Vec<Ipv4Addr> {
+    data.chunks_exact(4)
+        .map(|oct| Ipv4Addr::new(oct[0], oct[1], oct[2], oct[3]))
+        .collect()
+}
+
+// Parse a DHCPOFFER payload (the UDP payload), ignoring replies to other transactions
+pub fn parse_offer(bytes: &[u8], xid: u32) -> Result<DhcpOffer> {
+    if bytes.len() < BOOTP_HEADER_LEN + DHCP_MAGIC_COOKIE.len() {
+        return Err(anyhow!("DHCP packet too short ({} bytes)", bytes.len()));
+    }
+
+    if bytes[0] != BOOTREPLY || bytes[4..8] != xid.to_be_bytes() {
+        return Err(anyhow!("Not a reply to our DHCP transaction"));
+    }
+
+    if bytes[BOOTP_HEADER_LEN..BOOTP_HEADER_LEN + 4] != DHCP_MAGIC_COOKIE {
+        return Err(anyhow!("Missing DHCP magic cookie"));
+    }
+
+    let offered_ip = ipv4_list(&bytes[16..20])[0];
+    // siaddr, overridden by the server identifier option if present
+    let mut server_ip = ipv4_list(&bytes[20..24])[0];
+    let mut msg_type = None;
+    let mut offer = DhcpOffer {
+        server_ip,
+        server_mac: None,
+        offered_ip,
+        subnet_mask: None,
+        lease_secs: None,
+        routers: vec![],
+        dns_servers: vec![],
+    };
+
+    let mut idx = BOOTP_HEADER_LEN + DHCP_MAGIC_COOKIE.len();
+    while idx < bytes.len() {
+        let code = bytes[idx];
+        if code == OPT_END {
+            break;
+        }
+        if code == OPT_PAD {
+            idx += 1;
+            continue;
+        }
+
+        let len = *bytes
+            .get(idx + 1)
+            .ok_or_else(|| anyhow!("Truncated DHCP option {}", code))? as usize;
+        let data = bytes
+            .get(idx + 2..idx + 2 + len)
+            .ok_or_else(|| anyhow!("Truncated DHCP option {}", code))?;
+
+        match code {
+            OPT_MESSAGE_TYPE => msg_type = data.first().copied(),
+            OPT_SUBNET_MASK => offer.subnet_mask = ipv4_list(data).first().copied(),
+            OPT_ROUTER => offer.routers = ipv4_list(data),
+            OPT_DNS => offer.dns_servers = ipv4_list(data),
+            OPT_SERVER_ID => {
+                if let Some(ip) = ipv4_list(data).first() {
+                    server_ip = *ip
+                }
+            }
+            OPT_LEASE_TIME => {
+                offer.lease_secs = data.try_into().ok().map(u32::from_be_bytes);
+            }
+            _ => {}
+        }
+
+        idx += 2 + len;
+    }
+
+    if msg_type != Some(DHCPOFFER) {
+        return Err(anyhow!("DHCP message is not an offer ({:?})", msg_type));
+    }
+
+    offer.server_ip = server_ip;
+    Ok(offer)
+}
+
+// Wrap a DHCP payload in a broadcast UDP/IPv4/Ethernet frame from 0.0.0.0:68
+fn build_discover_frame(src_mac: MacAddr, payload: &[u8]) -> Vec<u8> {
+    let udp_len = UDP_HEADER_LEN + payload.len();
+    let ip_len = IPV4_HEADER_LEN + udp_len;
+    let mut buf = vec![0u8; ETHERNET_HEADER_LEN + ip_len];
+
+    {
+        let mut udp_pkt =
+            MutableUdpPacket::new(&mut buf[ETHERNET_HEADER_LEN + IPV4_HEADER_LEN..]).unwrap();
+        udp_pkt.set_source(DHCP_CLIENT_PORT);
+        udp_pkt.set_destination(DHCP_SERVER_PORT);
+        udp_pkt.set_length(udp_len as u16);
+        udp_pkt.set_payload(payload);
+        let csum = udp::ipv4_checksum(
+            &udp_pkt.to_immutable(),
+            &Ipv4Addr::UNSPECIFIED,
+            &Ipv4Addr::BROADCAST,
+        );
+        udp_pkt.set_checksum(csum);
+    }
+
+    {
+        let mut ip_pkt = MutableIpv4Packet::new(&mut buf[ETHERNET_HEADER_LEN..]).unwrap();
+        ip_pkt.set_version(4);
+        ip_pkt.set_header_length((IPV4_HEADER_LEN / 4) as u8);
+        ip_pkt.set_total_length(ip_len as u16);
+        ip_pkt.set_ttl(64);
+        ip_pkt.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+        ip_pkt.set_source(Ipv4Addr::UNSPECIFIED);
+        ip_pkt.set_destination(Ipv4Addr::BROADCAST);
+        let csum = ipv4::checksum(&ip_pkt.to_immutable());
+        ip_pkt.set_checksum(csum);
+    }
+
+    let mut eth_pkt = MutableEthernetPacket::new(&mut buf).unwrap();
+    eth_pkt.set_destination(MacAddr::broadcast());
+    eth_pkt.set_source(src_mac);
+    eth_pkt.set_ethertype(EtherTypes::Ipv4);
+
+    buf
+}
+
+// Pull a DHCP reply out of a received ethernet frame, if it is one
+fn offer_from_frame(frame: &[u8], xid: u32) -> Option<DhcpOffer> {
+    let eth_pkt = EthernetPacket::new(frame)?;
+    if eth_pkt.get_ethertype() != EtherTypes::Ipv4 {
+        return None;
+    }
+
+    let ip_pkt = Ipv4Packet::new(eth_pkt.payload())?;
+    if ip_pkt.get_next_level_protocol() != IpNextHeaderProtocols::Udp {
+        return None;
+    }
+
+    let udp_pkt = UdpPacket::new(ip_pkt.payload())?;
+    if udp_pkt.get_destination() != DHCP_CLIENT_PORT {
+        return None;
+    }
+
+    let mut offer = parse_offer(udp_pkt.payload(), xid).ok()?;
+    offer.server_mac = Some(eth_pkt.get_source());
+    Some(offer)
+}
+
+// Broadcast a DHCPDISCOVER on the interface and collect offers for `window`.
+// Requires privileges to open a raw datalink channel.
+pub fn dhcp_discover(iface: &NetworkInterface, window: Duration) -> Result<Vec<DhcpOffer>> {
+    info!("Sending DHCPDISCOVER on {}", iface.name);
+    let config = datalink::Config {
+        read_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+
+    let (mut tx, mut rx) = match datalink::channel(iface, config)? {
+        Channel::Ethernet(tx, rx) => (tx, rx),
+        _ => return Err(anyhow!("Unsupported datalink channel on {}", iface.name)),
+    };
+
+    let src_mac = iface.mac.unwrap_or_else(MacAddr::zero);
+    let xid = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.subsec_nanos())
+        .unwrap_or(0xF00D);
+
+    let frame = build_discover_frame(src_mac, &build_discover(xid, src_mac));
+    match tx.send_to(&frame, None) {
+        Some(res) => res?,
+        None => return Err(anyhow!("Could not send DHCPDISCOVER on {}", iface.name)),
+    }
+
+    let mut offers: Vec<DhcpOffer> = vec![];
+    let start = Instant::now();
+    while start.elapsed() < window {
+        // Read timeouts surface as errors, just keep waiting until the window closes
+        if let Ok(frame) = rx.next() {
+            if let Some(offer) = offer_from_frame(frame, xid) {
+                trace!("Received DHCPOFFER {:?}", offer);
+                if !offers.contains(&offer) {
+                    offers.push(offer);
+                }
+            }
+        }
+    }
+
+    Ok(offers)
+}
+
+// Distinct servers that answered in the order they did, a server may send
+// more than one offer
+pub fn servers(offers: &[DhcpOffer]) -> Vec<(Ipv4Addr, Option<MacAddr>)> {
+    let mut servers = vec![];
+    for offer in offers {
+        let server = (offer.server_ip, offer.server_mac);
+        if !servers.contains(&server) {
+            servers.push(server);
+        }
+    }
+    servers
+}
+
+pub fn server_count(offers: &[DhcpOffer]) -> usize {
+    servers(offers).len()
+}
+
+// The server addresses, with the MAC for servers claiming the same address
+pub fn describe_servers(offers: &[DhcpOffer]) -> String {
+    let servers = servers(offers);
+    servers
+        .iter()
+        .map(|&(ip, mac)| match mac {
+            Some(mac) if servers.iter().filter(|(other, _)| *other == ip).count() > 1 => {
+                format!("{} ({})", ip, mac)
+            }
+            _ => ip.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const XID: u32 = 0xDEADBEEF;
+
+    fn offer_bytes(server_ip: [u8; 4]) -> Vec<u8> {
+        let mut bytes = vec![0u8; BOOTP_HEADER_LEN];
+        bytes[0] = BOOTREPLY;
+        bytes[4..8].copy_from_slice(&XID.to_be_bytes());
+        bytes[16..20].copy_from_slice(&[10, 0, 0, 50]);
+        bytes.extend_from_slice(&DHCP_MAGIC_COOKIE);
+        bytes.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, DHCPOFFER]);
+        bytes.extend_from_slice(&[OPT_SERVER_ID, 4]);
+        bytes.extend_from_slice(&server_ip);
+        bytes.extend_from_slice(&[OPT_LEASE_TIME, 4, 0x00, 0x01, 0x51, 0x80]);
+        bytes.extend_from_slice(&[OPT_ROUTER, 4, 10, 0, 0, 1]);
+        bytes.extend_from_slice(&[OPT_DNS, 8, 10, 0, 0, 1, 1, 1, 1, 1]);
+        bytes.push(OPT_PAD);
+        bytes.extend_from_slice(&[OPT_SUBNET_MASK, 4, 255, 255, 255, 0]);
+        bytes.push(OPT_END);
+        bytes
+    }
+
+    #[test]
+    fn test_build_discover() {
+        let mac = MacAddr::new(0x02, 0, 0, 0, 0, 0x01);
+        let bytes = build_discover(XID, mac);
+
+        assert_eq!(bytes[0], BOOTREQUEST);
+        assert_eq!(bytes[4..8], [0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(bytes[10..12], [0x80, 0x00]);
+        assert_eq!(bytes[28..34], mac.octets());
+        assert_eq!(bytes[236..240], DHCP_MAGIC_COOKIE);
+        assert_eq!(bytes[240..243], [OPT_MESSAGE_TYPE, 1, DHCPDISCOVER]);
+        assert_eq!(bytes.last(), Some(&OPT_END));
+    }
+
+    #[test]
+    fn test_parse_offer() {
+        let offer = parse_offer(&offer_bytes([10, 0, 0, 1]), XID).unwrap();
+
+        assert_eq!(offer.server_ip, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(offer.offered_ip, Ipv4Addr::new(10, 0, 0, 50));
+        assert_eq!(offer.lease_secs, Some(86400));
+        assert_eq!(offer.routers, vec![Ipv4Addr::new(10, 0, 0, 1)]);
+        assert_eq!(
+            offer.dns_servers,
+            vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(1, 1, 1, 1)]
+        );
+        assert_eq!(offer.subnet_mask, Some(Ipv4Addr::new(255, 255, 255, 0)));
+
+        // Wrong transaction
+        assert!(parse_offer(&offer_bytes([10, 0, 0, 1]), 0x1234).is_err());
+    }
+
+    #[test]
+    fn test_offer_from_frame() {
+        let server_mac = MacAddr::new(0x02, 0, 0, 0, 0, 0x99);
+        let mut frame = build_discover_frame(server_mac, &offer_bytes([10, 0, 0, 2]));
+        // Address the frame to the client port
+        let udp_start = ETHERNET_HEADER_LEN + IPV4_HEADER_LEN;
+        frame[udp_start + 2..udp_start + 4].copy_from_slice(&DHCP_CLIENT_PORT.to_be_bytes());
+
+        let offer = offer_from_frame(&frame, XID).unwrap();
+        assert_eq!(offer.server_ip, Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(offer.server_mac, Some(server_mac));
+    }
+
+    #[test]
+    fn test_server_count() {
+        let first = parse_offer(&offer_bytes([10, 0, 0, 1]), XID).unwrap();
+        let mut second = first.clone();
+        second.offered_ip = Ipv4Addr::new(10, 0, 0, 51);
+        let rogue = parse_offer(&offer_bytes([10, 0, 0, 66]), XID).unwrap();
+
+        assert_eq!(server_count(&[first.clone(), second.clone()]), 1);
+        assert_eq!(describe_servers(&[first.clone(), second]), "10.0.0.1");
+        assert_eq!(server_count(&[first.clone(), rogue]), 2);
+
+        // Two servers on one address are told apart by MAC
+        let mut clash = first.clone();
+        clash.server_mac = Some(MacAddr::new(0x02, 0, 0, 0, 0, 0x02));
+        let mut first = first;
+        first.server_mac = Some(MacAddr::new(0x02, 0, 0, 0, 0, 0x01));
+        assert_eq!(
+            describe_servers(&[first, clash]),
+            "10.0.0.1 (02:00:00:00:00:01), 10.0.0.1 (02:00:00:00:00:02)"
+        );
+    }
+}
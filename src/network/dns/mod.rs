@@ -25,6 +25,8 @@ use decoders::DnsAnswerDecoder;
 use encoders::DnsAddressEncoder;
 use transactors::{UdpTransactorType, UDP_MDNS_MULTICAST_ADDR, UDP_MDNS_MULTICAST_PORT};
 
+use super::rate_limit::RATE_LIMITER;
+
 use anyhow::Result;
 use bincode::config::{DefaultOptions, Options};
 use log::trace;
@@ -205,6 +207,7 @@ pub fn reverse_dns_lookup<T: DnsAnswerDecoder>(
     ip: Ipv4Addr,
     port: HostnameLookupUdpPort,
     transactor: UdpTransactorType,
+    timeout: Duration,
 ) -> Result<T> {
     let qtype = T::default_qtype();
 
@@ -216,9 +219,11 @@ pub fn reverse_dns_lookup<T: DnsAnswerDecoder>(
 
     match transactor {
         UdpTransactorType::HostTransact => {
-            udp_host_transact((ip, port as u16), &mut packet, &mut buf)?
+            udp_host_transact((ip, port as u16), &mut packet, &mut buf, timeout)?
+        }
+        UdpTransactorType::MulticastTransact => {
+            udp_multicast_transact(&mut packet, &mut buf, timeout)?
         }
-        UdpTransactorType::MulticastTransact => udp_multicast_transact(&mut packet, &mut buf)?,
     }
     trace!("Received tx bytes: {:?}", buf);
 
@@ -229,26 +234,29 @@ fn udp_host_transact<A: ToSocketAddrs + std::fmt::Debug>(
     dst: A,
     packet: &mut DnsPacket,
     buf: &mut [u8],
+    timeout: Duration,
 ) -> Result<()> {
     trace!("Starting UDP DNS transaction to {:?}", dst);
     let usock = UdpSocket::bind("0.0.0.0:0")?;
     usock.connect(dst)?;
+    RATE_LIMITER.packet();
     usock.send(&packet.as_bytes().unwrap())?;
-    usock.set_read_timeout(Some(Duration::from_millis(2000)))?;
+    usock.set_read_timeout(Some(timeout))?;
     usock.recv(buf)?;
     Ok(())
 }
 
-fn udp_multicast_transact(packet: &mut DnsPacket, buf: &mut [u8]) -> Result<()> {
+fn udp_multicast_transact(packet: &mut DnsPacket, buf: &mut [u8], timeout: Duration) -> Result<()> {
     trace!("Starting multicast transaction");
     let usock = UdpSocket::bind("0.0.0.0:0")?;
     usock.join_multicast_v4(&UDP_MDNS_MULTICAST_ADDR, &Ipv4Addr::UNSPECIFIED)?;
     usock.set_multicast_loop_v4(true)?;
+    RATE_LIMITER.packet();
     usock.send_to(
         &packet.as_bytes().unwrap(),
         (UDP_MDNS_MULTICAST_ADDR, UDP_MDNS_MULTICAST_PORT),
     )?;
-    usock.set_read_timeout(Some(Duration::from_millis(2000)))?;
+    usock.set_read_timeout(Some(timeout))?;
     usock.recv_from(buf)?;
     Ok(())
 }
@@ -0,0 +1,229 @@
+// Hosts remembered across scans, so that things like Wake-on-LAN can
+// target a host by IP even when it is asleep and not answering ARP,
+// and SSH host keys can be compared with the previous scan.
+//
+// Stored as one tab separated `ip mac hostname [ssh_keys]` line per host in
+// $XDG_CACHE_HOME/findi/hosts (falling back to ~/.cache/findi/hosts).
+// The MAC may be empty, SSH keys are comma separated `type=fingerprint`.
+
+use super::host::Host;
+use super::ssh::{SshHostKey, SshInfo};
+
+use anyhow::{anyhow, Result};
+use pnet::util::MacAddr;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+const HISTORY_FILE: &str = "hosts";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub mac: Option<MacAddr>,
+    pub host_name: Option<String>,
+    pub ssh_keys: Vec<SshHostKey>,
+}
+
+#[derive(Default, Debug)]
+pub struct ScanHistory {
+    pub entries: BTreeMap<Ipv4Addr, HistoryEntry>,
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("findi")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("findi")),
+    }
+}
+
+impl ScanHistory {
+    // A missing or unreadable history file is treated as an empty history
+    pub fn load() -> ScanHistory {
+        cache_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(HISTORY_FILE)).ok())
+            .map(|contents| ScanHistory::parse(&contents))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = cache_dir().ok_or_else(|| anyhow!("Could not find a cache directory"))?;
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(HISTORY_FILE), self.serialize())?;
+        Ok(())
+    }
+
+    pub fn parse(contents: &str) -> ScanHistory {
+        let mut history = ScanHistory::default();
+        for line in contents.lines() {
+            let mut cols = line.split('\t');
+            let ip = cols.next().and_then(|ip| ip.parse::<Ipv4Addr>().ok());
+            let mac = cols.next().and_then(|mac| mac.parse::<MacAddr>().ok());
+            let host_name = cols.next().filter(|hn| !hn.is_empty()).map(str::to_owned);
+            let ssh_keys: Vec<SshHostKey> = cols
+                .next()
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|key| key.split_once('='))
+                .map(|(key_type, fingerprint)| SshHostKey {
+                    key_type: key_type.to_owned(),
+                    fingerprint: fingerprint.to_owned(),
+                })
+                .collect();
+
+            if let Some(ip) = ip {
+                if mac.is_some() || !ssh_keys.is_empty() {
+                    history.entries.insert(
+                        ip,
+                        HistoryEntry {
+                            mac,
+                            host_name,
+                            ssh_keys,
+                        },
+                    );
+                }
+            }
+        }
+        history
+    }
+
+    pub fn serialize(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(ip, entry)| {
+                let mut line = format!(
+                    "{}\t{}\t{}",
+                    ip,
+                    entry.mac.map_or_else(String::new, |mac| mac.to_string()),
+                    entry.host_name.as_deref().unwrap_or("")
+                );
+                if !entry.ssh_keys.is_empty() {
+                    let keys: Vec<String> = entry
+                        .ssh_keys
+                        .iter()
+                        .map(|key| format!("{}={}", key.key_type, key.fingerprint))
+                        .collect();
+                    line = format!("{}\t{}", line, keys.join(","));
+                }
+                line + "\n"
+            })
+            .collect()
+    }
+
+    // Remember every host with a known MAC or SSH keys, newer results win
+    // but don't forget what this scan didn't look at
+    pub fn record_hosts<'a, I: IntoIterator<Item = &'a Host>>(&mut self, hosts: I) {
+        for host in hosts {
+            let ssh_keys = host
+                .ssh
+                .as_ref()
+                .map(|ssh| ssh.host_keys.clone())
+                .unwrap_or_default();
+            if host.mac.is_none() && ssh_keys.is_empty() {
+                continue;
+            }
+
+            let host_name = match &host.host_name {
+                Some(Ok(hn)) => Some(hn.to_owned()),
+                _ => None,
+            };
+            let old = self.entries.remove(&host.ip);
+            self.entries.insert(
+                host.ip,
+                HistoryEntry {
+                    mac: host.mac.or_else(|| old.as_ref().and_then(|e| e.mac)),
+                    host_name,
+                    ssh_keys: match ssh_keys.is_empty() {
+                        true => old.map(|e| e.ssh_keys).unwrap_or_default(),
+                        false => ssh_keys,
+                    },
+                },
+            );
+        }
+    }
+
+    pub fn mac_for(&self, ip: &Ipv4Addr) -> Option<MacAddr> {
+        self.entries.get(ip).and_then(|entry| entry.mac)
+    }
+
+    // A key type this IP had before now has a different fingerprint
+    pub fn ssh_key_changed(&self, ip: &Ipv4Addr, ssh: &SshInfo) -> bool {
+        let old_keys = match self.entries.get(ip) {
+            Some(entry) => &entry.ssh_keys,
+            None => return false,
+        };
+
+        ssh.host_keys.iter().any(|key| {
+            old_keys
+                .iter()
+                .any(|old| old.key_type == key.key_type && old.fingerprint != key.fingerprint)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history_roundtrip() {
+        let mut host = Host::new(Ipv4Addr::new(10, 0, 0, 2));
+        host.mac = Some(MacAddr::new(0xaa, 0xbb, 0xcc, 0x00, 0x11, 0x22));
+        host.host_name = Some(Ok("desktop.local".to_owned()));
+
+        let unknown_mac = Host::new(Ipv4Addr::new(10, 0, 0, 3));
+
+        let mut history = ScanHistory::default();
+        history.record_hosts(vec![&host, &unknown_mac]);
+
+        let serialized = history.serialize();
+        assert_eq!(serialized, "10.0.0.2\taa:bb:cc:00:11:22\tdesktop.local\n");
+
+        let parsed = ScanHistory::parse(&serialized);
+        assert_eq!(parsed.mac_for(&host.ip), host.mac);
+        assert_eq!(parsed.mac_for(&unknown_mac.ip), None);
+    }
+
+    #[test]
+    fn test_history_ssh_keys() {
+        let key = |fingerprint: &str| SshHostKey {
+            key_type: "ssh-ed25519".to_owned(),
+            fingerprint: fingerprint.to_owned(),
+        };
+
+        let mut host = Host::new(Ipv4Addr::new(10, 0, 0, 5));
+        host.ssh = Some(SshInfo {
+            host_keys: vec![key("SHA256:aaa")],
+            ..Default::default()
+        });
+
+        let mut history = ScanHistory::default();
+        history.record_hosts(vec![&host]);
+
+        let serialized = history.serialize();
+        assert_eq!(serialized, "10.0.0.5\t\t\tssh-ed25519=SHA256:aaa\n");
+
+        let parsed = ScanHistory::parse(&serialized);
+        assert_eq!(parsed.mac_for(&host.ip), None);
+        assert!(!parsed.ssh_key_changed(&host.ip, host.ssh.as_ref().unwrap()));
+
+        let changed = SshInfo {
+            host_keys: vec![key("SHA256:bbb")],
+            ..Default::default()
+        };
+        assert!(parsed.ssh_key_changed(&host.ip, &changed));
+        assert!(!parsed.ssh_key_changed(&Ipv4Addr::new(10, 0, 0, 6), &changed));
+    }
+
+    #[test]
+    fn test_history_parse_skips_malformed() {
+        let parsed = ScanHistory::parse("10.0.0.2\tnot-a-mac\t\n\n10.0.0.4\t00:11:22:33:44:55\n");
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(
+            parsed.mac_for(&Ipv4Addr::new(10, 0, 0, 4)),
+            Some(MacAddr::new(0, 0x11, 0x22, 0x33, 0x44, 0x55))
+        );
+    }
+}
@@ -1,26 +1,42 @@
+use super::arp::arp_cache_lookup;
 use super::dns::{
     decoders::{MdnsAnswer, NbnsAnswer},
     reverse_dns_lookup,
     transactors::UdpTransactorType::{HostTransact, MulticastTransact},
     HostnameLookupUdpPort,
 };
+use super::http::HttpInfo;
+use super::icmp_ping;
+use super::os_fingerprint::OsGuess;
 use super::ping_result::PingResultOption;
-use super::tcp_ping::{tcp_ping, TCP_PING_PORT};
-use super::udp_ping::udp_ping;
+use super::probe::{LivenessEvidence, PingStrategy};
+use super::service_probes::{Protocol, ServiceId};
+use super::snmp::{snmp_get_system, SnmpSysInfo};
+use super::ssh::SshInfo;
+use super::tls::TlsCertInfo;
+use super::udp_scan::UdpPortState;
+use crate::config::AppConfig;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use log::warn;
+use pnet::util::MacAddr;
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::Duration;
 
 pub type HostVec = Vec<Host>;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum PingType {
     UDP,
     TCP,
+    ICMP,
+    ARP,
+    MDNS,
 }
 
 impl fmt::Display for PingType {
@@ -32,14 +48,84 @@ impl fmt::Display for PingType {
             PingType::TCP => {
                 write!(f, "TCP")
             }
+            PingType::ICMP => {
+                write!(f, "ICMP")
+            }
+            PingType::ARP => {
+                write!(f, "ARP")
+            }
+            PingType::MDNS => {
+                write!(f, "mDNS")
+            }
+        }
+    }
+}
+
+impl FromStr for PingType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "udp" => Ok(PingType::UDP),
+            "tcp" => Ok(PingType::TCP),
+            "icmp" => Ok(PingType::ICMP),
+            "arp" => Ok(PingType::ARP),
+            "mdns" => Ok(PingType::MDNS),
+            _ => Err(anyhow!(
+                "Unknown ping method {}, use udp, tcp, icmp, arp or mdns",
+                s
+            )),
         }
     }
 }
 
+// Reverse name lookups, tried in order until one answers
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NameResolver {
+    MDNS,
+    DNS,
+    NBNS,
+}
+
+impl fmt::Display for NameResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            NameResolver::MDNS => write!(f, "mdns"),
+            NameResolver::DNS => write!(f, "dns"),
+            NameResolver::NBNS => write!(f, "nbns"),
+        }
+    }
+}
+
+impl FromStr for NameResolver {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mdns" => Ok(NameResolver::MDNS),
+            "dns" => Ok(NameResolver::DNS),
+            "nbns" => Ok(NameResolver::NBNS),
+            _ => Err(anyhow!("Unknown resolver {}, use mdns, dns or nbns", s)),
+        }
+    }
+}
+
+// An empty list turns name lookups off
+pub fn parse_resolvers(resolvers: &str) -> Result<Vec<NameResolver>> {
+    resolvers
+        .split(',')
+        .filter(|r| !r.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum HostResolutionType {
     MDNS,
     NBNS,
+    SNMP,
 }
 
 impl fmt::Display for HostResolutionType {
@@ -51,6 +137,9 @@ impl fmt::Display for HostResolutionType {
             HostResolutionType::NBNS => {
                 write!(f, "NetBios Name Service")
             }
+            HostResolutionType::SNMP => {
+                write!(f, "SNMP sysName")
+            }
         }
     }
 }
@@ -60,91 +149,180 @@ pub struct Host {
     pub ip: Ipv4Addr,
     pub ping_res: PingResultOption,
     pub ping_type: Option<PingType>,
+    // Port that answered a TCP ping
+    pub ping_port: Option<u16>,
+    // What the ping got back
+    pub liveness: Option<LivenessEvidence>,
+    // Didn't answer pings, but did answer a name lookup
+    pub alive_by_name: bool,
     pub tcp_ports: HashSet<u16>,
+    // Open and open|filtered UDP ports
+    pub udp_ports: BTreeMap<u16, UdpPortState>,
     pub host_name: Option<Result<String, String>>,
     pub res_type: Option<HostResolutionType>,
     pub ping_done: bool,
+    pub mac: Option<MacAddr>,
+    pub snmp: Option<SnmpSysInfo>,
+    // Service banners of open TCP ports
+    pub banners: BTreeMap<u16, String>,
+    // Services identified by the user's probe file
+    pub services: BTreeMap<(Protocol, u16), ServiceId>,
+    // Certificates of open TLS ports
+    pub tls_certs: BTreeMap<u16, TlsCertInfo>,
+    // Web pages of open HTTP(S) ports
+    pub http: BTreeMap<u16, HttpInfo>,
+    pub ssh: Option<SshInfo>,
+    pub os: Option<OsGuess>,
 }
 
 // TODO:
 // a  user setting can indicate whether a tcp and/or a udp ping should be use
 // also allow for ICMP echo
 impl Host {
-    pub fn host_ping(ip: Ipv4Addr) -> Host {
+    pub fn host_ping(ip: Ipv4Addr, config: &AppConfig, strategy: &PingStrategy) -> Host {
         let mut host = Host::new(ip);
-        host.ping();
-        if host.ping_res.is_none() {
+        host.ping(strategy);
+
+        // Name lookups go to the host itself (or a sleep proxy answering for it),
+        // so an answer means something is there. Try the probes that weren't run.
+        if host.ping_res.is_none()
+            && config.resolve_unresponsive
+            && host.resolve_name(&config.resolvers, config.timing.max_rtt_timeout)
+        {
+            host.ping(&strategy.fallback(config, icmp_ping::privileged()));
+            host.alive_by_name = host.ping_res.is_none();
+        }
+
+        if !host.is_alive() {
             host.ping_done = true;
             return host;
         }
 
-        // TODO CONFIG: do multicast lookup in a different thread?
-        // Standardize error
+        // The ping will have populated the ARP cache for local hosts
+        if host.mac.is_none() {
+            host.mac = arp_cache_lookup(ip);
+        }
 
-        // Perform DNS reverse lookup
-        // Then mDNS reverse lookup if fails
-        // Then NBNS NBSTAT query if fails
-        match reverse_dns_lookup::<MdnsAnswer>(ip, HostnameLookupUdpPort::MDNS, MulticastTransact) {
-            Ok(ans) => {
-                host.host_name = Some(Ok(ans.hostname));
-                host.res_type = Some(HostResolutionType::MDNS)
-            }
-            Err(_) => {
-                match reverse_dns_lookup::<MdnsAnswer>(ip, HostnameLookupUdpPort::DNS, HostTransact)
-                {
-                    Ok(ans) => {
-                        host.host_name = Some(Ok(ans.hostname));
-                        host.res_type = Some(HostResolutionType::MDNS)
-                    }
-                    Err(_) => match reverse_dns_lookup::<NbnsAnswer>(
-                        ip,
-                        HostnameLookupUdpPort::NBSTAT,
-                        HostTransact,
-                    ) {
-                        Ok(ans) => {
-                            host.host_name = Some(Ok(ans.hostname));
-                            host.res_type = Some(HostResolutionType::NBNS)
-                        }
-                        Err(_) => host.host_name = Some(Err("Reverse lookup failed".to_owned())),
-                    },
-                }
-            }
+        if !config.snmp_communities.is_empty() {
+            host.snmp = snmp_get_system(ip, &config.snmp_communities).ok();
+        }
+
+        if host.host_name.is_none() {
+            // Answers wait on the name service, not the network, so the
+            // template's timeout rather than one adapted to the ping
+            host.resolve_name(&config.resolvers, config.timing.max_rtt_timeout);
+        }
+
+        // Last resort, a name from the SNMP system group
+        if let (Some(Err(_)), Some(name)) = (
+            &host.host_name,
+            host.snmp.as_ref().and_then(|snmp| snmp.name.clone()),
+        ) {
+            host.host_name = Some(Ok(name));
+            host.res_type = Some(HostResolutionType::SNMP)
         }
 
         host.ping_done = true;
         host
     }
 
+    // TODO CONFIG: do multicast lookup in a different thread?
+    // Standardize error
+
+    // Try each name lookup in the configured order, mDNS, DNS then NBNS
+    // NBSTAT by default
+    // Returns whether a name was found
+    fn resolve_name(&mut self, resolvers: &[NameResolver], timeout: Duration) -> bool {
+        let ip = self.ip;
+        for resolver in resolvers {
+            let res = match resolver {
+                NameResolver::MDNS => reverse_dns_lookup::<MdnsAnswer>(
+                    ip,
+                    HostnameLookupUdpPort::MDNS,
+                    MulticastTransact,
+                    timeout,
+                )
+                .map(|ans| (ans.hostname, HostResolutionType::MDNS)),
+                NameResolver::DNS => reverse_dns_lookup::<MdnsAnswer>(
+                    ip,
+                    HostnameLookupUdpPort::DNS,
+                    HostTransact,
+                    timeout,
+                )
+                .map(|ans| (ans.hostname, HostResolutionType::MDNS)),
+                NameResolver::NBNS => reverse_dns_lookup::<NbnsAnswer>(
+                    ip,
+                    HostnameLookupUdpPort::NBSTAT,
+                    HostTransact,
+                    timeout,
+                )
+                .map(|ans| (ans.hostname, HostResolutionType::NBNS)),
+            };
+            if let Ok((hostname, res_type)) = res {
+                self.host_name = Some(Ok(hostname));
+                self.res_type = Some(res_type);
+                return true;
+            }
+        }
+        self.host_name = Some(Err("Reverse lookup failed".to_owned()));
+        false
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.ping_res.is_some() || self.alive_by_name
+    }
+
+    pub fn alive_reason(&self) -> Option<String> {
+        match (&self.liveness, self.alive_by_name, self.res_type) {
+            (Some(liveness), _, _) => Some(liveness.to_string()),
+            (None, true, Some(res_type)) => Some(format!("{} answer, no ping reply", res_type)),
+            _ => None,
+        }
+    }
+
     pub fn new(ip: Ipv4Addr) -> Host {
         Host {
             ip,
             ping_res: None,
             ping_type: None,
+            ping_port: None,
+            liveness: None,
+            alive_by_name: false,
             host_name: None,
             tcp_ports: HashSet::default(),
+            udp_ports: BTreeMap::new(),
             ping_done: false,
             res_type: None,
+            mac: None,
+            snmp: None,
+            banners: BTreeMap::new(),
+            services: BTreeMap::new(),
+            tls_certs: BTreeMap::new(),
+            http: BTreeMap::new(),
+            ssh: None,
+            os: None,
         }
     }
 
-    pub fn ping(&mut self) {
-        self.ping_res = match udp_ping(self.ip) {
-            Ok(t) => {
-                self.ping_type = Some(PingType::UDP);
-                Some(t)
-            }
-
-            Err(_) => match tcp_ping(self.ip) {
-                Ok(t) => {
-                    self.ping_type = Some(PingType::TCP);
-                    self.tcp_ports.insert(TCP_PING_PORT);
-                    Some(t)
+    pub fn ping(&mut self, strategy: &PingStrategy) {
+        match strategy.run(self.ip) {
+            Some(reply) => {
+                self.ping_res = Some(reply.rtt);
+                self.ping_type = Some(reply.ping_type);
+                self.ping_port = match reply.ping_type {
+                    PingType::TCP => reply.port,
+                    _ => None,
+                };
+                self.liveness = Some(reply.evidence());
+                if let (Some(port), true) = (reply.port, reply.port_open) {
+                    self.tcp_ports.insert(port);
                 }
-                Err(_) => {
-                    warn!("TCP ping failed to {:?}", self.ip);
-                    None
-                }
-            },
+                self.mac = reply.mac.or(self.mac);
+            }
+            None => {
+                warn!("No ping answer from {:?}", self.ip);
+                self.ping_res = None;
+            }
         }
     }
 }
@@ -0,0 +1,297 @@
+// HTTP fingerprinting of web admin pages: status, Server header, <title>,
+// redirect target and the favicon hash in the format Shodan uses
+// (murmur3 of the base64 encoded icon), so devices can be matched by icon.
+
+use super::encoding::base64_encode;
+use super::rate_limit::connect_limited;
+use super::tls::tls_connect;
+
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use log::info;
+use regex::Regex;
+
+use std::io::prelude::*;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+const HTTP_TIMEOUT: Duration = Duration::from_millis(3000);
+// Enough for the <head> of any admin page
+const MAX_RESPONSE_LEN: usize = 256 * 1024;
+const MAX_TITLE_LEN: usize = 80;
+
+pub const HTTP_PORTS: [u16; 6] = [80, 591, 8000, 8008, 8080, 8888];
+pub const HTTPS_PORTS: [u16; 2] = [443, 8443];
+
+lazy_static! {
+    static ref TITLE_RE: Regex = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct HttpInfo {
+    pub https: bool,
+    pub status: u16,
+    pub server: Option<String>,
+    pub title: Option<String>,
+    pub location: Option<String>,
+    pub favicon_hash: Option<i32>,
+}
+
+#[derive(Debug, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, val)| val.as_str())
+    }
+}
+
+impl HttpInfo {
+    // What to show when there is only room for one thing
+    pub fn summary(&self) -> String {
+        match (&self.title, &self.server) {
+            (Some(title), _) => title.to_owned(),
+            (None, Some(server)) => format!("{} {}", self.status, server),
+            (None, None) => self.status.to_string(),
+        }
+    }
+}
+
+// Some(true) for HTTPS ports, Some(false) for plain HTTP ports
+pub fn web_port(port: u16) -> Option<bool> {
+    if HTTPS_PORTS.contains(&port) {
+        Some(true)
+    } else if HTTP_PORTS.contains(&port) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+pub fn parse_response(bytes: &[u8]) -> Result<HttpResponse> {
+    let header_end = bytes
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("Incomplete HTTP response"))?;
+
+    let head = String::from_utf8_lossy(&bytes[..header_end]);
+    let mut lines = head.lines();
+
+    let status = lines
+        .next()
+        .filter(|line| line.starts_with("HTTP/"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow!("Not an HTTP response"))?;
+
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, val)| (key.trim().to_owned(), val.trim().to_owned()))
+        .collect();
+
+    Ok(HttpResponse {
+        status,
+        headers,
+        body: bytes[header_end + 4..].to_vec(),
+    })
+}
+
+pub fn parse_title(body: &[u8]) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let title = TITLE_RE.captures(&body)?.get(1)?.as_str();
+
+    let title = title
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'");
+
+    match title.is_empty() {
+        true => None,
+        false => Some(title.chars().take(MAX_TITLE_LEN).collect()),
+    }
+}
+
+// Base64 with a newline every 76 characters, like Python's base64.encodebytes
+pub fn base64_mime(data: &[u8]) -> String {
+    let encoded = base64_encode(data);
+
+    let mut out = String::new();
+    for line in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out
+}
+
+pub fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut hash = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+        hash = hash
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut k: u32 = 0;
+        for (i, b) in tail.iter().enumerate() {
+            k |= (*b as u32) << (8 * i);
+        }
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+    hash
+}
+
+pub fn favicon_hash(icon: &[u8]) -> i32 {
+    murmur3_32(base64_mime(icon).as_bytes(), 0) as i32
+}
+
+fn read_response<S: Read + Write>(stream: &mut S, ip: &Ipv4Addr, path: &str) -> Result<Vec<u8>> {
+    // HTTP/1.0 so the body is never chunked
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: findi\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        path, ip
+    );
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = vec![];
+    let mut buf = [0u8; 8192];
+    // Servers that skip the TLS close_notify error out here, keep what was read
+    while let Ok(n) = stream.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&buf[..n]);
+        if response.len() >= MAX_RESPONSE_LEN {
+            break;
+        }
+    }
+    Ok(response)
+}
+
+pub fn http_get(ip: &Ipv4Addr, port: u16, https: bool, path: &str) -> Result<HttpResponse> {
+    let response = if https {
+        read_response(&mut tls_connect(ip, port)?, ip, path)?
+    } else {
+        let sockaddr = SocketAddr::new(IpAddr::V4(*ip), port);
+        let mut stream = connect_limited(&sockaddr, HTTP_TIMEOUT)?;
+        stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+        read_response(&mut stream, ip, path)?
+    };
+
+    parse_response(&response)
+}
+
+pub fn http_fingerprint(ip: &Ipv4Addr, port: u16, https: bool) -> Result<HttpInfo> {
+    info!("HTTP fingerprinting {}:{}", ip, port);
+    let res = http_get(ip, port, https, "/")?;
+
+    let favicon_hash = http_get(ip, port, https, "/favicon.ico")
+        .ok()
+        .filter(|icon| icon.status == 200 && !icon.body.is_empty())
+        .map(|icon| favicon_hash(&icon.body));
+
+    Ok(HttpInfo {
+        https,
+        status: res.status,
+        server: res.header("server").map(str::to_owned),
+        title: parse_title(&res.body),
+        location: res.header("location").map(str::to_owned),
+        favicon_hash,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_favicon_hash_primitives() {
+        assert_eq!(base64_mime(b"hello"), "aGVsbG8=\n");
+        assert_eq!(base64_mime(b"ab"), "YWI=\n");
+        assert_eq!(base64_mime(&[0u8; 60]).lines().next().unwrap().len(), 76);
+
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"hello", 0), 0x248b_fa47);
+        assert_eq!(
+            murmur3_32(b"The quick brown fox jumps over the lazy dog", 0),
+            0x2e4f_f723
+        );
+    }
+
+    #[test]
+    fn test_parse_title() {
+        assert_eq!(
+            parse_title(b"<html><head><TITLE lang=en>\n  HP LaserJet &amp; co\n</TITLE>").unwrap(),
+            "HP LaserJet & co"
+        );
+        assert_eq!(parse_title(b"<title>  </title>"), None);
+        assert_eq!(parse_title(b"<html>no title</html>"), None);
+    }
+
+    #[test]
+    fn test_http_fingerprint_stub() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            for conn in listener.incoming().take(2) {
+                let mut conn = conn.unwrap();
+                let mut buf = [0u8; 1024];
+                let n = conn.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+
+                let response: &[u8] = if request.starts_with("GET /favicon.ico ") {
+                    b"HTTP/1.0 200 OK\r\nContent-Type: image/x-icon\r\n\r\nicon"
+                } else {
+                    b"HTTP/1.0 302 Found\r\nServer: lighttpd/1.4\r\nLocation: /login.html\r\n\r\n<title>Printer</title>"
+                };
+                conn.write_all(response).unwrap();
+            }
+        });
+
+        let info = http_fingerprint(&Ipv4Addr::LOCALHOST, port, false).unwrap();
+        assert_eq!(
+            info,
+            HttpInfo {
+                https: false,
+                status: 302,
+                server: Some("lighttpd/1.4".to_owned()),
+                title: Some("Printer".to_owned()),
+                location: Some("/login.html".to_owned()),
+                favicon_hash: Some(favicon_hash(b"icon")),
+            }
+        );
+    }
+}
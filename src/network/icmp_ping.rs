@@ -0,0 +1,104 @@
+use log::info;
+use pnet::packet::icmp::echo_reply::EchoReplyPacket;
+use pnet::packet::icmp::echo_request::MutableEchoRequestPacket;
+use pnet::packet::icmp::{checksum, IcmpPacket, IcmpTypes};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::Packet;
+use pnet::transport::{
+    ipv4_packet_iter, transport_channel,
+    TransportChannelType::{Layer3, Layer4},
+    TransportProtocol::Ipv4,
+};
+
+use super::rate_limit::RATE_LIMITER;
+
+use std::io::{self, Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+const ICMP_PAYLOAD_LEN: usize = 8;
+
+// Every worker shares the raw socket's view of ICMP replies, the sequence
+// number tells our echo apart from the others
+static ICMP_SEQ: AtomicU16 = AtomicU16::new(0);
+
+pub fn build_echo_request(identifier: u16, seq: u16) -> Vec<u8> {
+    let mut buf = vec![0u8; 8 + ICMP_PAYLOAD_LEN];
+    let mut pkt = MutableEchoRequestPacket::new(&mut buf).unwrap();
+    pkt.set_icmp_type(IcmpTypes::EchoRequest);
+    pkt.set_identifier(identifier);
+    pkt.set_sequence_number(seq);
+    pkt.set_payload(b"findi-pg");
+
+    let csum = checksum(&IcmpPacket::new(pkt.packet()).unwrap());
+    pkt.set_checksum(csum);
+    buf
+}
+
+// Whether raw sockets can be opened, CAP_NET_RAW alone isn't detected
+#[cfg(target_os = "linux")]
+pub fn privileged() -> bool {
+    // SAFETY: geteuid has no preconditions and always succeeds
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn privileged() -> bool {
+    false
+}
+
+// ICMP echo, needs raw sockets (root). Returns the round trip and the reply's TTL.
+pub fn icmp_ping(ip: Ipv4Addr, timeout: Duration) -> io::Result<(Duration, u8)> {
+    info!("Sending ICMP echo to {:?}", ip);
+
+    let (mut tx, _) = transport_channel(1024, Layer4(Ipv4(IpNextHeaderProtocols::Icmp)))?;
+    // Replies are read with their IP header for the TTL
+    let (_, mut rx) = transport_channel(4096, Layer3(IpNextHeaderProtocols::Icmp))?;
+    let identifier = std::process::id() as u16;
+    let seq = ICMP_SEQ.fetch_add(1, Ordering::Relaxed);
+
+    let mut request = build_echo_request(identifier, seq);
+    RATE_LIMITER.packet();
+    let now = Instant::now();
+    tx.send_to(
+        MutableEchoRequestPacket::new(&mut request).unwrap(),
+        IpAddr::V4(ip),
+    )?;
+
+    let mut replies = ipv4_packet_iter(&mut rx);
+    while let Some(remaining) = timeout.checked_sub(now.elapsed()) {
+        match replies.next_with_timeout(remaining)? {
+            Some((ip_pkt, IpAddr::V4(src))) if src == ip => {
+                let icmp_type = IcmpPacket::new(ip_pkt.payload()).map(|pkt| pkt.get_icmp_type());
+                if let (Some(IcmpTypes::EchoReply), Some(reply)) =
+                    (icmp_type, EchoReplyPacket::new(ip_pkt.payload()))
+                {
+                    if reply.get_identifier() == identifier && reply.get_sequence_number() == seq {
+                        return Ok((now.elapsed(), ip_pkt.get_ttl()));
+                    }
+                }
+            }
+            Some(_) => continue,
+            None => break,
+        }
+    }
+
+    Err(Error::new(ErrorKind::TimedOut, "No ICMP echo reply"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_echo_request() {
+        let request = build_echo_request(0x1234, 7);
+        let pkt = IcmpPacket::new(&request).unwrap();
+
+        assert_eq!(pkt.get_icmp_type(), IcmpTypes::EchoRequest);
+        // A correct checksum sums the whole message to zero
+        assert_eq!(pnet::util::checksum(&request, 0xffff), 0);
+        assert_eq!(&request[4..8], &[0x12, 0x34, 0, 7]);
+    }
+}
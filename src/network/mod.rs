@@ -1,27 +1,55 @@
+pub mod arp;
+pub mod banner;
+pub mod dhcp;
 pub mod dns;
+pub mod encoding;
+pub mod history;
 pub mod host;
+pub mod http;
+pub mod icmp_ping;
+pub mod os_fingerprint;
 pub mod ping_result;
 pub mod port_list;
+pub mod probe;
+pub mod rate_limit;
+pub mod service_probes;
+pub mod session;
+pub mod sniff;
+pub mod snmp;
+pub mod ssh;
 pub mod tcp_ping;
+pub mod timing;
+pub mod tls;
 pub mod udp_ping;
+pub mod udp_scan;
+pub mod wol;
 
 use crate::state::{
     host_modal_state::{HostModalAction, HostModalState},
     store::SharedAppStateStore,
 };
 
+use crate::config::AppConfig;
+use crate::network::history::ScanHistory;
 use crate::network::host::Host;
 use crate::state::actions::AppAction;
-use crate::GLOBAL_RUN;
+use crate::ui::notification::{Notification, NotificationLevel};
 
-use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
 
 use anyhow::{anyhow, Result};
+use log::warn;
+use pnet::datalink::{self, NetworkInterface};
 use pnet::ipnetwork::IpNetwork;
 use port_list::COMMON_PORTS;
+use probe::{LivenessEvidence, PingStrategy, ResponseKind};
+use rand::seq::SliceRandom;
+use rate_limit::RATE_LIMITER;
+use service_probes::Protocol;
+use session::ScanSession;
 use threadpool::ThreadPool;
+use timing::RttEstimator;
 
 use std::net::Ipv4Addr;
 
@@ -49,105 +77,524 @@ pub fn input_parse(input: &str) -> Result<Vec<Ipv4Addr>> {
     }
 }
 
-// TODO: profile performance here. On the one hand, dont want to have to
-// manage another global atomicbool for this thread, on the other hand,
-// locking the global state to check the run value and sleep if not set
-// doesn't seem like a great way to wait.
-// Note, this doens't seem to be working very well right now -- if start
-// a new search, takes a long time for the new query to start
-// May need to use an additional atomic bool since the search needs to stop before
-// other state is updated
-// Could use thread park https://doc.rust-lang.org/std/thread/fn.park.html
-// Or use an event loop with mpsc
-// This could be a good candidate for async
-
-/*
-2/15/21
-TODO
-Currently, when a new thread is launched here from a cancelled query,
-the new thread takes over the store lock and the old thread waits on line 80,
-then when the new thread terminates, the old one does too and eprints that it was
-interrupted. This works, but seems inefficient because the old  thread is just
-waiting on the store lock only to be cancelled. Revisit this and consider using an mpsc
-*/
+pub fn find_interface(iface_name: &str) -> Result<NetworkInterface> {
+    datalink::interfaces()
+        .into_iter()
+        .find(|iface| iface.name == iface_name)
+        .ok_or_else(|| anyhow!("Interface {} not found", iface_name))
+}
+
+// Scans run under a session: a new query cancels the old one's session, so its
+// workers stop picking up hosts and whatever they still report is dropped
 pub fn init_host_search(store: SharedAppStateStore) {
     thread::spawn(move || {
-        // Do we need to clone here?
+        let session = ScanSession::new();
         let mut lstore = store.lock().unwrap();
         let hosts = lstore.state.hosts.clone();
-        let network_nworkers = lstore.state.app_config.nworkers;
-        // Wait for search run to be started
-        lstore.dispatch(AppAction::SetHostSearchRun(true));
+        let port_query = lstore.state.port_query.clone();
+        let udp_port_query = lstore.state.udp_port_query.clone();
+        let iface_name = lstore.state.interface.clone();
+        let config = lstore.state.app_config.clone();
+        RATE_LIMITER.configure(config.max_rate, config.max_connections);
+        lstore.dispatch(AppAction::StartHostSearch(session.clone()));
         drop(lstore);
 
-        let pool = ThreadPool::new(network_nworkers);
-
-        for host in hosts {
-            let lstore = store.lock().unwrap();
-            if !GLOBAL_RUN.load(Ordering::Acquire) || !lstore.state.search_run {
-                break;
+        // Replies are picked up by the ARP monitor
+        if config.arp_sweep {
+            let targets: Vec<Ipv4Addr> = hosts.iter().map(|h| h.ip).collect();
+            let res = iface_name
+                .clone()
+                .ok_or_else(|| anyhow!("No network interface selected for the ARP sweep"))
+                .and_then(|name| find_interface(&name))
+                .and_then(|iface| arp::arp_sweep(&iface, &targets));
+            if let Err(err) = res {
+                warn!("ARP sweep failed: {}", err);
             }
-            drop(lstore);
+        }
 
-            let store_copy = store.clone();
-            thread::sleep(Duration::from_millis(50));
+        // Last scan's SSH keys to compare against
+        let prev_history = Arc::new(ScanHistory::load());
 
-            pool.execute(move || {
-                let localstore = store_copy.lock().unwrap();
-                let port_query = localstore.state.port_query.clone();
-                if !localstore.state.search_run {
-                    return;
+        let iface = iface_name.and_then(|name| find_interface(&name).ok());
+        let strategy = PingStrategy::from_config(&config, iface.as_ref());
+        let scan = HostScan {
+            store: store.clone(),
+            session: session.clone(),
+            config: Arc::new(config),
+            strategy: Arc::new(strategy),
+            prev_history,
+            port_query,
+            udp_port_query,
+        };
+
+        let pool = ThreadPool::new(scan.config.nworkers);
+
+        // Scan in random order, the table stays sorted
+        let mut hosts = hosts;
+        if scan.config.randomize_targets {
+            hosts.shuffle(&mut rand::thread_rng());
+        }
+
+        // One thread waits for all the UDP pings instead of a worker per host
+        let targets: Vec<Ipv4Addr> = hosts.iter().map(|h| h.ip).collect();
+        scan.strategy.sweep(&targets, &session);
+
+        if scan.config.interleave_ports {
+            interleaved_host_search(&scan, &pool, hosts);
+        } else {
+            for host in hosts {
+                if session.is_cancelled() {
+                    break;
                 }
-                drop(localstore);
+                let scan = scan.clone();
+                thread::sleep(scan.config.timing.host_delay);
 
-                let mut h = Host::host_ping(host.ip);
+                pool.execute(move || {
+                    if scan.session.is_cancelled() {
+                        return;
+                    }
+                    let config = &scan.config;
+                    let mut h = Host::host_ping(host.ip, config, &scan.strategy);
 
-                for port in port_query {
-                    match tcp_ping::tcp_scan_port(&h.ip, port) {
-                        Ok(_) => {
-                            h.tcp_ports.insert(port);
+                    // Timeouts follow the host's round trips, starting from the ping's
+                    let mut rtt = RttEstimator::new(h.ping_res);
+                    let mut port_query = scan.port_query.clone();
+                    if config.randomize_targets {
+                        port_query.shuffle(&mut rand::thread_rng());
+                    }
+                    for port in port_query {
+                        if scan.session.is_cancelled() {
+                            return;
                         }
-                        Err(_) => {}
+                        scan_host_tcp_port(&mut h, port, &mut rtt, config);
+                        thread::sleep(config.timing.port_delay);
+                    }
+
+                    scan_host_followups(&mut h, &scan);
+                    scan.report(AppAction::UpdateHost(h));
+                });
+            }
+        }
+        pool.join();
+
+        if session.is_cancelled() {
+            return;
+        }
+
+        // Remember MAC addresses for Wake-on-LAN and future scans
+        let mut history = ScanHistory::load();
+        history.record_hosts(store.lock().unwrap().state.hosts.iter());
+        if let Err(err) = history.save() {
+            warn!("Could not save scan history: {}", err);
+        }
+
+        scan.report(AppAction::QueryComplete);
+    });
+}
+
+// What every worker of a host search shares
+#[derive(Clone)]
+struct HostScan {
+    store: SharedAppStateStore,
+    session: ScanSession,
+    config: Arc<AppConfig>,
+    strategy: Arc<PingStrategy>,
+    prev_history: Arc<ScanHistory>,
+    port_query: Vec<u16>,
+    udp_port_query: Vec<u16>,
+}
+
+impl HostScan {
+    fn report(&self, action: AppAction) {
+        self.store
+            .lock()
+            .unwrap()
+            .dispatch(self.session.result(action));
+    }
+}
+
+// Scan one TCP port of a host, inspecting TLS and web services on it if it's open
+fn scan_host_tcp_port(h: &mut Host, port: u16, rtt: &mut RttEstimator, config: &AppConfig) {
+    let timeout = config.timing.adapted(rtt).max_rtt_timeout;
+    if config.banner_grab {
+        if let Ok((dur, banner)) = tcp_ping::tcp_scan_port_banner(&h.ip, port, timeout) {
+            rtt.update(dur);
+            h.tcp_ports.insert(port);
+            if let Some(banner) = banner {
+                h.banners.insert(port, banner);
+            }
+        }
+    } else if let Ok(dur) = tcp_ping::tcp_scan_port(&h.ip, port, timeout) {
+        rtt.update(dur);
+        h.tcp_ports.insert(port);
+    }
+
+    if h.tcp_ports.contains(&port) && tls::is_tls_port(port) {
+        if let Ok(tls_info) = tls::inspect_tls(&h.ip, port) {
+            h.tls_certs.insert(port, tls_info);
+        }
+    }
+
+    if let (true, Some(https)) = (
+        config.http_fingerprint && h.tcp_ports.contains(&port),
+        http::web_port(port),
+    ) {
+        if let Ok(http_info) = http::http_fingerprint(&h.ip, port, https) {
+            h.http.insert(port, http_info);
+        }
+    }
+}
+
+// What's left once the TCP ports are scanned: service identification, UDP ports
+// and SSH host keys
+fn scan_host_followups(h: &mut Host, scan: &HostScan) {
+    let config = &scan.config;
+    if let Some(probes) = &config.service_probes {
+        let mut open_ports: Vec<u16> = h.tcp_ports.iter().copied().collect();
+        open_ports.sort_unstable();
+        for port in open_ports {
+            if let Some(service) = probes.identify(&h.ip, Protocol::Tcp, port) {
+                h.services.insert((Protocol::Tcp, port), service);
+            }
+        }
+    }
+
+    // Closed UDP ports are the common case, only keep the rest
+    if h.is_alive() {
+        // Service replies take as long as the service does, whatever the round trip
+        let timeout = config.timing.ping_timeout;
+        for port in scan.udp_port_query.iter().copied() {
+            match udp_scan::udp_scan_port(&h.ip, port, timeout) {
+                Ok(udp_scan::UdpPortState::Closed) => {}
+                Ok(port_state) => {
+                    h.udp_ports.insert(port, port_state);
+                    if let Some(service) = config
+                        .service_probes
+                        .as_ref()
+                        .and_then(|probes| probes.identify(&h.ip, Protocol::Udp, port))
+                    {
+                        h.services.insert((Protocol::Udp, port), service);
                     }
                 }
+                Err(err) => warn!("UDP scan of {}:{} failed: {}", h.ip, port, err),
+            }
+        }
+    }
+
+    // Port 22 found open by the port scan, or answering the TCP ping
+    let ssh_open = h.tcp_ports.contains(&ssh::SSH_PORT)
+        || matches!(
+            &h.liveness,
+            Some(LivenessEvidence {
+                port: Some(ssh::SSH_PORT),
+                reason: ResponseKind::TcpSynAck,
+                ..
+            })
+        );
+    if config.ssh_host_keys && ssh_open {
+        if let Ok(mut ssh_info) = ssh::ssh_probe(&h.ip, ssh::SSH_PORT) {
+            ssh_info.key_changed = scan.prev_history.ssh_key_changed(&h.ip, &ssh_info);
+            h.ssh = Some(ssh_info);
+        }
+    }
+}
+
+// Pings every host first, then probes each port across all the hosts before
+// moving on to the next, so no host sees a burst of probes. A host's own probes
+// still never overlap, they take turns on its lock.
+fn interleaved_host_search(scan: &HostScan, pool: &ThreadPool, hosts: Vec<Host>) {
+    let config = &scan.config;
+    let scanned: Vec<Arc<Mutex<(Host, RttEstimator)>>> = hosts
+        .iter()
+        .map(|host| Arc::new(Mutex::new((host.clone(), RttEstimator::default()))))
+        .collect();
 
-                store_copy
-                    .lock()
-                    .unwrap()
-                    .dispatch(AppAction::UpdateHost(h));
+    for target in &scanned {
+        if scan.session.is_cancelled() {
+            return;
+        }
+        let (scan, target) = (scan.clone(), target.clone());
+        thread::sleep(config.timing.host_delay);
+
+        pool.execute(move || {
+            let mut target = target.lock().unwrap();
+            let mut h = Host::host_ping(target.0.ip, &scan.config, &scan.strategy);
+            target.1 = RttEstimator::new(h.ping_res);
+            // Ports are still to come, show the ping result without counting the host as done
+            h.ping_done = false;
+            target.0 = h.clone();
+            scan.report(AppAction::UpdateHost(h));
+        });
+    }
+    pool.join();
+
+    let mut port_query = scan.port_query.clone();
+    if config.randomize_targets {
+        port_query.shuffle(&mut rand::thread_rng());
+    }
+    for port in port_query {
+        for target in &scanned {
+            if scan.session.is_cancelled() {
+                return;
+            }
+            let (target, config) = (target.clone(), config.clone());
+            thread::sleep(config.timing.port_delay);
+
+            pool.execute(move || {
+                let (h, rtt) = &mut *target.lock().unwrap();
+                scan_host_tcp_port(h, port, rtt, &config);
             });
         }
-        pool.join();
-        // Need to check if the query was interrupted or not
-        store.lock().unwrap().dispatch(AppAction::QueryComplete);
+    }
+    pool.join();
+
+    for target in scanned {
+        if scan.session.is_cancelled() {
+            return;
+        }
+        let scan = scan.clone();
+
+        pool.execute(move || {
+            let (mut h, _) = target.lock().unwrap().clone();
+            scan_host_followups(&mut h, &scan);
+            h.ping_done = true;
+            scan.report(AppAction::UpdateHost(h));
+        });
+    }
+}
+
+// Passively listen for ARP traffic for the lifetime of the app, recording every
+// IP/MAC pairing so that address conflicts can be flagged
+pub fn start_arp_monitor(store: SharedAppStateStore) {
+    thread::spawn(move || {
+        let iface_name = store.lock().unwrap().state.interface.clone();
+        let store_copy = store.clone();
+
+        let res = iface_name
+            .ok_or_else(|| anyhow!("No network interface selected for ARP monitoring"))
+            .and_then(|name| find_interface(&name))
+            .and_then(|iface| {
+                arp::arp_listen(&iface, |ip, mac| {
+                    store_copy
+                        .lock()
+                        .unwrap()
+                        .dispatch(AppAction::ArpSeen(ip, mac))
+                })
+            });
+
+        if let Err(err) = res {
+            store
+                .lock()
+                .unwrap()
+                .dispatch(AppAction::SetNotification(Some(Notification::new(
+                    "ARP monitor",
+                    &format!("ARP monitoring failed: {}", err),
+                    NotificationLevel::Warn,
+                ))));
+        }
+    });
+}
+
+pub fn start_os_sniffer(store: SharedAppStateStore) {
+    thread::spawn(move || {
+        let iface_name = store.lock().unwrap().state.interface.clone();
+        let store_copy = store.clone();
+
+        let res = iface_name
+            .ok_or_else(|| anyhow!("No network interface selected for OS fingerprinting"))
+            .and_then(|name| find_interface(&name))
+            .and_then(|iface| {
+                sniff::listen_frames(&iface, |frame| {
+                    if let Some((ip, traits)) = os_fingerprint::parse_frame(frame) {
+                        store_copy
+                            .lock()
+                            .unwrap()
+                            .dispatch(AppAction::OsTraitsSeen(ip, traits))
+                    }
+                })
+            });
+
+        if let Err(err) = res {
+            store
+                .lock()
+                .unwrap()
+                .dispatch(AppAction::SetNotification(Some(Notification::new(
+                    "OS fingerprinting",
+                    &format!("Packet capture failed: {}", err),
+                    NotificationLevel::Warn,
+                ))));
+        }
+    });
+}
+
+pub fn dispatch_dhcp_probe(store: SharedAppStateStore) {
+    thread::spawn(move || {
+        let iface_name = store.lock().unwrap().state.interface.clone();
+
+        let res = iface_name
+            .ok_or_else(|| anyhow!("No network interface selected for the DHCP probe"))
+            .and_then(|name| find_interface(&name))
+            .and_then(|iface| dhcp::dhcp_discover(&iface, dhcp::DHCP_OFFER_WINDOW));
+
+        let mut lstore = store.lock().unwrap();
+        match res {
+            Ok(offers) => lstore.dispatch(AppAction::SetDhcpOffers(offers)),
+            Err(err) => lstore.dispatch(AppAction::SetNotification(Some(Notification::new(
+                "DHCP probe",
+                &format!("DHCP probe failed: {}", err),
+                NotificationLevel::Warn,
+            )))),
+        }
+    });
+}
+
+pub fn dispatch_wake_on_lan(store: SharedAppStateStore) {
+    thread::spawn(move || {
+        let lstore = store.lock().unwrap();
+        let host = match lstore.state.modal_state.as_ref() {
+            Some(modal_state) => modal_state.selected_host.clone(),
+            None => return,
+        };
+        let iface = lstore.state.interface.clone();
+        drop(lstore);
+
+        let notif = match host.mac.or_else(|| ScanHistory::load().mac_for(&host.ip)) {
+            Some(mac) => match wol::wake(mac, iface.as_deref()) {
+                Ok(_) => {
+                    Notification::info("Wake-on-LAN", &format!("Magic packet sent to {}", mac))
+                }
+                Err(err) => {
+                    Notification::new("Wake-on-LAN", &err.to_string(), NotificationLevel::Warn)
+                }
+            },
+            None => Notification::new(
+                "Wake-on-LAN",
+                &format!("No known MAC address for {}", host.ip),
+                NotificationLevel::Warn,
+            ),
+        };
+
+        store
+            .lock()
+            .unwrap()
+            .dispatch(AppAction::SetNotification(Some(notif)));
     });
 }
 
+// Scan one port of the modal's host, grabbing its banner if enabled
+fn modal_port_scan(
+    store: &SharedAppStateStore,
+    session: &ScanSession,
+    ip: &Ipv4Addr,
+    port: u16,
+    config: &AppConfig,
+) {
+    let timeout = config.timing.max_rtt_timeout;
+    let res = if config.banner_grab {
+        tcp_ping::tcp_scan_port_banner(ip, port, timeout)
+    } else {
+        tcp_ping::tcp_scan_port(ip, port, timeout).map(|dur| (dur, None))
+    };
+
+    let mut lstore = store.lock().unwrap();
+    match res {
+        Ok((dur, banner)) => {
+            lstore.dispatch(session.result(AppAction::SetModalAction(
+                HostModalAction::SetPortScanResult((port, Some(Ok(dur)))),
+            )));
+            if let Some(banner) = banner {
+                lstore.dispatch(session.result(AppAction::SetModalAction(
+                    HostModalAction::SetPortBanner(port, banner),
+                )));
+            }
+            drop(lstore);
+
+            if tls::is_tls_port(port) {
+                match tls::inspect_tls(ip, port) {
+                    Ok(tls_info) => {
+                        store
+                            .lock()
+                            .unwrap()
+                            .dispatch(session.result(AppAction::SetModalAction(
+                                HostModalAction::SetTlsCert(port, tls_info),
+                            )))
+                    }
+                    Err(err) => warn!("TLS inspection of {}:{} failed: {}", ip, port, err),
+                }
+            }
+
+            if let Some(probes) = &config.service_probes {
+                if let Some(service) = probes.identify(ip, Protocol::Tcp, port) {
+                    store
+                        .lock()
+                        .unwrap()
+                        .dispatch(session.result(AppAction::SetModalAction(
+                            HostModalAction::SetService(Protocol::Tcp, port, service),
+                        )));
+                }
+            }
+
+            if config.ssh_host_keys && port == ssh::SSH_PORT {
+                match ssh::ssh_probe(ip, port) {
+                    Ok(mut ssh_info) => {
+                        ssh_info.key_changed = ScanHistory::load().ssh_key_changed(ip, &ssh_info);
+                        store
+                            .lock()
+                            .unwrap()
+                            .dispatch(session.result(AppAction::SetModalAction(
+                                HostModalAction::SetSshInfo(ssh_info),
+                            )))
+                    }
+                    Err(err) => warn!("SSH probe of {}:{} failed: {}", ip, port, err),
+                }
+            }
+
+            if let (true, Some(https)) = (config.http_fingerprint, http::web_port(port)) {
+                match http::http_fingerprint(ip, port, https) {
+                    Ok(http_info) => {
+                        store
+                            .lock()
+                            .unwrap()
+                            .dispatch(session.result(AppAction::SetModalAction(
+                                HostModalAction::SetHttpInfo(port, http_info),
+                            )))
+                    }
+                    Err(err) => warn!("HTTP fingerprint of {}:{} failed: {}", ip, port, err),
+                }
+            }
+        }
+        Err(_) => lstore.dispatch(session.result(AppAction::SetModalAction(
+            HostModalAction::SetPortScanResult((port, Some(Err(())))),
+        ))),
+    }
+}
+
 /// TODO: combine with the above for a single shared resouce access func
 /// TODO: ensure this isn't dispatched more than once
 pub fn dispatch_port_scan(store: SharedAppStateStore) {
     thread::spawn(move || {
         let lstore = store.lock().unwrap();
-        let modal_state: HostModalState = lstore.state.modal_state.clone().unwrap();
+        let modal_state: HostModalState = match lstore.state.modal_state.clone() {
+            Some(modal_state) => modal_state,
+            None => return,
+        };
+        let config = lstore.state.app_config.clone();
         drop(lstore);
 
         for (port, _) in modal_state.ports {
-            let port_run = store.lock().unwrap().state.modal_state.is_none();
-
-            if !GLOBAL_RUN.load(Ordering::Acquire) || port_run {
+            if modal_state.session.is_cancelled() {
                 break;
             }
 
-            match tcp_ping::tcp_scan_port(&modal_state.selected_host.ip, port) {
-                Ok(dur) => store.lock().unwrap().dispatch(AppAction::SetModalAction(
-                    HostModalAction::SetPortScanResult((port, Some(Ok(dur)))),
-                )),
-                Err(_) => store.lock().unwrap().dispatch(AppAction::SetModalAction(
-                    HostModalAction::SetPortScanResult((port, Some(Err(())))),
-                )),
-            }
+            modal_port_scan(
+                &store,
+                &modal_state.session,
+                &modal_state.selected_host.ip,
+                port,
+                &config,
+            );
 
-            thread::sleep(Duration::from_millis(10));
+            thread::sleep(config.timing.port_delay);
         }
     });
 }
@@ -155,28 +602,82 @@ pub fn dispatch_port_scan(store: SharedAppStateStore) {
 pub fn dispatch_common_port_scan(store: SharedAppStateStore) {
     thread::spawn(move || {
         let mut lstore = store.lock().unwrap();
+        // The modal may have closed before this thread got the lock
+        if lstore.state.modal_state.is_none() {
+            return;
+        }
         lstore.dispatch(AppAction::SetModalAction(
             HostModalAction::SetCommonPortsForScanning,
         ));
         let modal_state: HostModalState = lstore.state.modal_state.clone().unwrap();
+        let config = lstore.state.app_config.clone();
         // don't lock up other threads
         drop(lstore);
 
-        for port in COMMON_PORTS.iter() {
-            let port_run = store.lock().unwrap().state.modal_state.is_none();
+        let mut ports = COMMON_PORTS.to_vec();
+        if config.randomize_targets {
+            ports.shuffle(&mut rand::thread_rng());
+        }
+        for port in ports {
+            if modal_state.session.is_cancelled() {
+                break;
+            }
+            modal_port_scan(
+                &store,
+                &modal_state.session,
+                &modal_state.selected_host.ip,
+                port,
+                &config,
+            );
+            thread::sleep(config.timing.port_delay);
+        }
+    });
+}
+
+pub fn dispatch_udp_port_scan(store: SharedAppStateStore) {
+    thread::spawn(move || {
+        let lstore = store.lock().unwrap();
+        let modal_state: HostModalState = match lstore.state.modal_state.clone() {
+            Some(modal_state) => modal_state,
+            None => return,
+        };
+        let probes = lstore.state.app_config.service_probes.clone();
+        let timing = lstore.state.app_config.timing.clone();
+        drop(lstore);
+        let ip = modal_state.selected_host.ip;
+        let session = modal_state.session.clone();
 
-            if !GLOBAL_RUN.load(Ordering::Acquire) || port_run {
+        for (port, _) in modal_state.udp_ports {
+            if modal_state.session.is_cancelled() {
                 break;
             }
-            match tcp_ping::tcp_scan_port(&modal_state.selected_host.ip, *port) {
-                Ok(dur) => store.lock().unwrap().dispatch(AppAction::SetModalAction(
-                    HostModalAction::SetPortScanResult((*port, Some(Ok(dur)))),
-                )),
-                Err(_) => store.lock().unwrap().dispatch(AppAction::SetModalAction(
-                    HostModalAction::SetPortScanResult((*port, Some(Err(())))),
-                )),
+
+            match udp_scan::udp_scan_port(&ip, port, timing.ping_timeout) {
+                Ok(port_state) => {
+                    store
+                        .lock()
+                        .unwrap()
+                        .dispatch(session.result(AppAction::SetModalAction(
+                            HostModalAction::SetUdpPortScanResult(port, port_state),
+                        )));
+
+                    if let (true, Some(probes)) =
+                        (port_state != udp_scan::UdpPortState::Closed, &probes)
+                    {
+                        if let Some(service) = probes.identify(&ip, Protocol::Udp, port) {
+                            store.lock().unwrap().dispatch(session.result(
+                                AppAction::SetModalAction(HostModalAction::SetService(
+                                    Protocol::Udp,
+                                    port,
+                                    service,
+                                )),
+                            ));
+                        }
+                    }
+                }
+                Err(err) => warn!("UDP scan of {}:{} failed: {}", ip, port, err),
             }
-            thread::sleep(Duration::from_millis(10));
+            thread::sleep(timing.port_delay);
         }
     });
 }
@@ -0,0 +1,332 @@
+// Passive OS fingerprinting
+// Guesses the OS family from replies we get anyway (ICMP replies to UDP pings
+// and the SYN-ACKs answering TCP pings and port scans): the initial TTL,
+// TCP window, MSS and the order of the TCP options, roughly the way p0f does.
+
+use super::probe::{LivenessEvidence, ResponseKind};
+
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
+use pnet::packet::icmp::{IcmpPacket, IcmpTypes};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::{TcpFlags, TcpOptionNumbers, TcpPacket};
+use pnet::packet::udp::UdpPacket;
+use pnet::packet::Packet;
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct PacketTraits {
+    pub ttl: u8,
+    // TCP fields, only set for SYN-ACKs
+    pub window: Option<u16>,
+    pub mss: Option<u16>,
+    // Option kinds in order, p0f style: M(SS), N(OP), W(indow scale), S(ACK ok), T(imestamp), E(OL)
+    pub options: Option<String>,
+    // What the packet was, and the port it came from (SYN-ACK) or was about
+    // (ICMP port unreachable)
+    pub reply: Option<ResponseKind>,
+    pub port: Option<u16>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OsFamily {
+    Linux,
+    Windows,
+    MacOs,
+    Bsd,
+    UnixLike,
+    Embedded,
+    NetworkGear,
+}
+
+impl fmt::Display for OsFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OsFamily::Linux => write!(f, "Linux"),
+            OsFamily::Windows => write!(f, "Windows"),
+            OsFamily::MacOs => write!(f, "macOS/iOS"),
+            OsFamily::Bsd => write!(f, "BSD"),
+            OsFamily::UnixLike => write!(f, "Linux/Unix"),
+            OsFamily::Embedded => write!(f, "Embedded"),
+            OsFamily::NetworkGear => write!(f, "Network gear"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct OsGuess {
+    pub family: OsFamily,
+    // Percent
+    pub confidence: u8,
+    pub traits: PacketTraits,
+}
+
+impl fmt::Display for OsGuess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}%)", self.family, self.confidence)
+    }
+}
+
+impl PacketTraits {
+    // Compact description of what the guess is based on
+    pub fn evidence(&self) -> String {
+        let mut evidence = format!("TTL {}", self.ttl);
+        if let Some(window) = self.window {
+            evidence += &format!(", win {}", window);
+        }
+        if let Some(mss) = self.mss {
+            evidence += &format!(", MSS {}", mss);
+        }
+        if let Some(options) = &self.options {
+            evidence += &format!(", opts {}", options);
+        }
+        evidence
+    }
+
+    // Whether this is the reply that proved the host alive
+    pub fn proves(&self, liveness: &LivenessEvidence) -> bool {
+        self.reply == Some(liveness.reason) && self.port == liveness.port
+    }
+}
+
+// Destination port of the UDP datagram an ICMP error quotes
+fn quoted_udp_port(icmp_pkt: &IcmpPacket) -> Option<u16> {
+    // The payload starts with the 4 unused bytes of the header
+    let quoted = Ipv4Packet::new(icmp_pkt.payload().get(4..)?)?;
+    if quoted.get_next_level_protocol() != IpNextHeaderProtocols::Udp {
+        return None;
+    }
+    let header_len = quoted.get_header_length() as usize * 4;
+    let udp_pkt = UdpPacket::new(quoted.packet().get(header_len..)?)?;
+    Some(udp_pkt.get_destination())
+}
+
+// TTLs start at a handful of well known values and only go down
+pub fn initial_ttl(ttl: u8) -> u8 {
+    match ttl {
+        0..=32 => 32,
+        33..=64 => 64,
+        65..=128 => 128,
+        _ => 255,
+    }
+}
+
+pub fn classify(traits: &PacketTraits) -> Option<OsGuess> {
+    let options = traits.options.as_deref();
+    let small_window = matches!(traits.window, Some(win) if win < 8192);
+    let guess = |family, confidence| {
+        Some(OsGuess {
+            family,
+            confidence,
+            traits: traits.clone(),
+        })
+    };
+
+    match (initial_ttl(traits.ttl), options) {
+        // Windows SYN-ACKs: mss,nop,ws,nop,nop,sok (no timestamps)
+        (128, Some("M,N,W,N,N,S")) | (128, Some("M,N,W,S,T")) => guess(OsFamily::Windows, 90),
+        (128, Some(_)) => guess(OsFamily::Windows, 75),
+        (128, None) => guess(OsFamily::Windows, 60),
+
+        // Apple stacks end with two EOLs after the timestamp
+        (64, Some(opts)) if opts.starts_with("M,N,W,N,N,T") => guess(OsFamily::MacOs, 85),
+        (64, Some("M,S,T,N,W")) => guess(OsFamily::Linux, 90),
+        (64, Some("M,N,N,S,N,W")) | (64, Some("M,S,N,W")) => guess(OsFamily::Linux, 75),
+        (64, Some("M,N,W,S,T")) => guess(OsFamily::Bsd, 70),
+        // Minimal stacks (lwIP, RTOS) send MSS alone and small windows
+        (64, Some("M")) | (64, Some("")) => guess(OsFamily::Embedded, 70),
+        (64, Some(_)) if small_window => guess(OsFamily::Embedded, 55),
+        (64, Some(_)) => guess(OsFamily::UnixLike, 50),
+        (64, None) => guess(OsFamily::UnixLike, 40),
+
+        (255, Some("M")) | (255, Some("")) => guess(OsFamily::NetworkGear, 75),
+        (255, _) => guess(OsFamily::NetworkGear, 60),
+
+        (32, _) => guess(OsFamily::Embedded, 40),
+        _ => None,
+    }
+}
+
+fn option_layout(tcp_pkt: &TcpPacket) -> (String, Option<u16>) {
+    let mut mss = None;
+    let kinds: Vec<&str> = tcp_pkt
+        .get_options_iter()
+        .map(|opt| match opt.get_number() {
+            TcpOptionNumbers::MSS => {
+                let payload = opt.payload();
+                if payload.len() >= 2 {
+                    mss = Some(u16::from_be_bytes([payload[0], payload[1]]));
+                }
+                "M"
+            }
+            TcpOptionNumbers::NOP => "N",
+            TcpOptionNumbers::WSCALE => "W",
+            TcpOptionNumbers::SACK_PERMITTED => "S",
+            TcpOptionNumbers::TIMESTAMPS => "T",
+            TcpOptionNumbers::EOL => "E",
+            _ => "?",
+        })
+        .collect();
+    (kinds.join(","), mss)
+}
+
+// Source address and traits of SYN-ACKs and ICMP replies
+pub fn parse_frame(frame: &[u8]) -> Option<(Ipv4Addr, PacketTraits)> {
+    let eth_pkt = EthernetPacket::new(frame)?;
+    if eth_pkt.get_ethertype() != EtherTypes::Ipv4 {
+        return None;
+    }
+
+    let ip_pkt = Ipv4Packet::new(eth_pkt.payload())?;
+    let mut traits = PacketTraits {
+        ttl: ip_pkt.get_ttl(),
+        ..Default::default()
+    };
+
+    match ip_pkt.get_next_level_protocol() {
+        IpNextHeaderProtocols::Tcp => {
+            let tcp_pkt = TcpPacket::new(ip_pkt.payload())?;
+            let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+            if tcp_pkt.get_flags() & syn_ack != syn_ack {
+                return None;
+            }
+
+            let (options, mss) = option_layout(&tcp_pkt);
+            traits.window = Some(tcp_pkt.get_window());
+            traits.mss = mss;
+            traits.options = Some(options);
+            traits.reply = Some(ResponseKind::TcpSynAck);
+            traits.port = Some(tcp_pkt.get_source());
+        }
+        IpNextHeaderProtocols::Icmp => {
+            let icmp_pkt = IcmpPacket::new(ip_pkt.payload())?;
+            match icmp_pkt.get_icmp_type() {
+                IcmpTypes::EchoReply => traits.reply = Some(ResponseKind::IcmpEchoReply),
+                IcmpTypes::DestinationUnreachable => {
+                    // Code 3 is port unreachable
+                    if icmp_pkt.get_icmp_code().0 == 3 {
+                        traits.reply = Some(ResponseKind::IcmpPortUnreachable);
+                        traits.port = quoted_udp_port(&icmp_pkt);
+                    }
+                }
+                _ => return None,
+            }
+        }
+        _ => return None,
+    }
+
+    Some((ip_pkt.get_source(), traits))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pnet::packet::ethernet::MutableEthernetPacket;
+    use pnet::packet::ipv4::MutableIpv4Packet;
+    use pnet::packet::tcp::MutableTcpPacket;
+
+    fn syn_ack_frame(ttl: u8, window: u16, options: &[u8]) -> Vec<u8> {
+        let tcp_len = 20 + options.len();
+        let mut frame = vec![0u8; 14 + 20 + tcp_len];
+
+        MutableEthernetPacket::new(&mut frame)
+            .unwrap()
+            .set_ethertype(EtherTypes::Ipv4);
+        {
+            let mut ip_pkt = MutableIpv4Packet::new(&mut frame[14..]).unwrap();
+            ip_pkt.set_version(4);
+            ip_pkt.set_header_length(5);
+            ip_pkt.set_total_length((20 + tcp_len) as u16);
+            ip_pkt.set_ttl(ttl);
+            ip_pkt.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
+            ip_pkt.set_source(Ipv4Addr::new(192, 168, 1, 20));
+        }
+        frame[54..].copy_from_slice(options);
+        {
+            let mut tcp_pkt = MutableTcpPacket::new(&mut frame[34..]).unwrap();
+            tcp_pkt.set_data_offset((tcp_len / 4) as u8);
+            tcp_pkt.set_source(443);
+            tcp_pkt.set_flags(TcpFlags::SYN | TcpFlags::ACK);
+            tcp_pkt.set_window(window);
+        }
+        frame
+    }
+
+    #[test]
+    fn test_parse_linux_syn_ack() {
+        // mss 1460, sackOK, timestamps, nop, wscale 7
+        let options = [
+            2, 4, 0x05, 0xb4, 4, 2, 8, 10, 0, 0, 0, 1, 0, 0, 0, 0, 1, 3, 3, 7,
+        ];
+        let frame = syn_ack_frame(63, 65160, &options);
+        let (ip, traits) = parse_frame(&frame).unwrap();
+
+        assert_eq!(ip, Ipv4Addr::new(192, 168, 1, 20));
+        assert_eq!(traits.mss, Some(1460));
+        assert_eq!(traits.options.as_deref(), Some("M,S,T,N,W"));
+        assert_eq!(traits.reply, Some(ResponseKind::TcpSynAck));
+        assert_eq!(traits.port, Some(443));
+
+        let guess = classify(&traits).unwrap();
+        assert_eq!(guess.family, OsFamily::Linux);
+        assert_eq!(guess.to_string(), "Linux (90%)");
+    }
+
+    #[test]
+    fn test_parse_port_unreachable() {
+        // ICMP header, then the quoted IP header and UDP header to port 9989
+        let mut icmp = vec![3, 3, 0, 0, 0, 0, 0, 0, 0x45];
+        icmp.resize(8 + 20, 0);
+        icmp[8 + 9] = 17;
+        icmp.extend_from_slice(&[0x9c, 0x40, 0x27, 0x05, 0, 8, 0, 0]);
+
+        let mut frame = vec![0u8; 14 + 20];
+        MutableEthernetPacket::new(&mut frame)
+            .unwrap()
+            .set_ethertype(EtherTypes::Ipv4);
+        frame.extend_from_slice(&icmp);
+        {
+            let mut ip_pkt = MutableIpv4Packet::new(&mut frame[14..]).unwrap();
+            ip_pkt.set_version(4);
+            ip_pkt.set_header_length(5);
+            ip_pkt.set_total_length((20 + icmp.len()) as u16);
+            ip_pkt.set_ttl(64);
+            ip_pkt.set_next_level_protocol(IpNextHeaderProtocols::Icmp);
+        }
+
+        let (_, traits) = parse_frame(&frame).unwrap();
+        assert_eq!(traits.reply, Some(ResponseKind::IcmpPortUnreachable));
+        assert_eq!(traits.port, Some(9989));
+    }
+
+    #[test]
+    fn test_classify() {
+        let windows = PacketTraits {
+            ttl: 127,
+            window: Some(64240),
+            mss: Some(1460),
+            options: Some("M,N,W,N,N,S".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(classify(&windows).unwrap().family, OsFamily::Windows);
+
+        let mac = PacketTraits {
+            ttl: 64,
+            window: Some(65535),
+            mss: Some(1460),
+            options: Some("M,N,W,N,N,T,S,E,E".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(classify(&mac).unwrap().family, OsFamily::MacOs);
+
+        let icmp_only = PacketTraits {
+            ttl: 250,
+            ..Default::default()
+        };
+        let guess = classify(&icmp_only).unwrap();
+        assert_eq!(guess.family, OsFamily::NetworkGear);
+        assert_eq!(guess.traits.evidence(), "TTL 250");
+    }
+}
@@ -0,0 +1,614 @@
+// Liveness probes
+// Each discovery method implements `Probe`, a `PingStrategy` runs the ones
+// picked in the config one after the other or all at once, and decides whether
+// one answer is enough or every probe has to answer.
+
+use super::arp::arp_ping;
+use super::dns::{
+    decoders::MdnsAnswer, reverse_dns_lookup, transactors::UdpTransactorType::MulticastTransact,
+    HostnameLookupUdpPort,
+};
+use super::host::PingType;
+use super::icmp_ping::icmp_ping;
+use super::session::ScanSession;
+use super::tcp_ping::tcp_ping_ports;
+use super::timing::Timing;
+use super::udp_ping::{udp_ping, udp_ping_sweep, UDP_PING_PORT};
+use crate::config::AppConfig;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use pnet::datalink::NetworkInterface;
+use pnet::util::MacAddr;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PingMode {
+    // In config order, stopping as soon as the outcome is known
+    Sequential,
+    // All at once, the fastest answer is kept
+    Parallel,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PingPolicy {
+    // One answer is enough
+    Any,
+    // Every probe has to answer
+    All,
+}
+
+impl FromStr for PingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sequential" => Ok(PingMode::Sequential),
+            "parallel" => Ok(PingMode::Parallel),
+            _ => Err(anyhow!(
+                "Unknown ping mode {}, use sequential or parallel",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PingMode::Sequential => write!(f, "sequential"),
+            PingMode::Parallel => write!(f, "parallel"),
+        }
+    }
+}
+
+impl fmt::Display for PingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PingPolicy::Any => write!(f, "any"),
+            PingPolicy::All => write!(f, "all"),
+        }
+    }
+}
+
+impl FromStr for PingPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "any" => Ok(PingPolicy::Any),
+            "all" => Ok(PingPolicy::All),
+            _ => Err(anyhow!("Unknown ping policy {}, use any or all", s)),
+        }
+    }
+}
+
+// Comma separated probe names, e.g. "icmp,tcp,udp"
+pub fn parse_ping_methods(methods: &str) -> Result<Vec<PingType>> {
+    let methods = methods
+        .split(',')
+        .filter(|m| !m.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<PingType>>>()?;
+
+    match methods.is_empty() {
+        true => Err(anyhow!("At least one ping method is needed")),
+        false => Ok(methods),
+    }
+}
+
+// What exactly came back, like nmap's --reason
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ResponseKind {
+    UdpReply,
+    IcmpPortUnreachable,
+    TcpSynAck,
+    TcpRst,
+    IcmpEchoReply,
+    ArpReply,
+    MdnsAnswer,
+}
+
+impl fmt::Display for ResponseKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResponseKind::UdpReply => write!(f, "UDP reply"),
+            ResponseKind::IcmpPortUnreachable => write!(f, "ICMP port unreachable"),
+            ResponseKind::TcpSynAck => write!(f, "TCP SYN/ACK"),
+            ResponseKind::TcpRst => write!(f, "TCP RST"),
+            ResponseKind::IcmpEchoReply => write!(f, "ICMP echo reply"),
+            ResponseKind::ArpReply => write!(f, "ARP reply"),
+            ResponseKind::MdnsAnswer => write!(f, "mDNS answer"),
+        }
+    }
+}
+
+// Why a host was considered alive
+#[derive(Clone, PartialEq, Debug)]
+pub struct LivenessEvidence {
+    pub ping_type: PingType,
+    pub port: Option<u16>,
+    pub reason: ResponseKind,
+    // Of the reply, if we got to see it
+    pub ttl: Option<u8>,
+}
+
+impl fmt::Display for LivenessEvidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(port) = self.port {
+            let proto = match self.ping_type {
+                PingType::UDP => "udp",
+                _ => "tcp",
+            };
+            write!(f, "port {}/{}: ", port, proto)?;
+        }
+        write!(f, "{}", self.reason)?;
+        if let Some(ttl) = self.ttl {
+            write!(f, ", TTL {}", ttl)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProbeReply {
+    pub ping_type: PingType,
+    pub reason: ResponseKind,
+    pub ttl: Option<u8>,
+    pub rtt: Duration,
+    // Port that answered, for port based probes
+    pub port: Option<u16>,
+    // Whether that port accepted the connection
+    pub port_open: bool,
+    pub mac: Option<MacAddr>,
+}
+
+impl ProbeReply {
+    pub fn new(ping_type: PingType, reason: ResponseKind, rtt: Duration) -> ProbeReply {
+        ProbeReply {
+            ping_type,
+            reason,
+            ttl: None,
+            rtt,
+            port: None,
+            port_open: false,
+            mac: None,
+        }
+    }
+
+    pub fn evidence(&self) -> LivenessEvidence {
+        LivenessEvidence {
+            ping_type: self.ping_type,
+            port: self.port,
+            reason: self.reason,
+            ttl: self.ttl,
+        }
+    }
+}
+
+pub trait Probe: Send + Sync {
+    fn ping_type(&self) -> PingType;
+    // Ok if the host answered
+    fn probe(&self, ip: Ipv4Addr) -> Result<ProbeReply>;
+    // Probes that can ping many hosts at once do it here, before the scan
+    fn sweep(&self, _ips: &[Ipv4Addr], _session: &ScanSession) {}
+}
+
+pub struct UdpProbe {
+    pub timing: Timing,
+    // Sweep results not yet handed to a probe
+    swept: Mutex<HashMap<Ipv4Addr, io::Result<(Duration, ResponseKind)>>>,
+}
+
+impl UdpProbe {
+    pub fn new(timing: Timing) -> UdpProbe {
+        UdpProbe {
+            timing,
+            swept: Mutex::default(),
+        }
+    }
+}
+
+impl Probe for UdpProbe {
+    fn ping_type(&self) -> PingType {
+        PingType::UDP
+    }
+
+    fn probe(&self, ip: Ipv4Addr) -> Result<ProbeReply> {
+        let swept = self.swept.lock().unwrap().remove(&ip);
+        let (rtt, reason) = match swept {
+            Some(res) => res?,
+            None => udp_ping(ip, &self.timing)?,
+        };
+        Ok(ProbeReply {
+            port: Some(UDP_PING_PORT),
+            ..ProbeReply::new(PingType::UDP, reason, rtt)
+        })
+    }
+
+    fn sweep(&self, ips: &[Ipv4Addr], session: &ScanSession) {
+        if let Some(results) = udp_ping_sweep(ips, &self.timing, session) {
+            *self.swept.lock().unwrap() = results;
+        }
+    }
+}
+
+pub struct TcpProbe {
+    pub ports: Vec<u16>,
+    pub timeout: Duration,
+}
+
+impl Probe for TcpProbe {
+    fn ping_type(&self) -> PingType {
+        PingType::TCP
+    }
+
+    // All ports at once, the first to answer wins
+    fn probe(&self, ip: Ipv4Addr) -> Result<ProbeReply> {
+        tcp_ping_ports(&ip, &self.ports, self.timeout)
+            .map(|(port, rtt, port_open)| ProbeReply {
+                port: Some(port),
+                port_open,
+                ..ProbeReply::new(
+                    PingType::TCP,
+                    match port_open {
+                        true => ResponseKind::TcpSynAck,
+                        false => ResponseKind::TcpRst,
+                    },
+                    rtt,
+                )
+            })
+            .map_err(|err| anyhow!("No TCP ping port answered on {}: {}", ip, err))
+    }
+}
+
+pub struct IcmpProbe {
+    pub timeout: Duration,
+}
+
+impl Probe for IcmpProbe {
+    fn ping_type(&self) -> PingType {
+        PingType::ICMP
+    }
+
+    fn probe(&self, ip: Ipv4Addr) -> Result<ProbeReply> {
+        let (rtt, ttl) = icmp_ping(ip, self.timeout)?;
+        Ok(ProbeReply {
+            ttl: Some(ttl),
+            ..ProbeReply::new(PingType::ICMP, ResponseKind::IcmpEchoReply, rtt)
+        })
+    }
+}
+
+pub struct ArpProbe {
+    pub iface: NetworkInterface,
+    pub timeout: Duration,
+}
+
+impl Probe for ArpProbe {
+    fn ping_type(&self) -> PingType {
+        PingType::ARP
+    }
+
+    fn probe(&self, ip: Ipv4Addr) -> Result<ProbeReply> {
+        let (rtt, mac) = arp_ping(&self.iface, ip, self.timeout)?;
+        Ok(ProbeReply {
+            mac: Some(mac),
+            ..ProbeReply::new(PingType::ARP, ResponseKind::ArpReply, rtt)
+        })
+    }
+}
+
+// ARP needs the scanned interface, without one that probe is left out
+fn build_probes(
+    methods: &[PingType],
+    config: &AppConfig,
+    iface: Option<&NetworkInterface>,
+) -> Vec<Box<dyn Probe>> {
+    let timing = &config.timing;
+    methods
+        .iter()
+        .filter_map(|method| -> Option<Box<dyn Probe>> {
+            match method {
+                PingType::UDP => Some(Box::new(UdpProbe::new(timing.clone()))),
+                PingType::TCP => Some(Box::new(TcpProbe {
+                    ports: config.tcp_ping_ports.clone(),
+                    timeout: timing.max_rtt_timeout,
+                })),
+                PingType::ICMP => Some(Box::new(IcmpProbe {
+                    timeout: timing.ping_timeout,
+                })),
+                PingType::MDNS => Some(Box::new(MdnsProbe {
+                    timeout: timing.max_rtt_timeout,
+                })),
+                PingType::ARP => match iface {
+                    Some(iface) => Some(Box::new(ArpProbe {
+                        iface: iface.clone(),
+                        timeout: timing.ping_timeout,
+                    })),
+                    None => {
+                        warn!("No network interface selected, skipping the ARP ping");
+                        None
+                    }
+                },
+            }
+        })
+        .collect()
+}
+
+// Hosts that answer a multicast reverse lookup for their own address
+pub struct MdnsProbe {
+    pub timeout: Duration,
+}
+
+impl Probe for MdnsProbe {
+    fn ping_type(&self) -> PingType {
+        PingType::MDNS
+    }
+
+    fn probe(&self, ip: Ipv4Addr) -> Result<ProbeReply> {
+        let now = Instant::now();
+        reverse_dns_lookup::<MdnsAnswer>(
+            ip,
+            HostnameLookupUdpPort::MDNS,
+            MulticastTransact,
+            self.timeout,
+        )?;
+        Ok(ProbeReply::new(
+            PingType::MDNS,
+            ResponseKind::MdnsAnswer,
+            now.elapsed(),
+        ))
+    }
+}
+
+// Services of devices that often ignore the usual ping ports: DNS, NetBIOS,
+// AFP, IPP, alternate HTTP and iOS devices
+const FALLBACK_TCP_PORTS: [u16; 6] = [53, 139, 548, 631, 8080, 62078];
+
+pub struct PingStrategy {
+    probes: Vec<Box<dyn Probe>>,
+    mode: PingMode,
+    policy: PingPolicy,
+}
+
+impl PingStrategy {
+    pub fn new(probes: Vec<Box<dyn Probe>>, mode: PingMode, policy: PingPolicy) -> PingStrategy {
+        PingStrategy {
+            probes,
+            mode,
+            policy,
+        }
+    }
+
+    pub fn from_config(config: &AppConfig, iface: Option<&NetworkInterface>) -> PingStrategy {
+        PingStrategy::new(
+            build_probes(&config.ping_methods, config, iface),
+            config.ping_mode,
+            config.ping_policy,
+        )
+    }
+
+    // Second chance for hosts that answered something else, all at once: UDP
+    // and ICMP (only when privileged) if this strategy doesn't run them, and
+    // TCP pings to the ports it didn't try
+    pub fn fallback(&self, config: &AppConfig, privileged: bool) -> PingStrategy {
+        let runs = |ping_type| self.probes.iter().any(|p| p.ping_type() == ping_type);
+
+        let mut methods = vec![];
+        if !runs(PingType::UDP) {
+            methods.push(PingType::UDP);
+        }
+        if privileged && !runs(PingType::ICMP) {
+            methods.push(PingType::ICMP);
+        }
+        let mut probes = build_probes(&methods, config, None);
+
+        let tried: &[u16] = match runs(PingType::TCP) {
+            true => &config.tcp_ping_ports,
+            false => &[],
+        };
+        let mut ports: Vec<u16> = config
+            .tcp_ping_ports
+            .iter()
+            .chain(FALLBACK_TCP_PORTS.iter())
+            .copied()
+            .filter(|port| !tried.contains(port))
+            .collect();
+        ports.sort_unstable();
+        ports.dedup();
+        probes.push(Box::new(TcpProbe {
+            ports,
+            timeout: config.timing.max_rtt_timeout,
+        }));
+
+        PingStrategy::new(probes, PingMode::Parallel, PingPolicy::Any)
+    }
+
+    // Pings the targets ahead of the per-host runs, where a probe can ping
+    // many hosts at once. Stops early if the session is cancelled.
+    pub fn sweep(&self, ips: &[Ipv4Addr], session: &ScanSession) {
+        for probe in &self.probes {
+            if session.is_cancelled() {
+                return;
+            }
+            probe.sweep(ips, session);
+        }
+    }
+
+    fn run_sequential(&self, ip: Ipv4Addr) -> Option<ProbeReply> {
+        let mut first = None;
+        for probe in &self.probes {
+            let res = probe.probe(ip);
+            if let Err(err) = &res {
+                info!("{} ping to {} failed: {}", probe.ping_type(), ip, err);
+            }
+            match (res, self.policy) {
+                (Ok(reply), PingPolicy::Any) => return Some(reply),
+                (Ok(reply), PingPolicy::All) => {
+                    first.get_or_insert(reply);
+                }
+                (Err(_), PingPolicy::All) => return None,
+                (Err(_), PingPolicy::Any) => {}
+            }
+        }
+        first
+    }
+
+    fn run_parallel(&self, ip: Ipv4Addr) -> Option<ProbeReply> {
+        let results: Vec<Result<ProbeReply>> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .probes
+                .iter()
+                .map(|probe| scope.spawn(move || probe.probe(ip)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow!("Probe panicked")))
+                })
+                .collect()
+        });
+
+        if self.policy == PingPolicy::All && results.iter().any(|res| res.is_err()) {
+            return None;
+        }
+        results
+            .into_iter()
+            .filter_map(|res| res.ok())
+            .min_by_key(|reply| reply.rtt)
+    }
+
+    // The reply the host is considered alive by, None if it isn't
+    pub fn run(&self, ip: Ipv4Addr) -> Option<ProbeReply> {
+        if self.probes.is_empty() {
+            return None;
+        }
+        match self.mode {
+            PingMode::Sequential => self.run_sequential(ip),
+            PingMode::Parallel => self.run_parallel(ip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Answers after a fixed delay, or not at all
+    struct FakeProbe(PingType, Option<u64>);
+
+    impl Probe for FakeProbe {
+        fn ping_type(&self) -> PingType {
+            self.0
+        }
+
+        fn probe(&self, _ip: Ipv4Addr) -> Result<ProbeReply> {
+            match self.1 {
+                Some(ms) => Ok(ProbeReply::new(
+                    self.0,
+                    ResponseKind::UdpReply,
+                    Duration::from_millis(ms),
+                )),
+                None => Err(anyhow!("no answer")),
+            }
+        }
+    }
+
+    fn strategy(mode: PingMode, policy: PingPolicy) -> PingStrategy {
+        PingStrategy::new(
+            vec![
+                Box::new(FakeProbe(PingType::UDP, None)),
+                Box::new(FakeProbe(PingType::TCP, Some(30))),
+                Box::new(FakeProbe(PingType::ICMP, Some(5))),
+            ],
+            mode,
+            policy,
+        )
+    }
+
+    #[test]
+    fn test_ping_strategy() {
+        let ip = Ipv4Addr::new(10, 0, 0, 1);
+
+        let seq_any = strategy(PingMode::Sequential, PingPolicy::Any).run(ip);
+        assert_eq!(seq_any.unwrap().ping_type, PingType::TCP);
+
+        let par_any = strategy(PingMode::Parallel, PingPolicy::Any).run(ip);
+        assert_eq!(par_any.unwrap().ping_type, PingType::ICMP);
+
+        assert_eq!(
+            strategy(PingMode::Sequential, PingPolicy::All).run(ip),
+            None
+        );
+        assert_eq!(strategy(PingMode::Parallel, PingPolicy::All).run(ip), None);
+    }
+
+    #[test]
+    fn test_tcp_probe_refused_counts() {
+        // Bound then dropped, so connecting gets a RST
+        let closed_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let probe = TcpProbe {
+            ports: vec![closed_port],
+            timeout: Timing::default().max_rtt_timeout,
+        };
+        let reply = probe.probe(Ipv4Addr::LOCALHOST).unwrap();
+        assert_eq!(reply.port, Some(closed_port));
+        assert!(!reply.port_open);
+        assert_eq!(
+            reply.evidence().to_string(),
+            format!("port {}/tcp: TCP RST", closed_port)
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open_port = listener.local_addr().unwrap().port();
+        let probe = TcpProbe {
+            ports: vec![open_port],
+            timeout: Timing::default().max_rtt_timeout,
+        };
+        assert!(probe.probe(Ipv4Addr::LOCALHOST).unwrap().port_open);
+    }
+
+    #[test]
+    fn test_parse_ping_options() {
+        assert_eq!(
+            parse_ping_methods("icmp,tcp,arp").unwrap(),
+            vec![PingType::ICMP, PingType::TCP, PingType::ARP]
+        );
+        assert!(parse_ping_methods("udp,smoke").is_err());
+        assert!(parse_ping_methods("").is_err());
+        assert_eq!("parallel".parse::<PingMode>().unwrap(), PingMode::Parallel);
+        assert!("some".parse::<PingPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_fallback_skips_configured_probes() {
+        let config = AppConfig::default();
+        let strategy = PingStrategy::from_config(&config, None);
+
+        // The default udp,tcp only leaves TCP ports that weren't tried
+        let fallback = strategy.fallback(&config, false);
+        let types: Vec<PingType> = fallback.probes.iter().map(|p| p.ping_type()).collect();
+        assert_eq!(types, vec![PingType::TCP]);
+        assert_eq!(fallback.mode, PingMode::Parallel);
+
+        let fallback = strategy.fallback(&config, true);
+        let types: Vec<PingType> = fallback.probes.iter().map(|p| p.ping_type()).collect();
+        assert_eq!(types, vec![PingType::ICMP, PingType::TCP]);
+    }
+}
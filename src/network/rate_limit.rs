@@ -0,0 +1,226 @@
+// Global probe rate limiting, shared by every probe path
+// Packets are spaced evenly at the configured rate. TCP connections also take
+// one of a fixed number of slots, held until the connection is dropped.
+
+use lazy_static::lazy_static;
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The effective rate is averaged over this long
+const RATE_WINDOW: Duration = Duration::from_millis(1000);
+
+lazy_static! {
+    pub static ref RATE_LIMITER: RateLimiter = RateLimiter::default();
+}
+
+#[derive(Default)]
+struct Pacing {
+    next_slot: Option<Instant>,
+    window_start: Option<Instant>,
+    window_packets: u32,
+    rate: f64,
+}
+
+impl Pacing {
+    fn count(&mut self, at: Instant) {
+        let start = *self.window_start.get_or_insert(at);
+        self.window_packets += 1;
+        let elapsed = at.saturating_duration_since(start);
+        if elapsed >= RATE_WINDOW {
+            self.rate = self.window_packets as f64 / elapsed.as_secs_f64();
+            self.window_start = Some(at);
+            self.window_packets = 0;
+        }
+    }
+}
+
+// 0 means no limit
+#[derive(Default)]
+pub struct RateLimiter {
+    max_rate: AtomicU32,
+    max_connections: AtomicUsize,
+    pacing: Mutex<Pacing>,
+    connections: Mutex<usize>,
+    connection_freed: Condvar,
+}
+
+impl RateLimiter {
+    pub fn configure(&self, max_rate: Option<u32>, max_connections: Option<usize>) {
+        self.max_rate
+            .store(max_rate.unwrap_or(0), Ordering::Release);
+        self.max_connections
+            .store(max_connections.unwrap_or(0), Ordering::Release);
+        // Waiters may fit under a raised limit
+        let _active = self.connections.lock().unwrap();
+        self.connection_freed.notify_all();
+    }
+
+    pub fn max_rate(&self) -> Option<u32> {
+        match self.max_rate.load(Ordering::Acquire) {
+            0 => None,
+            rate => Some(rate),
+        }
+    }
+
+    fn max_connections(&self) -> Option<usize> {
+        match self.max_connections.load(Ordering::Acquire) {
+            0 => None,
+            max => Some(max),
+        }
+    }
+
+    // Blocks until the next packet may go out
+    pub fn packet(&self) {
+        let now = Instant::now();
+        let slot = {
+            let mut pacing = self.pacing.lock().unwrap();
+            let slot = match self.max_rate() {
+                Some(rate) => {
+                    let slot = pacing.next_slot.map_or(now, |next| next.max(now));
+                    pacing.next_slot = Some(slot + Duration::from_secs(1) / rate);
+                    slot
+                }
+                None => now,
+            };
+            pacing.count(slot);
+            slot
+        };
+        if slot > now {
+            thread::sleep(slot - now);
+        }
+    }
+
+    // Blocks until a connection slot is free, the SYN counts as a packet
+    pub fn connection(&self) -> ConnectionPermit<'_> {
+        let mut active = self.connections.lock().unwrap();
+        while matches!(self.max_connections(), Some(max) if *active >= max) {
+            active = self.connection_freed.wait(active).unwrap();
+        }
+        *active += 1;
+        drop(active);
+
+        self.packet();
+        ConnectionPermit { limiter: self }
+    }
+
+    // Like connection, but None instead of waiting for a free slot
+    pub fn try_connection(&self) -> Option<ConnectionPermit<'_>> {
+        let mut active = self.connections.lock().unwrap();
+        if matches!(self.max_connections(), Some(max) if *active >= max) {
+            return None;
+        }
+        *active += 1;
+        drop(active);
+
+        self.packet();
+        Some(ConnectionPermit { limiter: self })
+    }
+
+    // Packets per second over the last window, 0 once probing has stopped
+    pub fn effective_rate(&self) -> f64 {
+        let pacing = self.pacing.lock().unwrap();
+        match pacing.window_start {
+            Some(start) if start.elapsed() < RATE_WINDOW * 2 => pacing.rate,
+            _ => 0.0,
+        }
+    }
+}
+
+pub struct ConnectionPermit<'a> {
+    limiter: &'a RateLimiter,
+}
+
+impl Drop for ConnectionPermit<'_> {
+    fn drop(&mut self) {
+        *self.limiter.connections.lock().unwrap() -= 1;
+        self.limiter.connection_freed.notify_one();
+    }
+}
+
+// A TCP connection holding its slot for as long as it's open
+pub struct LimitedStream {
+    stream: TcpStream,
+    _permit: ConnectionPermit<'static>,
+}
+
+pub fn connect_limited(addr: &SocketAddr, timeout: Duration) -> io::Result<LimitedStream> {
+    let permit = RATE_LIMITER.connection();
+    Ok(LimitedStream {
+        stream: TcpStream::connect_timeout(addr, timeout)?,
+        _permit: permit,
+    })
+}
+
+impl Deref for LimitedStream {
+    type Target = TcpStream;
+
+    fn deref(&self) -> &TcpStream {
+        &self.stream
+    }
+}
+
+impl DerefMut for LimitedStream {
+    fn deref_mut(&mut self) -> &mut TcpStream {
+        &mut self.stream
+    }
+}
+
+impl Read for LimitedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for LimitedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_packet_pacing() {
+        let limiter = RateLimiter::default();
+        limiter.configure(Some(100), None);
+
+        let now = Instant::now();
+        for _ in 0..11 {
+            limiter.packet();
+        }
+        // The first goes out at once, the other ten 10ms apart
+        assert!(now.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_connection_slots() {
+        let limiter = Arc::new(RateLimiter::default());
+        limiter.configure(None, Some(1));
+        let permit = limiter.connection();
+        assert!(limiter.try_connection().is_none());
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let waiter = limiter.clone();
+        thread::spawn(move || {
+            let _permit = waiter.connection();
+            tx.send(()).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+        drop(permit);
+        assert!(rx.recv_timeout(Duration::from_secs(1)).is_ok());
+    }
+}
@@ -0,0 +1,446 @@
+// User defined service probes, loosely following the nmap-service-probes format:
+//
+//   # comment
+//   Probe TCP GetRequest q|GET / HTTP/1.0\r\n\r\n|
+//   ports 80,8000-8100
+//   match http m|^HTTP/1\.[01] \d+.*\r\nServer: nginx/([\d.]+)|s p/nginx/ v/$1/
+//
+// A probe without a ports line is tried on every port, an empty payload (q||)
+// just waits for the service to speak first. Matches extract product (p),
+// version (v), info (i) and host name (h) fields, $1..$9 refer to regex groups.
+
+use super::rate_limit::RATE_LIMITER;
+use super::tcp_ping::{parse_portlist, tcp_connect};
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, trace};
+use regex::bytes::{Captures, Regex, RegexBuilder};
+
+use std::fmt;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::Duration;
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(2000);
+// Once a response starts, the rest of it shouldn't take long
+const PROBE_TAIL_TIMEOUT: Duration = Duration::from_millis(300);
+const MAX_RESPONSE_LEN: usize = 16 * 1024;
+const MAX_FIELD_LEN: usize = 80;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Protocol::Tcp => write!(f, "tcp"),
+            Protocol::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+// What a match rule says about the service behind a port
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ServiceId {
+    pub service: String,
+    pub product: Option<String>,
+    pub version: Option<String>,
+    pub info: Option<String>,
+    pub host_name: Option<String>,
+}
+
+impl fmt::Display for ServiceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.service)?;
+        if let Some(product) = &self.product {
+            write!(f, " {}", product)?;
+        }
+        if let Some(version) = &self.version {
+            write!(f, " {}", version)?;
+        }
+        if let Some(info) = &self.info {
+            write!(f, " ({})", info)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct MatchRule {
+    service: String,
+    pattern: Regex,
+    product: Option<String>,
+    version: Option<String>,
+    info: Option<String>,
+    host_name: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ServiceProbe {
+    pub protocol: Protocol,
+    pub name: String,
+    payload: Vec<u8>,
+    ports: Vec<u16>,
+    rules: Vec<MatchRule>,
+}
+
+#[derive(Debug, Default)]
+pub struct ServiceProbes {
+    pub probes: Vec<ServiceProbe>,
+}
+
+// Expand $1..$9 with the matching groups, dropping anything unprintable
+fn fill_template(template: &Option<String>, caps: &Captures) -> Option<String> {
+    let template = template.as_ref()?;
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().and_then(|d| d.to_digit(10))) {
+            ('$', Some(group)) => {
+                chars.next();
+                if let Some(m) = caps.get(group as usize) {
+                    out.push_str(&String::from_utf8_lossy(m.as_bytes()));
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    let out: String = out
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_FIELD_LEN)
+        .collect();
+    match out.trim().is_empty() {
+        true => None,
+        false => Some(out.trim().to_owned()),
+    }
+}
+
+impl MatchRule {
+    fn identify(&self, response: &[u8]) -> Option<ServiceId> {
+        let caps = self.pattern.captures(response)?;
+        Some(ServiceId {
+            service: self.service.to_owned(),
+            product: fill_template(&self.product, &caps),
+            version: fill_template(&self.version, &caps),
+            info: fill_template(&self.info, &caps),
+            host_name: fill_template(&self.host_name, &caps),
+        })
+    }
+}
+
+impl ServiceProbe {
+    pub fn applies_to(&self, protocol: Protocol, port: u16) -> bool {
+        self.protocol == protocol && (self.ports.is_empty() || self.ports.contains(&port))
+    }
+
+    pub fn identify(&self, response: &[u8]) -> Option<ServiceId> {
+        self.rules.iter().find_map(|rule| rule.identify(response))
+    }
+}
+
+// C style escapes in probe payloads: \r \n \t \0 \xHH and friends
+fn unescape(raw: &str) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    let mut bytes = raw.bytes();
+
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'r') => out.push(b'\r'),
+            Some(b'n') => out.push(b'\n'),
+            Some(b't') => out.push(b'\t'),
+            Some(b'0') => out.push(0),
+            Some(b'a') => out.push(7),
+            Some(b'b') => out.push(8),
+            Some(b'f') => out.push(12),
+            Some(b'v') => out.push(11),
+            Some(b'x') => {
+                let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+                let hex = std::str::from_utf8(&hex)?;
+                out.push(
+                    u8::from_str_radix(hex, 16)
+                        .map_err(|_| anyhow!("Bad hex escape \\x{}", hex))?,
+                );
+            }
+            Some(other) => out.push(other),
+            None => return Err(anyhow!("Trailing backslash")),
+        }
+    }
+    Ok(out)
+}
+
+// Splits "<delim>body<delim>rest" into (body, rest)
+fn delimited(s: &str) -> Result<(&str, &str)> {
+    let delim = s
+        .chars()
+        .next()
+        .ok_or_else(|| anyhow!("Missing delimiter"))?;
+    let s = &s[delim.len_utf8()..];
+    let end = s
+        .find(delim)
+        .ok_or_else(|| anyhow!("Unterminated {}...{}", delim, delim))?;
+    Ok((&s[..end], &s[end + delim.len_utf8()..]))
+}
+
+fn parse_probe(args: &str) -> Result<ServiceProbe> {
+    let mut parts = args.splitn(3, ' ');
+    let protocol = match parts.next() {
+        Some("TCP") => Protocol::Tcp,
+        Some("UDP") => Protocol::Udp,
+        _ => return Err(anyhow!("Probe protocol must be TCP or UDP")),
+    };
+    let name = parts
+        .next()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| anyhow!("Probe is missing a name"))?;
+    let payload = parts
+        .next()
+        .and_then(|q| q.trim_start().strip_prefix('q'))
+        .ok_or_else(|| anyhow!("Probe is missing its q|payload|"))?;
+
+    Ok(ServiceProbe {
+        protocol,
+        name: name.to_owned(),
+        payload: unescape(delimited(payload)?.0)?,
+        ports: vec![],
+        rules: vec![],
+    })
+}
+
+fn parse_match(args: &str) -> Result<MatchRule> {
+    let (service, rest) = args
+        .split_once(' ')
+        .ok_or_else(|| anyhow!("match needs a service name and m|pattern|"))?;
+    let rest = rest
+        .trim_start()
+        .strip_prefix('m')
+        .ok_or_else(|| anyhow!("match is missing its m|pattern|"))?;
+    let (pattern, mut rest) = delimited(rest)?;
+
+    let flags: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    rest = &rest[flags.len()..];
+    let pattern = RegexBuilder::new(pattern)
+        .case_insensitive(flags.contains('i'))
+        .dot_matches_new_line(flags.contains('s'))
+        // Responses are bytes, \xHH matches a byte rather than a code point
+        .unicode(false)
+        .build()?;
+
+    let mut rule = MatchRule {
+        service: service.to_owned(),
+        pattern,
+        product: None,
+        version: None,
+        info: None,
+        host_name: None,
+    };
+
+    // Fields like p/product/ v/version/, unknown ones (cpe:/.../) are skipped
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let key: String = rest
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic() || *c == ':')
+            .collect();
+        if key.is_empty() {
+            return Err(anyhow!("Unexpected text after the pattern: {}", rest));
+        }
+        let (value, tail) = delimited(&rest[key.len()..])?;
+        rest = tail;
+
+        let value = Some(value.to_owned());
+        match key.as_str() {
+            "p" => rule.product = value,
+            "v" => rule.version = value,
+            "i" => rule.info = value,
+            "h" => rule.host_name = value,
+            _ => {}
+        }
+    }
+
+    Ok(rule)
+}
+
+impl ServiceProbes {
+    pub fn parse(text: &str) -> Result<ServiceProbes> {
+        let mut probes: Vec<ServiceProbe> = vec![];
+
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (directive, args) = line.split_once(' ').unwrap_or((line, ""));
+            let res = match directive {
+                "Probe" => parse_probe(args.trim()).map(|probe| probes.push(probe)),
+                "ports" | "match" | "softmatch" => match probes.last_mut() {
+                    Some(probe) if directive == "ports" => {
+                        parse_portlist(args.trim()).map(|ports| probe.ports = ports)
+                    }
+                    Some(probe) => parse_match(args.trim()).map(|rule| probe.rules.push(rule)),
+                    None => Err(anyhow!("{} before the first Probe", directive)),
+                },
+                _ => Err(anyhow!("Unknown directive {}", directive)),
+            };
+            res.with_context(|| format!("line {}", lineno + 1))?;
+        }
+
+        Ok(ServiceProbes { probes })
+    }
+
+    pub fn load(path: &str) -> Result<ServiceProbes> {
+        let text = fs::read_to_string(path).with_context(|| format!("Could not read {}", path))?;
+        ServiceProbes::parse(&text).with_context(|| format!("Bad service probe file {}", path))
+    }
+
+    // Runs the probes that apply to the port in file order, first match wins
+    pub fn identify(&self, ip: &Ipv4Addr, protocol: Protocol, port: u16) -> Option<ServiceId> {
+        self.probes
+            .iter()
+            .filter(|probe| probe.applies_to(protocol, port))
+            .find_map(|probe| {
+                info!(
+                    "Sending probe {} to {}:{}/{}",
+                    probe.name, ip, port, protocol
+                );
+                let response = match protocol {
+                    Protocol::Tcp => tcp_exchange(ip, port, &probe.payload),
+                    Protocol::Udp => udp_exchange(ip, port, &probe.payload),
+                }
+                .ok()?;
+                trace!("Probe {} response: {:?}", probe.name, response);
+                probe.identify(&response)
+            })
+    }
+}
+
+fn tcp_exchange(ip: &Ipv4Addr, port: u16, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stream = tcp_connect(ip, port, PROBE_TIMEOUT)?;
+    stream.set_read_timeout(Some(PROBE_TIMEOUT))?;
+    if !payload.is_empty() {
+        stream.write_all(payload)?;
+    }
+
+    let mut response = vec![];
+    let mut buf = [0u8; 4096];
+    while let Ok(n) = stream.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&buf[..n]);
+        if response.len() >= MAX_RESPONSE_LEN {
+            break;
+        }
+        stream.set_read_timeout(Some(PROBE_TAIL_TIMEOUT))?;
+    }
+    Ok(response)
+}
+
+fn udp_exchange(ip: &Ipv4Addr, port: u16, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let usock = UdpSocket::bind("0.0.0.0:0")?;
+    usock.connect((*ip, port))?;
+    usock.set_read_timeout(Some(PROBE_TIMEOUT))?;
+    RATE_LIMITER.packet();
+    usock.send(payload)?;
+
+    let mut buf = [0u8; 1500];
+    let n = usock.recv(&mut buf)?;
+    Ok(buf[..n].to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const PROBE_FILE: &str = r#"
+# Wait for a greeting
+Probe TCP NULL q||
+match ssh m|^SSH-([\d.]+)-OpenSSH_([\w.]+)| p/OpenSSH/ v/$2/ i/protocol $1/ cpe:/a:openbsd:openssh:$2/
+match inventory m|^INV\x01 ready host=(\w+)|s p/Inventory daemon/ h/$1/
+
+Probe TCP Status q|STATUS\r\n|
+ports 9000-9010
+match acme-status m|^OK acme-status/([\d.]+)|i p/ACME status/ v/$1/
+"#;
+
+    #[test]
+    fn test_parse_probe_file() {
+        let probes = ServiceProbes::parse(PROBE_FILE).unwrap();
+        assert_eq!(probes.probes.len(), 2);
+
+        let status = &probes.probes[1];
+        assert_eq!(status.payload, b"STATUS\r\n");
+        assert!(status.applies_to(Protocol::Tcp, 9005));
+        assert!(!status.applies_to(Protocol::Tcp, 22));
+        assert!(!status.applies_to(Protocol::Udp, 9005));
+        assert!(probes.probes[0].applies_to(Protocol::Tcp, 22));
+
+        let ssh = probes.probes[0]
+            .identify(b"SSH-2.0-OpenSSH_8.9p1 Ubuntu-3\r\n")
+            .unwrap();
+        assert_eq!(ssh.version.as_deref(), Some("8.9p1"));
+        assert_eq!(ssh.to_string(), "ssh OpenSSH 8.9p1 (protocol 2.0)");
+
+        let inv = probes.probes[0]
+            .identify(b"INV\x01 ready host=store7\n")
+            .unwrap();
+        assert_eq!(inv.host_name.as_deref(), Some("store7"));
+        assert_eq!(inv.version, None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = ServiceProbes::parse("match ssh m|^SSH|").unwrap_err();
+        assert!(format!("{:#}", err).contains("line 1"));
+
+        assert!(ServiceProbes::parse("Probe SCTP x q||").is_err());
+        assert!(ServiceProbes::parse("Probe TCP x q|abc").is_err());
+        assert!(ServiceProbes::parse("Probe TCP x q||\nmatch a m|(|").is_err());
+        assert_eq!(unescape(r"a\x41\r\n\\").unwrap(), b"aA\r\n\\");
+    }
+
+    #[test]
+    fn test_identify_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let probes =
+            ServiceProbes::parse(&PROBE_FILE.replace("9000-9010", &port.to_string())).unwrap();
+
+        thread::spawn(move || {
+            for conn in listener.incoming().take(2) {
+                let mut conn = conn.unwrap();
+                let mut buf = [0u8; 64];
+                // Silent until asked, so the NULL probe gets nothing
+                if let Ok(n) = conn.read(&mut buf) {
+                    if buf[..n].starts_with(b"STATUS") {
+                        conn.write_all(b"ok ACME-STATUS/2.1 up\r\n").unwrap();
+                    }
+                }
+            }
+        });
+
+        let service = probes
+            .identify(&Ipv4Addr::LOCALHOST, Protocol::Tcp, port)
+            .unwrap();
+        assert_eq!(service.to_string(), "acme-status ACME status 2.1");
+    }
+}
@@ -0,0 +1,53 @@
+// Scan sessions
+// Every scan runs under a session that can be cancelled without touching the
+// store. Its results are stamped with the session ID, and the reducer drops
+// results from sessions that are no longer live.
+
+use crate::state::actions::AppAction;
+use crate::GLOBAL_RUN;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub type SessionId = u64;
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Debug)]
+pub struct ScanSession {
+    id: SessionId,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScanSession {
+    pub fn new() -> ScanSession {
+        ScanSession {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    // Also true once the app is shutting down
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire) || !GLOBAL_RUN.load(Ordering::Acquire)
+    }
+
+    // Stamps a result action with this session
+    pub fn result(&self, action: AppAction) -> AppAction {
+        AppAction::SessionResult(self.id, Box::new(action))
+    }
+}
+
+impl Default for ScanSession {
+    fn default() -> ScanSession {
+        ScanSession::new()
+    }
+}
@@ -0,0 +1,29 @@
+// Passive capture of every frame on an interface (requires root)
+
+use crate::GLOBAL_RUN;
+
+use anyhow::{anyhow, Result};
+use pnet::datalink::{self, Channel, NetworkInterface};
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+pub fn listen_frames<F: FnMut(&[u8])>(iface: &NetworkInterface, mut on_frame: F) -> Result<()> {
+    let config = datalink::Config {
+        read_timeout: Some(Duration::from_millis(250)),
+        ..Default::default()
+    };
+
+    let mut rx = match datalink::channel(iface, config)? {
+        Channel::Ethernet(_, rx) => rx,
+        _ => return Err(anyhow!("Unsupported datalink channel on {}", iface.name)),
+    };
+
+    while GLOBAL_RUN.load(Ordering::Acquire) {
+        // Read timeouts surface as errors, they just give us a chance to check for a stop
+        if let Ok(frame) = rx.next() {
+            on_frame(frame);
+        }
+    }
+    Ok(())
+}
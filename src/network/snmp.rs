@@ -0,0 +1,388 @@
+// SNMP v1/v2c system group probe
+// Only the tiny subset of BER needed to send a GetRequest and read a GetResponse
+// https://datatracker.ietf.org/doc/html/rfc1157
+// https://datatracker.ietf.org/doc/html/rfc3416
+
+use super::rate_limit::RATE_LIMITER;
+
+use anyhow::{anyhow, Result};
+use log::{info, trace};
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::Duration;
+
+pub const SNMP_PORT: u16 = 161;
+const SNMP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+// BER tags
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_TIMETICKS: u8 = 0x43;
+const TAG_GET_REQUEST: u8 = 0xA0;
+const TAG_GET_RESPONSE: u8 = 0xA2;
+
+// system group, RFC 1213
+const OID_SYS_DESCR: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 1, 0];
+const OID_SYS_OBJECT_ID: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 2, 0];
+const OID_SYS_UPTIME: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 3, 0];
+const OID_SYS_CONTACT: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 4, 0];
+const OID_SYS_NAME: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 5, 0];
+
+const SYSTEM_OIDS: [&[u32]; 5] = [
+    OID_SYS_DESCR,
+    OID_SYS_OBJECT_ID,
+    OID_SYS_UPTIME,
+    OID_SYS_CONTACT,
+    OID_SYS_NAME,
+];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SnmpVersion {
+    V1 = 0,
+    V2c = 1,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct SnmpSysInfo {
+    pub community: String,
+    pub descr: Option<String>,
+    pub object_id: Option<String>,
+    pub uptime: Option<Duration>,
+    pub contact: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum SnmpValue {
+    Str(String),
+    Oid(Vec<u32>),
+    Ticks(u32),
+    Int(i64),
+    // NULL, noSuchObject, etc.
+    Empty,
+}
+
+fn encode_len(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        return vec![len as u8];
+    }
+
+    let bytes: Vec<u8> = len
+        .to_be_bytes()
+        .iter()
+        .copied()
+        .skip_while(|b| *b == 0)
+        .collect();
+    let mut enc = vec![0x80 | bytes.len() as u8];
+    enc.extend(bytes);
+    enc
+}
+
+fn encode_tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut enc = vec![tag];
+    enc.extend(encode_len(value.len()));
+    enc.extend_from_slice(value);
+    enc
+}
+
+fn encode_integer(val: i64) -> Vec<u8> {
+    let bytes = val.to_be_bytes();
+    // Strip redundant leading bytes while keeping the sign bit intact
+    let mut start = 0;
+    while start < bytes.len() - 1
+        && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xFF && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    encode_tlv(TAG_INTEGER, &bytes[start..])
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut value = vec![(oid[0] * 40 + oid[1]) as u8];
+    for sub in &oid[2..] {
+        let mut chunk = vec![(sub & 0x7F) as u8];
+        let mut rest = sub >> 7;
+        while rest > 0 {
+            chunk.push(0x80 | (rest & 0x7F) as u8);
+            rest >>= 7;
+        }
+        value.extend(chunk.iter().rev());
+    }
+    encode_tlv(TAG_OID, &value)
+}
+
+pub fn build_get_request(version: SnmpVersion, community: &str, request_id: i32) -> Vec<u8> {
+    let varbinds: Vec<u8> = SYSTEM_OIDS
+        .iter()
+        .flat_map(|oid| {
+            let mut varbind = encode_oid(oid);
+            varbind.extend(encode_tlv(TAG_NULL, &[]));
+            encode_tlv(TAG_SEQUENCE, &varbind)
+        })
+        .collect();
+
+    let mut pdu = encode_integer(request_id as i64);
+    // error-status and error-index
+    pdu.extend(encode_integer(0));
+    pdu.extend(encode_integer(0));
+    pdu.extend(encode_tlv(TAG_SEQUENCE, &varbinds));
+
+    let mut msg = encode_integer(version as i64);
+    msg.extend(encode_tlv(TAG_OCTET_STRING, community.as_bytes()));
+    msg.extend(encode_tlv(TAG_GET_REQUEST, &pdu));
+
+    encode_tlv(TAG_SEQUENCE, &msg)
+}
+
+// Read one TLV, returning (tag, value, rest)
+fn decode_tlv(bytes: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+    let tag = *bytes.first().ok_or_else(|| anyhow!("Empty BER element"))?;
+    let first_len = *bytes
+        .get(1)
+        .ok_or_else(|| anyhow!("Truncated BER length"))?;
+
+    let (len, header_len) = if first_len & 0x80 == 0 {
+        (first_len as usize, 2)
+    } else {
+        let n = (first_len & 0x7F) as usize;
+        if n == 0 || n > 4 {
+            return Err(anyhow!("Unsupported BER length encoding"));
+        }
+        let len_bytes = bytes
+            .get(2..2 + n)
+            .ok_or_else(|| anyhow!("Truncated BER length"))?;
+        let len = len_bytes
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, 2 + n)
+    };
+
+    let value = bytes
+        .get(header_len..header_len + len)
+        .ok_or_else(|| anyhow!("Truncated BER value"))?;
+    Ok((tag, value, &bytes[header_len + len..]))
+}
+
+fn decode_unsigned(value: &[u8]) -> u64 {
+    value.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64)
+}
+
+fn decode_integer(value: &[u8]) -> i64 {
+    let sign: i64 = if matches!(value.first(), Some(b) if b & 0x80 != 0) {
+        -1
+    } else {
+        0
+    };
+    value.iter().fold(sign, |acc, b| (acc << 8) | *b as i64)
+}
+
+fn decode_oid(value: &[u8]) -> Vec<u32> {
+    let mut oid = vec![];
+    if let Some(first) = value.first() {
+        oid.push((*first / 40) as u32);
+        oid.push((*first % 40) as u32);
+    }
+
+    let mut sub: u32 = 0;
+    for b in value.iter().skip(1) {
+        sub = (sub << 7) | (b & 0x7F) as u32;
+        if b & 0x80 == 0 {
+            oid.push(sub);
+            sub = 0;
+        }
+    }
+    oid
+}
+
+fn decode_value(tag: u8, value: &[u8]) -> SnmpValue {
+    match tag {
+        TAG_OCTET_STRING => SnmpValue::Str(String::from_utf8_lossy(value).trim().to_owned()),
+        TAG_OID => SnmpValue::Oid(decode_oid(value)),
+        TAG_TIMETICKS => SnmpValue::Ticks(decode_unsigned(value) as u32),
+        TAG_INTEGER => SnmpValue::Int(decode_integer(value)),
+        _ => SnmpValue::Empty,
+    }
+}
+
+fn oid_string(oid: &[u32]) -> String {
+    oid.iter()
+        .map(|sub| sub.to_string())
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+pub fn parse_get_response(bytes: &[u8], request_id: i32, community: &str) -> Result<SnmpSysInfo> {
+    let (tag, msg, _) = decode_tlv(bytes)?;
+    if tag != TAG_SEQUENCE {
+        return Err(anyhow!("SNMP message is not a sequence"));
+    }
+
+    let (_, _version, rest) = decode_tlv(msg)?;
+    let (_, _community, rest) = decode_tlv(rest)?;
+    let (tag, pdu, _) = decode_tlv(rest)?;
+    if tag != TAG_GET_RESPONSE {
+        return Err(anyhow!("Unexpected SNMP PDU type {:#x}", tag));
+    }
+
+    let (_, req_id, rest) = decode_tlv(pdu)?;
+    if decode_integer(req_id) != request_id as i64 {
+        return Err(anyhow!("SNMP response for another request"));
+    }
+
+    let (_, err_status, rest) = decode_tlv(rest)?;
+    let (_, _err_index, rest) = decode_tlv(rest)?;
+    // v1 agents fail the whole request if any OID is unknown
+    if decode_integer(err_status) != 0 {
+        return Err(anyhow!("SNMP error status {}", decode_integer(err_status)));
+    }
+
+    let mut info = SnmpSysInfo {
+        community: community.to_owned(),
+        ..Default::default()
+    };
+
+    let (_, mut varbinds, _) = decode_tlv(rest)?;
+    while !varbinds.is_empty() {
+        let (_, varbind, rest) = decode_tlv(varbinds)?;
+        varbinds = rest;
+
+        let (_, oid, val) = decode_tlv(varbind)?;
+        let (val_tag, val, _) = decode_tlv(val)?;
+        let oid = decode_oid(oid);
+
+        match (oid.as_slice(), decode_value(val_tag, val)) {
+            (OID_SYS_DESCR, SnmpValue::Str(s)) => info.descr = Some(s),
+            (OID_SYS_OBJECT_ID, SnmpValue::Oid(o)) => info.object_id = Some(oid_string(&o)),
+            // Hundredths of a second
+            (OID_SYS_UPTIME, SnmpValue::Ticks(t)) => {
+                info.uptime = Some(Duration::from_millis(t as u64 * 10))
+            }
+            (OID_SYS_CONTACT, SnmpValue::Str(s)) => info.contact = Some(s),
+            (OID_SYS_NAME, SnmpValue::Str(s)) => info.name = Some(s),
+            (_, other) => trace!("Ignoring SNMP varbind {:?} {:?}", oid, other),
+        }
+    }
+
+    // Empty strings are common for unset fields
+    info.descr = info.descr.filter(|s| !s.is_empty());
+    info.contact = info.contact.filter(|s| !s.is_empty());
+    info.name = info.name.filter(|s| !s.is_empty());
+
+    Ok(info)
+}
+
+fn snmp_transact(ip: Ipv4Addr, version: SnmpVersion, community: &str) -> Result<SnmpSysInfo> {
+    let request_id = 0x5EED;
+    let usock = UdpSocket::bind("0.0.0.0:0")?;
+    usock.connect((ip, SNMP_PORT))?;
+    usock.set_read_timeout(Some(SNMP_TIMEOUT))?;
+    RATE_LIMITER.packet();
+    usock.send(&build_get_request(version, community, request_id))?;
+
+    let mut buf = [0u8; 1500];
+    let len = usock.recv(&mut buf)?;
+    parse_get_response(&buf[..len], request_id, community)
+}
+
+// Try each community with v2c, then v1, until an agent answers
+pub fn snmp_get_system(ip: Ipv4Addr, communities: &[String]) -> Result<SnmpSysInfo> {
+    info!("Sending SNMP system query to {:?}", ip);
+    let mut last_err = anyhow!("No SNMP communities configured");
+    for community in communities {
+        for version in [SnmpVersion::V2c, SnmpVersion::V1].iter() {
+            match snmp_transact(ip, *version, community) {
+                Ok(info) => return Ok(info),
+                Err(err) => last_err = err,
+            }
+        }
+    }
+    Err(last_err)
+}
+
+pub fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    format!(
+        "{}d {:02}:{:02}:{:02}",
+        secs / 86400,
+        (secs % 86400) / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_primitives() {
+        assert_eq!(encode_integer(0), vec![0x02, 0x01, 0x00]);
+        assert_eq!(encode_integer(128), vec![0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(encode_integer(-1), vec![0x02, 0x01, 0xFF]);
+        assert_eq!(encode_len(0x81), vec![0x81, 0x81]);
+        assert_eq!(encode_len(0x0102), vec![0x82, 0x01, 0x02]);
+        assert_eq!(
+            encode_oid(OID_SYS_DESCR),
+            vec![0x06, 0x08, 0x2B, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00]
+        );
+        assert_eq!(
+            decode_oid(&encode_oid(&[1, 3, 6, 1, 4, 1, 9, 1, 1208])[2..]),
+            vec![1, 3, 6, 1, 4, 1, 9, 1, 1208]
+        );
+    }
+
+    #[test]
+    fn test_build_get_request() {
+        let req = build_get_request(SnmpVersion::V2c, "public", 1);
+        // Sequence, v2c, "public"
+        assert_eq!(req[0], TAG_SEQUENCE);
+        assert_eq!(
+            req[2..13],
+            [0x02, 0x01, 0x01, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c']
+        );
+        assert_eq!(req[13], TAG_GET_REQUEST);
+        assert_eq!(req.len(), req[1] as usize + 2);
+    }
+
+    #[test]
+    fn test_parse_get_response() {
+        let varbind = |oid: &[u32], tag: u8, val: &[u8]| {
+            let mut vb = encode_oid(oid);
+            vb.extend(encode_tlv(tag, val));
+            encode_tlv(TAG_SEQUENCE, &vb)
+        };
+
+        let mut varbinds = varbind(OID_SYS_DESCR, TAG_OCTET_STRING, b"HP LaserJet");
+        varbinds.extend(varbind(
+            OID_SYS_OBJECT_ID,
+            TAG_OID,
+            &encode_oid(&[1, 3, 6, 1, 4, 1, 11, 2, 3, 9, 1])[2..],
+        ));
+        varbinds.extend(varbind(OID_SYS_UPTIME, TAG_TIMETICKS, &[0x01, 0x00]));
+        varbinds.extend(varbind(OID_SYS_CONTACT, TAG_OCTET_STRING, b""));
+        varbinds.extend(varbind(OID_SYS_NAME, TAG_OCTET_STRING, b"printer-2f"));
+
+        let mut pdu = encode_integer(42);
+        pdu.extend(encode_integer(0));
+        pdu.extend(encode_integer(0));
+        pdu.extend(encode_tlv(TAG_SEQUENCE, &varbinds));
+
+        let mut msg = encode_integer(1);
+        msg.extend(encode_tlv(TAG_OCTET_STRING, b"public"));
+        msg.extend(encode_tlv(TAG_GET_RESPONSE, &pdu));
+        let resp = encode_tlv(TAG_SEQUENCE, &msg);
+
+        let info = parse_get_response(&resp, 42, "public").unwrap();
+        assert_eq!(info.descr, Some("HP LaserJet".to_owned()));
+        assert_eq!(info.object_id, Some("1.3.6.1.4.1.11.2.3.9.1".to_owned()));
+        assert_eq!(info.uptime, Some(Duration::from_millis(2560)));
+        assert_eq!(info.contact, None);
+        assert_eq!(info.name, Some("printer-2f".to_owned()));
+
+        assert!(parse_get_response(&resp, 43, "public").is_err());
+    }
+}
@@ -0,0 +1,359 @@
+// SSH host key probe
+// Reads the protocol banner and runs the key exchange up to the server's
+// KEX_ECDH_REPLY, which carries the host key. Nothing is verified or
+// encrypted, we hang up as soon as the key is in hand.
+// https://datatracker.ietf.org/doc/html/rfc4253
+// https://datatracker.ietf.org/doc/html/rfc5656
+
+use super::encoding::base64_encode;
+use super::rate_limit::{connect_limited, LimitedStream};
+
+use anyhow::{anyhow, Result};
+use log::{info, trace};
+use ring::digest::{digest, SHA256};
+
+use std::io::prelude::*;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+pub const SSH_PORT: u16 = 22;
+const SSH_TIMEOUT: Duration = Duration::from_millis(3000);
+const CLIENT_VERSION: &str = "SSH-2.0-findi";
+const MAX_PACKET_LEN: usize = 256 * 1024;
+
+const MSG_KEXINIT: u8 = 20;
+const MSG_KEX_ECDH_INIT: u8 = 30;
+const MSG_KEX_ECDH_REPLY: u8 = 31;
+
+const KEX_ALGORITHMS: &str = "curve25519-sha256,curve25519-sha256@libssh.org,ecdh-sha2-nistp256";
+const CIPHERS: &str = "aes128-ctr,aes256-ctr,aes128-gcm@openssh.com,chacha20-poly1305@openssh.com";
+const MACS: &str = "hmac-sha2-256,hmac-sha1";
+
+// The curve base points make valid (if not very secret) ephemeral keys
+const CURVE25519_BASE_POINT: [u8; 32] = {
+    let mut point = [0u8; 32];
+    point[0] = 9;
+    point
+};
+const NISTP256_GENERATOR: [u8; 65] = [
+    0x04, 0x6b, 0x17, 0xd1, 0xf2, 0xe1, 0x2c, 0x42, 0x47, 0xf8, 0xbc, 0xe6, 0xe5, 0x63, 0xa4, 0x40,
+    0xf2, 0x77, 0x03, 0x7d, 0x81, 0x2d, 0xeb, 0x33, 0xa0, 0xf4, 0xa1, 0x39, 0x45, 0xd8, 0x98, 0xc2,
+    0x96, 0x4f, 0xe3, 0x42, 0xe2, 0xfe, 0x1a, 0x7f, 0x9b, 0x8e, 0xe7, 0xeb, 0x4a, 0x7c, 0x0f, 0x9e,
+    0x16, 0x2b, 0xce, 0x33, 0x57, 0x6b, 0x31, 0x5e, 0xce, 0xcb, 0xb6, 0x40, 0x68, 0x37, 0xbf, 0x51,
+    0xf5,
+];
+
+// Host key algorithms worth asking for, one per key type
+const HOST_KEY_ALGORITHMS: [&str; 7] = [
+    "ssh-ed25519",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "rsa-sha2-512",
+    "rsa-sha2-256",
+    "ssh-rsa",
+];
+
+// The RSA signature algorithms all get the same ssh-rsa key
+fn host_key_type(alg: &str) -> &str {
+    match alg {
+        "rsa-sha2-512" | "rsa-sha2-256" => "ssh-rsa",
+        _ => alg,
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SshHostKey {
+    pub key_type: String,
+    // OpenSSH style, SHA256:<unpadded base64>
+    pub fingerprint: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct SshInfo {
+    pub banner: String,
+    pub host_keys: Vec<SshHostKey>,
+    // The keys differ from the ones seen on this IP in a previous scan
+    pub key_changed: bool,
+}
+
+pub fn fingerprint(key_blob: &[u8]) -> String {
+    let hash = digest(&SHA256, key_blob);
+    format!(
+        "SHA256:{}",
+        base64_encode(hash.as_ref()).trim_end_matches('=')
+    )
+}
+
+fn put_string(buf: &mut Vec<u8>, val: &[u8]) {
+    buf.extend_from_slice(&(val.len() as u32).to_be_bytes());
+    buf.extend_from_slice(val);
+}
+
+fn read_string(buf: &[u8]) -> Result<(&[u8], &[u8])> {
+    let len_bytes = buf
+        .get(..4)
+        .ok_or_else(|| anyhow!("Truncated SSH string"))?;
+    let len = u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+    let val = buf
+        .get(4..4 + len)
+        .ok_or_else(|| anyhow!("Truncated SSH string"))?;
+    Ok((val, &buf[4 + len..]))
+}
+
+// Unencrypted binary packet, padded to a multiple of 8 with at least 4 bytes
+pub fn frame_packet(payload: &[u8]) -> Vec<u8> {
+    let mut padding = 8 - (payload.len() + 5) % 8;
+    if padding < 4 {
+        padding += 8;
+    }
+
+    let mut packet = ((payload.len() + padding + 1) as u32)
+        .to_be_bytes()
+        .to_vec();
+    packet.push(padding as u8);
+    packet.extend_from_slice(payload);
+    packet.extend(vec![0u8; padding]);
+    packet
+}
+
+pub fn build_kexinit(host_key_algorithm: &str) -> Vec<u8> {
+    let mut payload = vec![MSG_KEXINIT];
+    payload.extend_from_slice(b"findi-ssh-cookie");
+    for name_list in &[
+        KEX_ALGORITHMS,
+        host_key_algorithm,
+        CIPHERS,
+        CIPHERS,
+        MACS,
+        MACS,
+        "none",
+        "none",
+        "",
+        "",
+    ] {
+        put_string(&mut payload, name_list.as_bytes());
+    }
+    // first_kex_packet_follows and reserved
+    payload.push(0);
+    payload.extend_from_slice(&[0; 4]);
+    payload
+}
+
+// The server's host key algorithms from its KEXINIT payload
+pub fn parse_kexinit_host_key_algorithms(payload: &[u8]) -> Result<Vec<String>> {
+    if payload.first() != Some(&MSG_KEXINIT) || payload.len() < 17 {
+        return Err(anyhow!("Not an SSH KEXINIT"));
+    }
+    let (_kex, rest) = read_string(&payload[17..])?;
+    let (host_key_algs, _) = read_string(rest)?;
+    Ok(String::from_utf8_lossy(host_key_algs)
+        .split(',')
+        .map(str::to_owned)
+        .collect())
+}
+
+// Host key from a KEX_ECDH_REPLY payload
+pub fn parse_ecdh_reply(payload: &[u8]) -> Result<SshHostKey> {
+    if payload.first() != Some(&MSG_KEX_ECDH_REPLY) {
+        return Err(anyhow!("Not an SSH KEX_ECDH_REPLY"));
+    }
+    let (key_blob, _) = read_string(&payload[1..])?;
+    let (key_type, _) = read_string(key_blob)?;
+
+    Ok(SshHostKey {
+        key_type: String::from_utf8_lossy(key_type).to_string(),
+        fingerprint: fingerprint(key_blob),
+    })
+}
+
+struct SshConn {
+    stream: LimitedStream,
+    banner: String,
+}
+
+impl SshConn {
+    fn connect(ip: &Ipv4Addr, port: u16) -> Result<SshConn> {
+        let sockaddr = SocketAddr::new(IpAddr::V4(*ip), port);
+        let mut stream = connect_limited(&sockaddr, SSH_TIMEOUT)?;
+        stream.set_read_timeout(Some(SSH_TIMEOUT))?;
+        stream.set_write_timeout(Some(SSH_TIMEOUT))?;
+
+        stream.write_all(format!("{}\r\n", CLIENT_VERSION).as_bytes())?;
+
+        // Servers may send other lines before the version line
+        let mut banner = String::new();
+        for _ in 0..20 {
+            let line = Self::read_line(&mut stream)?;
+            if line.starts_with("SSH-") {
+                banner = line;
+                break;
+            }
+        }
+        if banner.is_empty() {
+            return Err(anyhow!("No SSH version line"));
+        }
+
+        Ok(SshConn { stream, banner })
+    }
+
+    fn read_line(stream: &mut TcpStream) -> Result<String> {
+        let mut line = vec![];
+        let mut byte = [0u8; 1];
+        while line.len() < 255 {
+            stream.read_exact(&mut byte)?;
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+        }
+        Ok(String::from_utf8_lossy(&line).trim_end().to_owned())
+    }
+
+    fn read_packet(&mut self) -> Result<Vec<u8>> {
+        let mut header = [0u8; 5];
+        self.stream.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let padding = header[4] as usize;
+        if len > MAX_PACKET_LEN || len < padding + 1 {
+            return Err(anyhow!("Bad SSH packet length {}", len));
+        }
+
+        let mut body = vec![0u8; len - 1];
+        self.stream.read_exact(&mut body)?;
+        body.truncate(len - 1 - padding);
+        Ok(body)
+    }
+
+    fn send_payload(&mut self, payload: &[u8]) -> Result<()> {
+        self.stream.write_all(&frame_packet(payload))?;
+        Ok(())
+    }
+
+    // Skip IGNORE/DEBUG and friends until the wanted message shows up
+    fn expect(&mut self, msg: u8) -> Result<Vec<u8>> {
+        for _ in 0..10 {
+            let payload = self.read_packet()?;
+            trace!("SSH message {:?}", payload.first());
+            if payload.first() == Some(&msg) {
+                return Ok(payload);
+            }
+        }
+        Err(anyhow!("SSH message {} never arrived", msg))
+    }
+}
+
+fn fetch_host_key(ip: &Ipv4Addr, port: u16, host_key_algorithm: &str) -> Result<SshHostKey> {
+    let mut conn = SshConn::connect(ip, port)?;
+    let server_kexinit = conn.expect(MSG_KEXINIT)?;
+    conn.send_payload(&build_kexinit(host_key_algorithm))?;
+
+    // Pick the ephemeral key for the kex method the server agrees on
+    let (server_kex, _) = read_string(server_kexinit.get(17..).unwrap_or_default())?;
+    let server_kex = String::from_utf8_lossy(server_kex);
+    let curve25519 = server_kex
+        .split(',')
+        .any(|kex| kex.starts_with("curve25519-sha256"));
+
+    let mut ecdh_init = vec![MSG_KEX_ECDH_INIT];
+    if curve25519 {
+        put_string(&mut ecdh_init, &CURVE25519_BASE_POINT);
+    } else {
+        put_string(&mut ecdh_init, &NISTP256_GENERATOR);
+    }
+    conn.send_payload(&ecdh_init)?;
+
+    parse_ecdh_reply(&conn.expect(MSG_KEX_ECDH_REPLY)?)
+}
+
+// One key exchange per key type the server offers
+pub fn ssh_probe(ip: &Ipv4Addr, port: u16) -> Result<SshInfo> {
+    info!("Fetching SSH host keys from {}:{}", ip, port);
+
+    let mut conn = SshConn::connect(ip, port)?;
+    let server_algs = parse_kexinit_host_key_algorithms(&conn.expect(MSG_KEXINIT)?)?;
+    let banner = conn.banner.to_owned();
+    drop(conn);
+
+    let mut host_keys: Vec<SshHostKey> = vec![];
+    for alg in HOST_KEY_ALGORITHMS.iter() {
+        if !server_algs.iter().any(|server_alg| server_alg == alg)
+            || host_keys
+                .iter()
+                .any(|key| key.key_type == host_key_type(alg))
+        {
+            continue;
+        }
+
+        match fetch_host_key(ip, port, alg) {
+            Ok(key) => host_keys.push(key),
+            Err(err) => trace!("SSH {} key from {} failed: {}", alg, ip, err),
+        }
+    }
+
+    Ok(SshInfo {
+        banner,
+        host_keys,
+        key_changed: false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        // Same as `ssh-keygen -lf` on the matching .pub file
+        let ed25519_blob = [
+            0x00, 0x00, 0x00, 0x0b, 0x73, 0x73, 0x68, 0x2d, 0x65, 0x64, 0x32, 0x35, 0x35, 0x31,
+            0x39, 0x00, 0x00, 0x00, 0x20, 0x03, 0x91, 0x22, 0xd4, 0xa0, 0xfe, 0xa6, 0x5f, 0x84,
+            0xc3, 0x48, 0x38, 0x2f, 0x28, 0x67, 0xfd, 0x12, 0x2e, 0xb4, 0xe6, 0x3f, 0xc2, 0x7f,
+            0x80, 0x8c, 0x5f, 0x95, 0xae, 0x80, 0x28, 0x79, 0x36,
+        ];
+        assert_eq!(
+            fingerprint(&ed25519_blob),
+            "SHA256:ywfJT0fbmYZP+NOFDldOTXAg8f2Y/zdElIYGZwo/+Q4"
+        );
+    }
+
+    #[test]
+    fn test_frame_packet() {
+        for payload_len in 0..40 {
+            let packet = frame_packet(&vec![1u8; payload_len]);
+            let padding = packet[4] as usize;
+            assert_eq!(packet.len() % 8, 0);
+            assert!(padding >= 4);
+            assert_eq!(
+                u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]) as usize,
+                packet.len() - 4
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_kex_messages() {
+        let kexinit = build_kexinit("ssh-ed25519,ssh-rsa");
+        assert_eq!(
+            parse_kexinit_host_key_algorithms(&kexinit).unwrap(),
+            vec!["ssh-ed25519", "ssh-rsa"]
+        );
+
+        let mut key_blob = vec![];
+        put_string(&mut key_blob, b"ssh-ed25519");
+        put_string(&mut key_blob, &[7u8; 32]);
+
+        let mut reply = vec![MSG_KEX_ECDH_REPLY];
+        put_string(&mut reply, &key_blob);
+        put_string(&mut reply, &[9u8; 32]);
+
+        assert_eq!(
+            parse_ecdh_reply(&reply).unwrap(),
+            SshHostKey {
+                key_type: "ssh-ed25519".to_owned(),
+                fingerprint: fingerprint(&key_blob),
+            }
+        );
+        assert!(parse_ecdh_reply(&kexinit).is_err());
+    }
+}
@@ -1,14 +1,18 @@
 use std::collections::HashSet;
-use std::io::prelude::*;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::io::{self, prelude::*, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::thread;
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
-use log::info;
+use socket2::{Domain, Protocol, Socket, Type};
 
+use super::banner::grab_banner;
 use super::ping_result::PingResult;
+use super::rate_limit::{connect_limited, ConnectionPermit, LimitedStream, RATE_LIMITER};
 
-pub const TCP_PING_PORT: u16 = 22;
+// SSH, web, SMB and RDP between them catch most servers, appliances and desktops
+pub const TCP_PING_PORTS: [u16; 5] = [22, 80, 443, 445, 3389];
 
 pub fn parse_portlist(plist_str: &str) -> Result<Vec<u16>> {
     let mut plist: HashSet<u16> = HashSet::new();
@@ -42,25 +46,154 @@ pub fn parse_portlist(plist_str: &str) -> Result<Vec<u16>> {
     Ok(plist_vec)
 }
 
-pub fn tcp_ping(ip: Ipv4Addr) -> PingResult {
-    info!("Sending TCP ping to {:?}", ip);
-    tcp_scan_port(&ip, TCP_PING_PORT)
+// The reverse of parse_portlist, runs of ports are written as ranges
+pub fn format_portlist(ports: &[u16]) -> String {
+    let mut groups: Vec<(u16, u16)> = vec![];
+    for &port in ports {
+        match groups.last_mut() {
+            Some((_, end)) if port == *end || Some(port) == end.checked_add(1) => *end = port,
+            _ => groups.push((port, port)),
+        }
+    }
+    groups
+        .iter()
+        .map(|&(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
 }
 
-pub fn tcp_scan_port(ip: &Ipv4Addr, port: u16) -> PingResult {
-    let now = Instant::now();
+pub fn tcp_connect(ip: &Ipv4Addr, port: u16, timeout: Duration) -> std::io::Result<LimitedStream> {
     let sockaddr = SocketAddr::new(IpAddr::V4(*ip), port);
-    // TODO: config
-    let to = Some(Duration::from_millis(2000));
-    let mut stream = TcpStream::connect_timeout(&sockaddr, to.unwrap())?;
+    let stream = connect_limited(&sockaddr, timeout)?;
+
+    stream.set_write_timeout(Some(timeout))?;
+    stream.set_read_timeout(Some(timeout))?;
+    Ok(stream)
+}
 
-    stream.set_write_timeout(to)?;
-    stream.set_read_timeout(to)?;
+pub fn tcp_scan_port(ip: &Ipv4Addr, port: u16, timeout: Duration) -> PingResult {
+    let now = Instant::now();
+    let mut stream = tcp_connect(ip, port, timeout)?;
     stream.write_all(&[1])?;
 
     Ok(now.elapsed())
 }
 
+// How often connects in flight are checked on
+const CONNECT_POLL: Duration = Duration::from_millis(1);
+
+// A non-blocking connect returns this while the handshake is under way
+#[cfg(target_os = "linux")]
+const EINPROGRESS: i32 = libc::EINPROGRESS;
+// macOS and the BSDs
+#[cfg(not(target_os = "linux"))]
+const EINPROGRESS: i32 = 36;
+
+// A connect in flight, holding its slot until it's dropped
+struct PendingConnect {
+    port: u16,
+    socket: Socket,
+    started: Instant,
+    _permit: ConnectionPermit<'static>,
+}
+
+fn start_connect(ip: &Ipv4Addr, port: u16) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_nonblocking(true)?;
+    match socket.connect(&SocketAddr::new(IpAddr::V4(*ip), port).into()) {
+        Ok(()) => Ok(socket),
+        Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(socket),
+        Err(err) if err.raw_os_error() == Some(EINPROGRESS) => Ok(socket),
+        Err(err) => Err(err),
+    }
+}
+
+// Connects to all the ports from this thread, the first to accept the
+// connection or refuse it with a RST wins and the others are closed. The bool
+// tells the two apart (true for an open port). A connection slot is only
+// waited for while no connect is in flight, so hosts can't starve each other.
+pub fn tcp_ping_ports(
+    ip: &Ipv4Addr,
+    ports: &[u16],
+    timeout: Duration,
+) -> io::Result<(u16, Duration, bool)> {
+    let deadline = Instant::now() + timeout;
+    let mut waiting = ports.iter().copied().peekable();
+    let mut pending: Vec<PendingConnect> = vec![];
+    let mut last_err = None;
+
+    loop {
+        while let Some(&port) = waiting.peek() {
+            let permit = match pending.is_empty() {
+                true => RATE_LIMITER.connection(),
+                false => match RATE_LIMITER.try_connection() {
+                    Some(permit) => permit,
+                    None => break,
+                },
+            };
+            waiting.next();
+            let started = Instant::now();
+            match start_connect(ip, port) {
+                Ok(socket) => pending.push(PendingConnect {
+                    port,
+                    socket,
+                    started,
+                    _permit: permit,
+                }),
+                Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+                    return Ok((port, started.elapsed(), false))
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        let mut idx = 0;
+        while idx < pending.len() {
+            let conn = &pending[idx];
+            let err = match conn.socket.take_error() {
+                Ok(None) if conn.socket.peer_addr().is_ok() => {
+                    return Ok((conn.port, conn.started.elapsed(), true))
+                }
+                Ok(None) => {
+                    idx += 1;
+                    continue;
+                }
+                Ok(Some(err)) | Err(err) => err,
+            };
+            if err.kind() == ErrorKind::ConnectionRefused {
+                return Ok((conn.port, conn.started.elapsed(), false));
+            }
+            pending.swap_remove(idx);
+            last_err = Some(err);
+        }
+
+        if pending.is_empty() && waiting.peek().is_none() {
+            return Err(last_err
+                .unwrap_or_else(|| io::Error::new(ErrorKind::InvalidInput, "No ports to ping")));
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(ErrorKind::TimedOut, "TCP ping timed out"));
+        }
+        thread::sleep(CONNECT_POLL);
+    }
+}
+
+// Like tcp_scan_port, but reads the service banner instead of poking the port
+pub fn tcp_scan_port_banner(
+    ip: &Ipv4Addr,
+    port: u16,
+    timeout: Duration,
+) -> std::io::Result<(Duration, Option<String>)> {
+    let now = Instant::now();
+    let mut stream = tcp_connect(ip, port, timeout)?;
+    let connected = now.elapsed();
+
+    Ok((connected, grab_banner(&mut stream, port)))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -79,4 +212,30 @@ mod test {
         let range = parse_portlist(invalid_range1);
         assert_eq!(range.is_err(), true);
     }
+
+    #[test]
+    fn test_tcp_ping_ports() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = {
+            let unused = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            unused.local_addr().unwrap().port()
+        };
+        let localhost = Ipv4Addr::LOCALHOST;
+        let timeout = Duration::from_secs(1);
+
+        let (port, _, port_open) = tcp_ping_ports(&localhost, &[open], timeout).unwrap();
+        assert_eq!((port, port_open), (open, true));
+        let (port, _, port_open) = tcp_ping_ports(&localhost, &[closed], timeout).unwrap();
+        assert_eq!((port, port_open), (closed, false));
+        assert!(tcp_ping_ports(&localhost, &[], timeout).is_err());
+    }
+
+    #[test]
+    fn test_port_list_format() {
+        assert_eq!(format_portlist(&[22, 80, 81, 82, 443]), "22,80-82,443");
+        assert_eq!(format_portlist(&[]), "");
+        let ports = parse_portlist("1-1024,8080").unwrap();
+        assert_eq!(format_portlist(&ports), "1-1024,8080");
+    }
 }
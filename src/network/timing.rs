@@ -0,0 +1,198 @@
+// Timing templates, from paranoid to insane, and per-host adaptive timeouts
+// The templates set the upper bounds. Once a host has answered, connects and
+// lookups to it only wait a few round trips (RFC 6298 style), never less than
+// the template's floor.
+
+use anyhow::{anyhow, Error, Result};
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TimingTemplate {
+    Paranoid,
+    Sneaky,
+    Polite,
+    Normal,
+    Aggressive,
+    Insane,
+}
+
+const TEMPLATES: [TimingTemplate; 6] = [
+    TimingTemplate::Paranoid,
+    TimingTemplate::Sneaky,
+    TimingTemplate::Polite,
+    TimingTemplate::Normal,
+    TimingTemplate::Aggressive,
+    TimingTemplate::Insane,
+];
+
+impl fmt::Display for TimingTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TimingTemplate::Paranoid => "paranoid",
+            TimingTemplate::Sneaky => "sneaky",
+            TimingTemplate::Polite => "polite",
+            TimingTemplate::Normal => "normal",
+            TimingTemplate::Aggressive => "aggressive",
+            TimingTemplate::Insane => "insane",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Either the name or its number, 0 (paranoid) to 5 (insane)
+impl FromStr for TimingTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<TimingTemplate> {
+        let s = s.trim().to_lowercase();
+        TEMPLATES
+            .iter()
+            .enumerate()
+            .find(|(idx, template)| s == idx.to_string() || s == template.to_string())
+            .map(|(_, template)| *template)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown timing template {}, expected 0-5 or paranoid..insane",
+                    s
+                )
+            })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Timing {
+    // Longest wait for a TCP connect or a name lookup answer
+    pub max_rtt_timeout: Duration,
+    // Adaptive timeouts never go below this
+    pub min_rtt_timeout: Duration,
+    // Wait for each UDP, ICMP or ARP ping
+    pub ping_timeout: Duration,
+    // UDP ping resends, each waiting retry_delay longer than the last
+    pub ping_retries: u32,
+    pub retry_delay: Duration,
+    // Pause before starting on the next host, and between ports of a host
+    pub host_delay: Duration,
+    pub port_delay: Duration,
+}
+
+fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+impl TimingTemplate {
+    pub fn timing(self) -> Timing {
+        let (max_rtt, min_rtt, ping, retries, retry_delay, host_delay, port_delay) = match self {
+            TimingTemplate::Paranoid => (10000, 1000, 5000, 5, 1000, 5000, 1000),
+            TimingTemplate::Sneaky => (5000, 500, 3000, 4, 500, 1000, 400),
+            TimingTemplate::Polite => (3000, 200, 2000, 3, 300, 400, 100),
+            TimingTemplate::Normal => (2000, 100, 1000, 3, 200, 50, 10),
+            TimingTemplate::Aggressive => (1250, 50, 500, 2, 100, 10, 0),
+            TimingTemplate::Insane => (300, 25, 250, 1, 50, 0, 0),
+        };
+        Timing {
+            max_rtt_timeout: ms(max_rtt),
+            min_rtt_timeout: ms(min_rtt),
+            ping_timeout: ms(ping),
+            ping_retries: retries,
+            retry_delay: ms(retry_delay),
+            host_delay: ms(host_delay),
+            port_delay: ms(port_delay),
+        }
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Timing {
+        TimingTemplate::Normal.timing()
+    }
+}
+
+impl Timing {
+    // Timeouts for a host that answered in about the estimated round trip
+    pub fn adapted(&self, rtt: &RttEstimator) -> Timing {
+        let timeout = rtt.timeout(self.min_rtt_timeout, self.max_rtt_timeout);
+        Timing {
+            max_rtt_timeout: timeout,
+            ping_timeout: timeout.min(self.ping_timeout),
+            ..self.clone()
+        }
+    }
+}
+
+// Smoothed round trip and its variation, without samples the template's
+// maximum is used
+#[derive(Clone, Default, Debug)]
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+}
+
+impl RttEstimator {
+    pub fn new(sample: Option<Duration>) -> RttEstimator {
+        let mut rtt = RttEstimator::default();
+        if let Some(sample) = sample {
+            rtt.update(sample);
+        }
+        rtt
+    }
+
+    pub fn update(&mut self, sample: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(sample);
+                self.rttvar = sample / 2;
+            }
+            Some(srtt) => {
+                let err = srtt.max(sample) - srtt.min(sample);
+                self.rttvar = self.rttvar * 3 / 4 + err / 4;
+                self.srtt = Some(srtt * 7 / 8 + sample / 8);
+            }
+        }
+    }
+
+    pub fn timeout(&self, min: Duration, max: Duration) -> Duration {
+        match self.srtt {
+            Some(srtt) => (srtt + self.rttvar * 4).max(min).min(max),
+            None => max,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_template() {
+        assert_eq!(
+            "4".parse::<TimingTemplate>().unwrap(),
+            TimingTemplate::Aggressive
+        );
+        assert_eq!(
+            "Polite".parse::<TimingTemplate>().unwrap(),
+            TimingTemplate::Polite
+        );
+        assert!("6".parse::<TimingTemplate>().is_err());
+        assert_eq!(Timing::default().max_rtt_timeout, ms(2000));
+    }
+
+    #[test]
+    fn test_adaptive_timeout() {
+        let timing = Timing::default();
+        assert_eq!(timing.adapted(&RttEstimator::default()), timing);
+
+        // A fast LAN host is held to the floor
+        let lan = RttEstimator::new(Some(ms(2)));
+        assert_eq!(timing.adapted(&lan).max_rtt_timeout, ms(100));
+
+        // Jittery Wi-Fi gets room for the spikes
+        let mut wifi = RttEstimator::new(Some(ms(40)));
+        wifi.update(ms(300));
+        wifi.update(ms(60));
+        let timeout = timing.adapted(&wifi).max_rtt_timeout;
+        assert!(timeout > ms(300) && timeout <= ms(2000), "{:?}", timeout);
+    }
+}
@@ -0,0 +1,218 @@
+// TLS certificate inspection for implicit TLS ports
+// The handshake accepts any certificate, we only want to look at it.
+
+use super::rate_limit::{connect_limited, LimitedStream};
+
+use anyhow::{anyhow, Result};
+use log::info;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, ClientConnection, ServerName, StreamOwned};
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::prelude::*;
+use x509_parser::public_key::PublicKey;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const TLS_TIMEOUT: Duration = Duration::from_millis(3000);
+
+// Ports that speak TLS straight away (no STARTTLS)
+pub const TLS_PORTS: [u16; 9] = [443, 465, 636, 853, 989, 990, 993, 995, 8443];
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct TlsCertInfo {
+    pub subject: String,
+    pub sans: Vec<String>,
+    pub issuer: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub expired: bool,
+    pub not_yet_valid: bool,
+    pub self_signed: bool,
+    pub key_type: String,
+    pub protocol: String,
+    pub cipher: String,
+}
+
+impl TlsCertInfo {
+    // Short description of what is wrong with the certificate, if anything
+    pub fn warning(&self) -> Option<String> {
+        let validity = if self.expired {
+            Some(format!("expired {}", self.not_after))
+        } else if self.not_yet_valid {
+            Some(format!("not valid before {}", self.not_before))
+        } else {
+            None
+        };
+        match (validity, self.self_signed) {
+            (Some(validity), true) => Some(format!("{}, self-signed", validity)),
+            (Some(validity), false) => Some(validity),
+            (None, true) => Some("self-signed".to_owned()),
+            (None, false) => None,
+        }
+    }
+}
+
+pub fn is_tls_port(port: u16) -> bool {
+    TLS_PORTS.contains(&port)
+}
+
+struct AcceptAnyCert;
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn key_type(cert: &X509Certificate) -> String {
+    match cert.public_key().parsed() {
+        Ok(PublicKey::RSA(rsa)) => format!("RSA {} bits", rsa.key_size()),
+        Ok(PublicKey::EC(ec)) => format!("EC {} bits", ec.key_size()),
+        Ok(PublicKey::DSA(_)) => "DSA".to_owned(),
+        _ => {
+            let alg = &cert.public_key().algorithm.algorithm;
+            oid2sn(alg, oid_registry()).map_or_else(|_| alg.to_id_string(), str::to_owned)
+        }
+    }
+}
+
+pub fn parse_certificate(der: &[u8]) -> Result<TlsCertInfo> {
+    let (_, cert) =
+        X509Certificate::from_der(der).map_err(|err| anyhow!("Bad certificate: {}", err))?;
+
+    let sans = match cert.subject_alternative_name() {
+        Ok(Some(ext)) => ext
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(dns.to_string()),
+                GeneralName::IPAddress(&[a, b, c, d]) => {
+                    Some(Ipv4Addr::new(a, b, c, d).to_string())
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    let validity = cert.validity();
+    let now = ASN1Time::now();
+    Ok(TlsCertInfo {
+        subject: cert.subject().to_string(),
+        sans,
+        issuer: cert.issuer().to_string(),
+        not_before: validity.not_before.to_string(),
+        not_after: validity.not_after.to_string(),
+        expired: validity.not_after < now,
+        not_yet_valid: now < validity.not_before,
+        self_signed: cert.subject().as_raw() == cert.issuer().as_raw(),
+        key_type: key_type(&cert),
+        ..Default::default()
+    })
+}
+
+// Connect and finish the handshake, whatever certificate the server has
+pub fn tls_connect(
+    ip: &Ipv4Addr,
+    port: u16,
+) -> Result<StreamOwned<ClientConnection, LimitedStream>> {
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCert))
+        .with_no_client_auth();
+    let mut conn = ClientConnection::new(Arc::new(config), ServerName::IpAddress(IpAddr::V4(*ip)))?;
+
+    let sockaddr = SocketAddr::new(IpAddr::V4(*ip), port);
+    let mut stream = connect_limited(&sockaddr, TLS_TIMEOUT)?;
+    stream.set_read_timeout(Some(TLS_TIMEOUT))?;
+    stream.set_write_timeout(Some(TLS_TIMEOUT))?;
+
+    while conn.is_handshaking() {
+        conn.complete_io(&mut stream)?;
+    }
+
+    Ok(StreamOwned::new(conn, stream))
+}
+
+pub fn inspect_tls(ip: &Ipv4Addr, port: u16) -> Result<TlsCertInfo> {
+    info!("Inspecting TLS certificate on {}:{}", ip, port);
+
+    let conn = tls_connect(ip, port)?.conn;
+
+    let cert = conn
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .ok_or_else(|| anyhow!("No certificate presented"))?;
+
+    let mut tls_info = parse_certificate(&cert.0)?;
+    tls_info.protocol = conn
+        .protocol_version()
+        .map_or_else(|| "--".to_owned(), |ver| format!("{:?}", ver));
+    tls_info.cipher = conn
+        .negotiated_cipher_suite()
+        .map_or_else(|| "--".to_owned(), |suite| format!("{:?}", suite.suite()));
+
+    Ok(tls_info)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tls_warning() {
+        let mut tls_info = TlsCertInfo {
+            not_after: "Jan  1 00:00:00 2020 +00:00".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(tls_info.warning(), None);
+
+        tls_info.self_signed = true;
+        assert_eq!(tls_info.warning().unwrap(), "self-signed");
+
+        tls_info.self_signed = false;
+        tls_info.expired = true;
+        assert_eq!(
+            tls_info.warning().unwrap(),
+            "expired Jan  1 00:00:00 2020 +00:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_certificate() {
+        let tls_info = parse_certificate(include_bytes!("testdata/expired_cert.der")).unwrap();
+        assert_eq!(tls_info.subject, "CN=printer.local, O=Findi Test");
+        assert_eq!(tls_info.sans, vec!["printer.local", "10.0.0.5"]);
+        assert_eq!(tls_info.key_type, "EC 256 bits");
+        assert!(tls_info.self_signed);
+        assert!(tls_info.expired);
+        assert!(!tls_info.not_yet_valid);
+        assert_eq!(
+            tls_info.warning().unwrap(),
+            format!("expired {}, self-signed", tls_info.not_after)
+        );
+
+        let tls_info = parse_certificate(include_bytes!("testdata/future_cert.der")).unwrap();
+        assert!(!tls_info.expired);
+        assert!(tls_info.not_yet_valid);
+        assert!(tls_info.warning().unwrap().starts_with("not valid before"));
+    }
+
+    #[test]
+    fn test_parse_certificate_garbage() {
+        assert!(parse_certificate(&[0x30, 0x03, 0x02, 0x01, 0x01]).is_err());
+        assert!(is_tls_port(443));
+        assert!(!is_tls_port(80));
+    }
+}
@@ -1,46 +1,465 @@
-use super::ping_result::PingResult;
-use ::std::io::ErrorKind;
+// UDP ping
+// On Linux every ping goes out of one shared socket. A receiver thread matches
+// replies by source address and ICMP errors, read from the socket's error
+// queue (IP_RECVERR), by the address the failed datagram was sent to. A sweep
+// pings every target of a scan up front with a single thread waiting for the
+// answers. Other platforms, or if the shared socket can't be set up, use a
+// socket per ping.
+
+use super::probe::ResponseKind;
+use super::rate_limit::RATE_LIMITER;
+use super::session::ScanSession;
+use super::timing::Timing;
+use ::std::collections::HashMap;
+use ::std::io::{self, ErrorKind};
 use ::std::net::{Ipv4Addr, UdpSocket};
 use ::std::time::{Duration, Instant};
 use std::thread::sleep;
 
 use log::info;
 
-const UDP_PING_PORT: u16 = 9989;
-const UDP_PING_DUR: Duration = Duration::from_millis(1000);
-const UDP_COOL_OFF_MS: i32 = 200;
-const UDP_MAX_TRIES: i32 = 3;
+#[cfg(target_os = "linux")]
+use lazy_static::lazy_static;
+#[cfg(target_os = "linux")]
+use log::debug;
+#[cfg(target_os = "linux")]
+use std::net::SocketAddr;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(target_os = "linux")]
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+#[cfg(target_os = "linux")]
+use std::sync::{Arc, Mutex};
+#[cfg(target_os = "linux")]
+use std::{mem, ptr, thread};
+
+pub const UDP_PING_PORT: u16 = 9989;
 
-pub fn udp_ping(ip: Ipv4Addr) -> PingResult {
+// Any datagram back, or an ICMP port unreachable, proves the host is there.
+// Host/network unreachable errors don't.
+pub fn udp_ping(ip: Ipv4Addr, timing: &Timing) -> io::Result<(Duration, ResponseKind)> {
     info!("Sending UDP ping to {:?}", ip);
-    // TODO make this user settable
+
+    #[cfg(target_os = "linux")]
+    match UDP_PINGER.as_ref() {
+        Ok(pinger) => return pinger.ping(ip, timing),
+        Err(err) => debug!("Shared UDP ping socket unavailable: {}", err),
+    }
+
+    socket_udp_ping(ip, timing)
+}
+
+// Result of every target, None without a shared socket to ping them all from
+pub fn udp_ping_sweep(
+    ips: &[Ipv4Addr],
+    timing: &Timing,
+    session: &ScanSession,
+) -> Option<HashMap<Ipv4Addr, io::Result<(Duration, ResponseKind)>>> {
+    info!("Sending UDP pings to {} hosts", ips.len());
+
+    #[cfg(target_os = "linux")]
+    if let Ok(pinger) = UDP_PINGER.as_ref() {
+        return Some(pinger.sweep(ips, timing, session));
+    }
+
+    None
+}
+
+// A connected socket of its own reports the ICMP port unreachable as
+// ECONNREFUSED
+fn socket_udp_ping(ip: Ipv4Addr, timing: &Timing) -> io::Result<(Duration, ResponseKind)> {
     let mut tries = 0;
 
     let usock = UdpSocket::bind("0.0.0.0:0")?;
 
     let now = Instant::now();
 
-    while tries >= 0 {
+    loop {
         usock.connect((ip, UDP_PING_PORT))?;
+        RATE_LIMITER.packet();
         usock.send(&[1; 1])?;
-        usock.set_read_timeout(Some(UDP_PING_DUR))?;
+        usock.set_read_timeout(Some(timing.ping_timeout))?;
         match usock.recv(&mut [0; 1]) {
-            Ok(_) => break,
+            Ok(_) => return Ok((now.elapsed(), ResponseKind::UdpReply)),
             Err(err) => match err.kind() {
+                ErrorKind::ConnectionRefused => {
+                    return Ok((now.elapsed(), ResponseKind::IcmpPortUnreachable))
+                }
                 ErrorKind::WouldBlock => {
-                    if tries == UDP_MAX_TRIES {
+                    if tries == timing.ping_retries {
                         return Err(err);
                     } else {
-                        sleep(Duration::from_millis(
-                            ((tries + 1) * UDP_COOL_OFF_MS) as u64,
-                        ));
+                        sleep(timing.retry_delay * (tries + 1));
                         tries += 1;
                     }
                 }
-                _ => break,
+                _ => return Err(err),
             },
         };
     }
+}
+
+#[cfg(target_os = "linux")]
+lazy_static! {
+    static ref UDP_PINGER: io::Result<UdpPinger> = UdpPinger::start();
+}
+
+// What the receiver saw for a target, and when
+#[cfg(target_os = "linux")]
+type Answer = (Ipv4Addr, Instant, io::Result<ResponseKind>);
+
+#[cfg(target_os = "linux")]
+type Waiters = Arc<Mutex<HashMap<Ipv4Addr, Vec<(u64, Sender<Answer>)>>>>;
+
+#[cfg(target_os = "linux")]
+struct UdpPinger {
+    socket: UdpSocket,
+    waiters: Waiters,
+    next_waiter: AtomicU64,
+}
+
+// The receiver wakes up this often even without traffic, in case an error
+// was queued while it was busy
+#[cfg(target_os = "linux")]
+const RECV_POLL: Duration = Duration::from_millis(100);
+
+// A send gets the pending error of an earlier datagram, possibly to another
+// target, which the receiver also reads off the error queue
+#[cfg(target_os = "linux")]
+fn is_pending_error(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::ECONNREFUSED | libc::EHOSTUNREACH | libc::ENETUNREACH | libc::EHOSTDOWN)
+    )
+}
+
+#[cfg(target_os = "linux")]
+impl UdpPinger {
+    fn start() -> io::Result<UdpPinger> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        let enable: libc::c_int = 1;
+        // SAFETY: the fd is open for the socket's lifetime, and the option
+        // value points to a c_int of the size passed
+        let res = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_IP,
+                libc::IP_RECVERR,
+                &enable as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        socket.set_read_timeout(Some(RECV_POLL))?;
+
+        let waiters = Waiters::default();
+        let recv_socket = socket.try_clone()?;
+        let recv_waiters = waiters.clone();
+        thread::Builder::new()
+            .name("udp-ping-recv".into())
+            .spawn(move || receive(recv_socket, recv_waiters))?;
+
+        Ok(UdpPinger {
+            socket,
+            waiters,
+            next_waiter: AtomicU64::new(0),
+        })
+    }
+
+    // Answers for the targets go to the returned receiver until unregistered
+    fn register(&self, ips: &[Ipv4Addr]) -> (u64, Receiver<Answer>) {
+        let (tx, rx) = mpsc::channel();
+        let id = self.next_waiter.fetch_add(1, Ordering::Relaxed);
+        let mut waiters = self.waiters.lock().unwrap();
+        for ip in ips {
+            waiters.entry(*ip).or_default().push((id, tx.clone()));
+        }
+        (id, rx)
+    }
 
-    Ok(now.elapsed())
+    fn unregister(&self, ips: &[Ipv4Addr], id: u64) {
+        let mut waiters = self.waiters.lock().unwrap();
+        for ip in ips {
+            if let Some(list) = waiters.get_mut(ip) {
+                list.retain(|(waiter, _)| *waiter != id);
+                if list.is_empty() {
+                    waiters.remove(ip);
+                }
+            }
+        }
+    }
+
+    fn ping(&self, ip: Ipv4Addr, timing: &Timing) -> io::Result<(Duration, ResponseKind)> {
+        let (id, rx) = self.register(&[ip]);
+        let res = self.await_answer(ip, timing, &rx);
+        self.unregister(&[ip], id);
+        res
+    }
+
+    // Each round resends to the targets still quiet, then waits as long as a
+    // single ping would. A cancelled session stops it between sends and rounds.
+    fn sweep(
+        &self,
+        ips: &[Ipv4Addr],
+        timing: &Timing,
+        session: &ScanSession,
+    ) -> HashMap<Ipv4Addr, io::Result<(Duration, ResponseKind)>> {
+        let (id, rx) = self.register(ips);
+        let mut sent = HashMap::new();
+        let mut results = HashMap::new();
+
+        'rounds: for tries in 0..=timing.ping_retries {
+            let quiet: Vec<Ipv4Addr> = ips
+                .iter()
+                .copied()
+                .filter(|ip| !results.contains_key(ip))
+                .collect();
+            for ip in quiet {
+                if session.is_cancelled() {
+                    break 'rounds;
+                }
+                match self.send(ip, timing) {
+                    Ok(at) => {
+                        sent.insert(ip, at);
+                    }
+                    Err(err) => {
+                        results.insert(ip, Err(err));
+                    }
+                }
+            }
+
+            let wait_until = Instant::now() + retry_wait(timing, tries);
+            while results.len() < ips.len() {
+                let remaining = wait_until.saturating_duration_since(Instant::now());
+                match rx.recv_timeout(remaining) {
+                    Ok((ip, at, res)) => {
+                        if let Some(sent) = sent.get(&ip) {
+                            results.entry(ip).or_insert_with(|| {
+                                res.map(|kind| (at.saturating_duration_since(*sent), kind))
+                            });
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => break 'rounds,
+                }
+            }
+            if results.len() == ips.len() {
+                break;
+            }
+        }
+        self.unregister(ips, id);
+
+        for ip in ips {
+            results.entry(*ip).or_insert_with(|| {
+                Err(io::Error::new(ErrorKind::WouldBlock, "no UDP ping answer"))
+            });
+        }
+        results
+    }
+
+    // A late answer to an earlier try still counts while waiting to resend
+    fn await_answer(
+        &self,
+        ip: Ipv4Addr,
+        timing: &Timing,
+        rx: &Receiver<Answer>,
+    ) -> io::Result<(Duration, ResponseKind)> {
+        for tries in 0..=timing.ping_retries {
+            let sent = self.send(ip, timing)?;
+            match rx.recv_timeout(retry_wait(timing, tries)) {
+                Ok((_, at, res)) => {
+                    return res.map(|kind| (at.saturating_duration_since(sent), kind))
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        Err(io::Error::new(ErrorKind::WouldBlock, "no UDP ping answer"))
+    }
+
+    // Pending errors are retried for up to a ping timeout, however many other
+    // pings queue them meanwhile
+    fn send(&self, ip: Ipv4Addr, timing: &Timing) -> io::Result<Instant> {
+        let deadline = Instant::now() + timing.ping_timeout;
+        loop {
+            RATE_LIMITER.packet();
+            let sent = Instant::now();
+            match self.socket.send_to(&[1; 1], (ip, UDP_PING_PORT)) {
+                Ok(_) => return Ok(sent),
+                Err(err) if is_pending_error(&err) && sent < deadline => {
+                    thread::sleep(Duration::from_millis(1))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+// How long to wait after a try, a late answer to an earlier one still counts
+#[cfg(target_os = "linux")]
+fn retry_wait(timing: &Timing, tries: u32) -> Duration {
+    if tries == timing.ping_retries {
+        timing.ping_timeout
+    } else {
+        timing.ping_timeout + timing.retry_delay * (tries + 1)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn answer(waiters: &Waiters, ip: Ipv4Addr, at: Instant, res: io::Result<ResponseKind>) {
+    if let Some(list) = waiters.lock().unwrap().get(&ip) {
+        for (_, tx) in list {
+            let res = match &res {
+                Ok(kind) => Ok(*kind),
+                Err(err) => Err(err
+                    .raw_os_error()
+                    .map_or_else(|| err.kind().into(), io::Error::from_raw_os_error)),
+            };
+            let _ = tx.send((ip, at, res));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn receive(socket: UdpSocket, waiters: Waiters) {
+    let mut buf = [0; 64];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((_, SocketAddr::V4(src))) if src.port() == UDP_PING_PORT => answer(
+                &waiters,
+                *src.ip(),
+                Instant::now(),
+                Ok(ResponseKind::UdpReply),
+            ),
+            // Timeouts, stray datagrams, and the pending error that flags a
+            // new entry on the error queue
+            _ => {}
+        }
+        while let Some((dst, res)) = recv_error(&socket) {
+            if let Some(res) = res {
+                answer(&waiters, dst, Instant::now(), res);
+            }
+        }
+    }
+}
+
+// Next entry on the error queue, with the target the failed datagram was
+// sent to. None once the queue is empty.
+#[cfg(target_os = "linux")]
+#[allow(clippy::type_complexity)]
+fn recv_error(socket: &UdpSocket) -> Option<(Ipv4Addr, Option<io::Result<ResponseKind>>)> {
+    let mut data = [0u8; 64];
+    // u64s keep the control buffer aligned for the cmsg headers
+    let mut control = [0u64; 64];
+    // SAFETY: both are plain C structs, all zeroes is a valid value
+    let mut dst: libc::sockaddr_in = unsafe { mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut dst as *mut libc::sockaddr_in as *mut libc::c_void;
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    // SAFETY: msg points to buffers that live until the call returns, with
+    // their sizes, and MSG_DONTWAIT keeps it from blocking
+    let res = unsafe {
+        libc::recvmsg(
+            socket.as_raw_fd(),
+            &mut msg,
+            libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT,
+        )
+    };
+    if res < 0 {
+        return None;
+    }
+
+    let ip = Ipv4Addr::from(u32::from_be(dst.sin_addr.s_addr));
+    if u16::from_be(dst.sin_port) != UDP_PING_PORT {
+        return Some((ip, None));
+    }
+
+    // SAFETY: recvmsg filled in the control buffer and msg_controllen, and
+    // the cmsg macros stay within them. Each non-null header is aligned in
+    // the u64 buffer, the error payload is read unaligned.
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let hdr = unsafe { &*cmsg };
+        if hdr.cmsg_level == libc::SOL_IP && hdr.cmsg_type == libc::IP_RECVERR {
+            let err: libc::sock_extended_err = unsafe {
+                ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err)
+            };
+            let errno = err.ee_errno as i32;
+            let res = if err.ee_origin == libc::SO_EE_ORIGIN_ICMP && errno == libc::ECONNREFUSED {
+                Ok(ResponseKind::IcmpPortUnreachable)
+            } else {
+                Err(io::Error::from_raw_os_error(errno))
+            };
+            return Some((ip, Some(res)));
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+    Some((ip, None))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // All of 127.0.0.0/8 is local, so each test gets its own target
+    #[test]
+    fn test_port_unreachable() {
+        let ip = Ipv4Addr::new(127, 0, 45, 1);
+        let (_, kind) = udp_ping(ip, &Timing::default()).unwrap();
+        assert_eq!(kind, ResponseKind::IcmpPortUnreachable);
+    }
+
+    #[test]
+    fn test_udp_reply() {
+        let ip = Ipv4Addr::new(127, 0, 45, 2);
+        let server = UdpSocket::bind((ip, UDP_PING_PORT)).unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0; 16];
+            let (_, src) = server.recv_from(&mut buf).unwrap();
+            server.send_to(&buf[..1], src).unwrap();
+        });
+
+        let (_, kind) = udp_ping(ip, &Timing::default()).unwrap();
+        assert_eq!(kind, ResponseKind::UdpReply);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sweep() {
+        let closed = Ipv4Addr::new(127, 0, 45, 3);
+        let open = Ipv4Addr::new(127, 0, 45, 4);
+        let server = UdpSocket::bind((open, UDP_PING_PORT)).unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0; 16];
+            let (_, src) = server.recv_from(&mut buf).unwrap();
+            server.send_to(&buf[..1], src).unwrap();
+        });
+
+        let results =
+            udp_ping_sweep(&[closed, open], &Timing::default(), &ScanSession::new()).unwrap();
+        assert_eq!(
+            results[&closed].as_ref().unwrap().1,
+            ResponseKind::IcmpPortUnreachable
+        );
+        assert_eq!(results[&open].as_ref().unwrap().1, ResponseKind::UdpReply);
+
+        // A cancelled scan sends nothing
+        let session = ScanSession::new();
+        session.cancel();
+        let results = udp_ping_sweep(&[closed], &Timing::default(), &session).unwrap();
+        assert!(results[&closed].is_err());
+    }
 }
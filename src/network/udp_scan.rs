@@ -0,0 +1,194 @@
+// UDP port scanning
+// Well known ports get a payload their service will answer, anything else an
+// empty datagram. A reply means open, an ICMP port unreachable (reported as
+// ECONNREFUSED on a connected socket) means closed, and silence is open|filtered.
+
+use super::rate_limit::RATE_LIMITER;
+use super::snmp::{build_get_request, SnmpVersion};
+
+use log::info;
+
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::Duration;
+
+// Datagrams get lost, resend once before calling it open|filtered
+const UDP_SCAN_TRIES: usize = 2;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum UdpPortState {
+    Open,
+    Closed,
+    OpenFiltered,
+}
+
+impl fmt::Display for UdpPortState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UdpPortState::Open => write!(f, "open"),
+            UdpPortState::Closed => write!(f, "closed"),
+            UdpPortState::OpenFiltered => write!(f, "open|filtered"),
+        }
+    }
+}
+
+// DNS query for version.bind TXT CH, any DNS server answers it, if only with REFUSED
+const DNS_PAYLOAD: &[u8] = b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+    \x07version\x04bind\x00\x00\x10\x00\x03";
+
+// NBSTAT query for the wildcard name "*"
+const NETBIOS_PAYLOAD: &[u8] = b"\x80\xf0\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+    \x20CKAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\x00\x00\x21\x00\x01";
+
+const SSDP_PAYLOAD: &[u8] = b"M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\
+    MAN: \"ssdp:discover\"\r\nMX: 1\r\nST: ssdp:all\r\n\r\n";
+
+// Unicast (QU) PTR query for _services._dns-sd._udp.local
+const MDNS_PAYLOAD: &[u8] = b"\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+    \x09_services\x07_dns-sd\x04_udp\x05local\x00\x00\x0c\x80\x01";
+
+// Read request, servers answer with data or an error packet
+const TFTP_PAYLOAD: &[u8] = b"\x00\x01findi\x00octet\x00";
+
+// NTPv3 client request
+fn ntp_payload() -> Vec<u8> {
+    let mut payload = vec![0u8; 48];
+    payload[0] = 0x1b;
+    payload
+}
+
+// IKEv1 main mode with a single 3DES/SHA1/PSK/MODP1024 proposal
+fn ike_payload() -> Vec<u8> {
+    let attributes: [(u16, u16); 6] = [
+        (0x8001, 5),     // encryption: 3DES
+        (0x8002, 2),     // hash: SHA1
+        (0x8003, 1),     // auth: pre-shared key
+        (0x8004, 2),     // group: MODP 1024
+        (0x800b, 1),     // life type: seconds
+        (0x800c, 28800), // life duration
+    ];
+
+    let mut transform = vec![0, 0, 0, 0, 1, 1, 0, 0];
+    for (attr, val) in attributes.iter() {
+        transform.extend_from_slice(&attr.to_be_bytes());
+        transform.extend_from_slice(&val.to_be_bytes());
+    }
+    let transform_len = transform.len() as u16;
+    transform[2..4].copy_from_slice(&transform_len.to_be_bytes());
+
+    // proposal #1, protocol ISAKMP, no SPI, one transform
+    let mut proposal = vec![0, 0, 0, 0, 1, 1, 0, 1];
+    proposal.extend(transform);
+    let proposal_len = proposal.len() as u16;
+    proposal[2..4].copy_from_slice(&proposal_len.to_be_bytes());
+
+    // DOI IPsec, situation identity only
+    let mut sa = vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1];
+    sa.extend(proposal);
+    let sa_len = sa.len() as u16;
+    sa[2..4].copy_from_slice(&sa_len.to_be_bytes());
+
+    let mut packet = b"findi-ik".to_vec();
+    packet.extend_from_slice(&[0; 8]);
+    // next payload SA, version 1.0, identity protection exchange, no flags, message id 0
+    packet.extend_from_slice(&[1, 0x10, 2, 0, 0, 0, 0, 0]);
+    packet.extend_from_slice(&((28 + sa.len()) as u32).to_be_bytes());
+    packet.extend(sa);
+    packet
+}
+
+pub fn udp_payload(port: u16) -> Vec<u8> {
+    match port {
+        53 => DNS_PAYLOAD.to_vec(),
+        69 => TFTP_PAYLOAD.to_vec(),
+        123 => ntp_payload(),
+        137 => NETBIOS_PAYLOAD.to_vec(),
+        161 => build_get_request(SnmpVersion::V1, "public", 0x1234),
+        500 => ike_payload(),
+        1900 => SSDP_PAYLOAD.to_vec(),
+        5353 => MDNS_PAYLOAD.to_vec(),
+        _ => vec![],
+    }
+}
+
+pub fn udp_scan_port(ip: &Ipv4Addr, port: u16, timeout: Duration) -> io::Result<UdpPortState> {
+    info!("UDP scanning {}:{}", ip, port);
+
+    let usock = UdpSocket::bind("0.0.0.0:0")?;
+    usock.connect((*ip, port))?;
+    usock.set_read_timeout(Some(timeout))?;
+
+    let payload = udp_payload(port);
+    let mut buf = [0u8; 1500];
+
+    for _ in 0..UDP_SCAN_TRIES {
+        RATE_LIMITER.packet();
+        match usock.send(&payload) {
+            Ok(_) => {}
+            // A late port unreachable for the last try
+            Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+                return Ok(UdpPortState::Closed)
+            }
+            Err(err) => return Err(err),
+        }
+        match usock.recv(&mut buf) {
+            Ok(_) => return Ok(UdpPortState::Open),
+            Err(err) => match err.kind() {
+                ErrorKind::ConnectionRefused => return Ok(UdpPortState::Closed),
+                ErrorKind::WouldBlock | ErrorKind::TimedOut => continue,
+                _ => return Err(err),
+            },
+        }
+    }
+
+    Ok(UdpPortState::OpenFiltered)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ike_payload_lengths() {
+        let packet = ike_payload();
+        let total = u32::from_be_bytes([packet[24], packet[25], packet[26], packet[27]]);
+        assert_eq!(total as usize, packet.len());
+        assert_eq!(packet.len(), 80);
+        // SA payload length
+        assert_eq!(u16::from_be_bytes([packet[30], packet[31]]), 52);
+    }
+
+    #[test]
+    fn test_udp_payloads() {
+        assert_eq!(udp_payload(123).len(), 48);
+        assert!(udp_payload(1900).starts_with(b"M-SEARCH"));
+        assert!(udp_payload(40000).is_empty());
+    }
+
+    #[test]
+    fn test_udp_scan_local() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (_, peer) = server.recv_from(&mut buf).unwrap();
+            server.send_to(b"pong", peer).unwrap();
+        });
+        assert_eq!(
+            udp_scan_port(&Ipv4Addr::LOCALHOST, port, Duration::from_secs(1)).unwrap(),
+            UdpPortState::Open
+        );
+
+        // Nothing listens here anymore, the kernel answers with port unreachable
+        let closed_port = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        assert_eq!(
+            udp_scan_port(&Ipv4Addr::LOCALHOST, closed_port, Duration::from_secs(1)).unwrap(),
+            UdpPortState::Closed
+        );
+    }
+}
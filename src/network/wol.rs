@@ -0,0 +1,121 @@
+// Wake-on-LAN magic packets
+// https://en.wikipedia.org/wiki/Wake-on-LAN#Magic_packet
+
+use super::arp::arp_cache_lookup;
+use super::find_interface;
+use super::history::ScanHistory;
+
+use anyhow::{anyhow, Result};
+use log::info;
+use pnet::datalink::{self, Channel};
+use pnet::packet::ethernet::{EtherType, MutableEthernetPacket};
+use pnet::packet::Packet;
+use pnet::util::MacAddr;
+
+use std::net::{Ipv4Addr, UdpSocket};
+
+pub const WOL_UDP_PORT: u16 = 9;
+const WOL_ETHERTYPE: EtherType = EtherType(0x0842);
+const MAGIC_PACKET_LEN: usize = 102;
+const ETHERNET_HEADER_LEN: usize = 14;
+
+// 6 bytes of 0xFF followed by the target MAC repeated 16 times
+pub fn magic_packet(mac: MacAddr) -> [u8; MAGIC_PACKET_LEN] {
+    let mut packet = [0xFF; MAGIC_PACKET_LEN];
+    let octets = mac.octets();
+    for chunk in packet[6..].chunks_mut(6) {
+        chunk.copy_from_slice(&octets);
+    }
+    packet
+}
+
+pub fn wake_udp(mac: MacAddr) -> Result<()> {
+    let usock = UdpSocket::bind("0.0.0.0:0")?;
+    usock.set_broadcast(true)?;
+    usock.send_to(&magic_packet(mac), (Ipv4Addr::BROADCAST, WOL_UDP_PORT))?;
+    Ok(())
+}
+
+// Sending raw frames usually requires elevated privileges
+pub fn wake_ethernet(iface_name: &str, mac: MacAddr) -> Result<()> {
+    let iface = find_interface(iface_name)?;
+
+    let mut tx = match datalink::channel(&iface, Default::default())? {
+        Channel::Ethernet(tx, _) => tx,
+        _ => return Err(anyhow!("Unsupported datalink channel on {}", iface_name)),
+    };
+
+    let mut buf = [0u8; ETHERNET_HEADER_LEN + MAGIC_PACKET_LEN];
+    let mut frame = MutableEthernetPacket::new(&mut buf).unwrap();
+    frame.set_destination(MacAddr::broadcast());
+    frame.set_source(iface.mac.unwrap_or_else(MacAddr::zero));
+    frame.set_ethertype(WOL_ETHERTYPE);
+    frame.set_payload(&magic_packet(mac));
+
+    match tx.send_to(frame.packet(), None) {
+        Some(res) => Ok(res?),
+        None => Err(anyhow!("Could not send frame on {}", iface_name)),
+    }
+}
+
+// Send the magic packet both as a UDP broadcast and as a raw ethernet frame
+// (when an interface is known). Succeeds if either of them went out.
+pub fn wake(mac: MacAddr, iface_name: Option<&str>) -> Result<()> {
+    info!("Sending Wake-on-LAN packet to {}", mac);
+    let udp_res = wake_udp(mac);
+    let eth_res = match iface_name {
+        Some(name) => wake_ethernet(name, mac),
+        None => Err(anyhow!("No interface for raw ethernet")),
+    };
+
+    match (udp_res, eth_res) {
+        (Err(udp_err), Err(eth_err)) => Err(anyhow!(
+            "Could not send magic packet ({}; {})",
+            udp_err,
+            eth_err
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Resolve a `findi wake` target, either a MAC address or an IP address
+// that was seen in a previous scan or is still in the ARP cache
+pub fn resolve_wake_target(target: &str) -> Result<MacAddr> {
+    if let Ok(mac) = target.parse::<MacAddr>() {
+        return Ok(mac);
+    }
+
+    let ip = target
+        .parse::<Ipv4Addr>()
+        .map_err(|_| anyhow!("{} is not a MAC or IPv4 address", target))?;
+
+    ScanHistory::load()
+        .mac_for(&ip)
+        .or_else(|| arp_cache_lookup(ip))
+        .ok_or_else(|| anyhow!("No known MAC address for {}, scan the network first", ip))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_magic_packet() {
+        let mac = MacAddr::new(0x00, 0x11, 0x22, 0x33, 0x44, 0x55);
+        let packet = magic_packet(mac);
+
+        assert_eq!(packet[..6], [0xFF; 6]);
+        for rep in packet[6..].chunks(6) {
+            assert_eq!(rep, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        }
+    }
+
+    #[test]
+    fn test_resolve_wake_target() {
+        assert_eq!(
+            resolve_wake_target("aa:bb:cc:dd:ee:ff").unwrap(),
+            MacAddr::new(0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff)
+        );
+        assert!(resolve_wake_target("not-a-host").is_err());
+    }
+}
@@ -1,30 +1,46 @@
 use crate::config::AppConfig;
+use crate::network::dhcp::DhcpOffer;
 use crate::network::host::Host;
+use crate::network::os_fingerprint::PacketTraits;
 use crate::network::ping_result::PingResult;
+use crate::network::session::{ScanSession, SessionId};
+use crate::state::host_filter::HostFilter;
 use crate::state::host_modal_state::HostModalAction;
+use crate::state::settings_state::SettingField;
+use crate::ui::components::search_filter::SearchFilterOption;
 use crate::ui::modal::Modal;
 use crate::ui::notification::Notification;
-use crate::ui::{components::search_filter::SearchFilterOption, pages::PageContent};
+use crate::ui::pages::{Page, PageContent};
 
+use pnet::util::MacAddr;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
 
 pub trait Action {}
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::large_enum_variant)]
 pub enum AppAction {
     BuildHosts(Vec<Ipv4Addr>),
     UpdatePingResult(Ipv4Addr, PingResult),
     UpdateHost(Host),
     SetQuery(String),
     SetPortQuery(Option<String>),
+    SetUdpPortQuery(Option<String>),
     SetInputErr(bool),
     SetHostSearchRun(bool),
+    StartHostSearch(ScanSession),
+    // A scan result, dropped unless its session is still live
+    SessionResult(SessionId, Box<AppAction>),
     NewQuery(Vec<Ipv4Addr>),
     TableSelect(Option<usize>),
     ShiftFocus(PageContent),
+    SetPage(Page),
     SetNotification(Option<Notification>),
     SetModal(Option<Modal>),
     SetSearchFilter(SearchFilterOption),
+    SetHostFilter(Option<HostFilter>),
+    // Some while the quick filter is being typed
+    SetFilterInput(Option<String>),
     SetSelectedHost(Option<usize>),
     SetModalAction(HostModalAction),
     QueryComplete,
@@ -33,7 +49,18 @@ pub enum AppAction {
     SetConfig(AppConfig),
     SetConfigNWorkers(usize),
     SetConfigTick(usize),
+    SetConfigFile(Option<PathBuf>),
+    SetConfigProfile(Option<String>),
+    SetSettingsSelected(usize),
+    // Some while a settings field is being edited
+    SetSettingsInput(Option<String>),
+    // A new value was applied from the settings page
+    SettingEdited(SettingField),
     SetNetworkNameServer(Ipv4Addr),
+    SetInterface(Option<String>),
+    SetDhcpOffers(Vec<DhcpOffer>),
+    ArpSeen(Ipv4Addr, MacAddr),
+    OsTraitsSeen(Ipv4Addr, PacketTraits),
 }
 
 // impl AppAction {
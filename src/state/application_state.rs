@@ -1,21 +1,36 @@
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
 
 use tui::widgets::TableState;
 
 use crate::config::AppConfig;
+use crate::network::arp::ArpTable;
+use crate::network::dhcp::DhcpOffer;
 use crate::network::host::{Host, HostVec};
-use crate::state::host_modal_state::HostModalState;
+use crate::network::session::{ScanSession, SessionId};
+use crate::state::host_filter::HostFilter;
+use crate::state::host_modal_state::{is_port_list_char, HostModalState};
+use crate::state::settings_state::SettingsState;
 use crate::ui::components::search_filter::SearchFilterOption;
-use crate::ui::{modal::Modal, notification::Notification, pages::PageContent};
+use crate::ui::{
+    event::Key,
+    modal::Modal,
+    notification::Notification,
+    pages::{Page, PageContent},
+};
 
 #[derive(Default, Clone)]
 pub struct ApplicationState {
     pub hosts: HostVec,
     pub query: String,
     pub port_query: Vec<u16>,
+    pub udp_port_query: Vec<u16>,
     pub query_state: bool,
     pub input_err: bool,
     pub search_run: bool,
+    // The running host search, cancelled when a new one starts
+    pub host_session: Option<ScanSession>,
+    pub curr_page: Page,
     pub curr_focus: PageContent,
     pub table_state: TableState,
     pub notification: Option<Notification>,
@@ -24,14 +39,39 @@ pub struct ApplicationState {
     // TODO: make dynamic?
     pub modal_state: Option<HostModalState>,
     pub search_filter_opt: SearchFilterOption,
+    // The `/` quick filter, on top of the filter option
+    pub host_filter: Option<HostFilter>,
+    pub filter_input: Option<String>,
     pub app_config: AppConfig,
+    pub settings_state: SettingsState,
+    // Where the settings page saves to, and the --profile it saves under
+    pub config_file: Option<PathBuf>,
+    pub config_profile: Option<String>,
     pub network_name_server: Option<Ipv4Addr>,
+    // Name of the network interface being scanned, if known
+    pub interface: Option<String>,
+    // Offers collected by the last DHCP probe
+    pub dhcp_offers: Vec<DhcpOffer>,
+    // Every IP/MAC pairing seen so far, kept across queries
+    pub arp_table: ArpTable,
     // TODO: should ui focus be part of application state?
     // pub focus: UiComponent
 }
 
 // State convenience methods
 impl ApplicationState {
+    // The host search or the open host modal's scans
+    pub fn session_live(&self, id: SessionId) -> bool {
+        self.host_session
+            .iter()
+            .chain(
+                self.modal_state
+                    .iter()
+                    .map(|modal_state| &modal_state.session),
+            )
+            .any(|session| session.id() == id && !session.is_cancelled())
+    }
+
     pub fn get_selected_host(&self) -> Option<Host> {
         if let Some(idx) = self.selected_host {
             return Some(self.filtered_hosts().nth(idx).unwrap().clone());
@@ -40,16 +80,76 @@ impl ApplicationState {
         None
     }
 
+    pub fn dhcp_offer_from(&self, ip: &Ipv4Addr) -> Option<&DhcpOffer> {
+        self.dhcp_offers.iter().find(|offer| offer.server_ip == *ip)
+    }
+
+    // Other hosts presenting one of this host's SSH keys, e.g. cloned VM images
+    pub fn ssh_key_shared_with(&self, host: &Host) -> Vec<Ipv4Addr> {
+        let fingerprints: Vec<&str> = match &host.ssh {
+            Some(ssh) => ssh
+                .host_keys
+                .iter()
+                .map(|k| k.fingerprint.as_str())
+                .collect(),
+            None => return vec![],
+        };
+
+        self.hosts
+            .iter()
+            .filter(|other| other.ip != host.ip)
+            .filter(|other| match &other.ssh {
+                Some(ssh) => ssh
+                    .host_keys
+                    .iter()
+                    .any(|k| fingerprints.contains(&k.fingerprint.as_str())),
+                None => false,
+            })
+            .map(|other| other.ip)
+            .collect()
+    }
+
+    // Whether the key is typed into the focused text input. Commands aren't
+    // looked up for it, so a key bound to one can still be typed.
+    pub fn types_key(&self, key: Key) -> bool {
+        let c = match key {
+            Key::Char(c) | Key::Shift(c) => c,
+            _ => return false,
+        };
+        if self.settings_state.input.is_some() || self.filter_input.is_some() {
+            return true;
+        }
+        match (&self.modal, &self.modal_state) {
+            (Some(_), Some(modal_state)) => {
+                modal_state.port_input_focused() && is_port_list_char(c)
+            }
+            (Some(_), None) => false,
+            (None, _) => {
+                self.curr_page == Page::MainPage
+                    && self.curr_focus == PageContent::QueryInput
+                    && is_query_char(c)
+            }
+        }
+    }
+
     pub fn filtered_hosts(&self) -> impl Iterator<Item = &Host> {
         self.hosts
             .iter()
             .filter(move |&h| match self.search_filter_opt {
                 SearchFilterOption::ShowFound => {
-                    h.ping_res.is_some()
-                        || h.host_name.as_ref().unwrap_or(&Err(String::new())).is_ok()
+                    h.is_alive() || h.host_name.as_ref().unwrap_or(&Err(String::new())).is_ok()
                 }
                 SearchFilterOption::ShowAll => true,
                 SearchFilterOption::HasPort(idx) => h.tcp_ports.contains(&self.port_query[idx]),
             })
+            .filter(move |&h| match &self.host_filter {
+                Some(filter) => filter.matches(h),
+                None => true,
+            })
     }
 }
+
+// Characters of a host query like 10.0.0.0/24
+pub fn is_query_char(c: char) -> bool {
+    c.is_numeric() || c == '.' || c == '/'
+}
@@ -0,0 +1,240 @@
+// The `/` quick filter of the hosts table. A query is a list of terms that
+// all have to match:
+// - `up` or `down`: hosts that are alive or not
+// - `port:22`, `port:8000-8100`: an open TCP or UDP port in the range
+// - `ip:`, `name:`, `mac:`, `os:` followed by text: that field contains it
+// - any other word: the IP, host name, MAC, OS guess or open ports contain it
+// Text is matched ignoring case, and is a regex if wrapped in slashes, like
+// `name:/^nas-\d+/`.
+
+use crate::network::host::Host;
+
+use anyhow::{anyhow, Context, Result};
+use regex::{Regex, RegexBuilder};
+
+use std::ops::Range;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FilterField {
+    Ip,
+    Name,
+    Mac,
+    Os,
+    Ports,
+}
+
+const FILTER_FIELDS: [FilterField; 5] = [
+    FilterField::Ip,
+    FilterField::Name,
+    FilterField::Mac,
+    FilterField::Os,
+    FilterField::Ports,
+];
+
+impl FilterField {
+    // The text of the field as shown in the hosts table
+    pub fn text(&self, host: &Host) -> String {
+        match self {
+            FilterField::Ip => host.ip.to_string(),
+            FilterField::Name => match &host.host_name {
+                Some(Ok(name)) => name.to_owned(),
+                _ => String::new(),
+            },
+            FilterField::Mac => host.mac.map(|mac| mac.to_string()).unwrap_or_default(),
+            FilterField::Os => host
+                .os
+                .as_ref()
+                .map(|os| os.to_string())
+                .unwrap_or_default(),
+            FilterField::Ports => host
+                .tcp_ports
+                .iter()
+                .map(|p| p.to_string())
+                .chain(host.udp_ports.keys().map(|p| format!("{}/udp", p)))
+                .collect::<Vec<String>>()
+                .join(","),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Up,
+    Down,
+    Port(u16, u16),
+    // Text in one field, or in any of them
+    Text(Option<FilterField>, Regex),
+}
+
+#[derive(Clone, Debug)]
+pub struct HostFilter {
+    pub query: String,
+    terms: Vec<Term>,
+}
+
+fn parse_text(text: &str) -> Result<Regex> {
+    let pattern = match text
+        .strip_prefix('/')
+        .and_then(|rest| rest.strip_suffix('/'))
+    {
+        Some(pattern) if !pattern.is_empty() => pattern.to_owned(),
+        _ => regex::escape(text),
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()
+        .with_context(|| format!("Invalid regex {}", text))
+}
+
+fn parse_port_range(range: &str) -> Result<(u16, u16)> {
+    let port = |p: &str| {
+        p.parse::<u16>()
+            .map_err(|_| anyhow!("Invalid port {}, use port:22 or port:8000-8100", range))
+    };
+    match range.split_once('-') {
+        Some((start, end)) => Ok((port(start)?, port(end)?)),
+        None => port(range).map(|p| (p, p)),
+    }
+}
+
+impl HostFilter {
+    pub fn parse(query: &str) -> Result<HostFilter> {
+        let mut terms = vec![];
+        for word in query.split_whitespace() {
+            let term = match word.split_once(':') {
+                Some(("port", range)) => {
+                    let (start, end) = parse_port_range(range)?;
+                    Term::Port(start, end)
+                }
+                Some((key @ ("ip" | "name" | "mac" | "os"), text)) if !text.is_empty() => {
+                    let field = match key {
+                        "ip" => FilterField::Ip,
+                        "name" => FilterField::Name,
+                        "mac" => FilterField::Mac,
+                        _ => FilterField::Os,
+                    };
+                    Term::Text(Some(field), parse_text(text)?)
+                }
+                _ if word.eq_ignore_ascii_case("up") => Term::Up,
+                _ if word.eq_ignore_ascii_case("down") => Term::Down,
+                _ => Term::Text(None, parse_text(word)?),
+            };
+            terms.push(term);
+        }
+        Ok(HostFilter {
+            query: query.to_owned(),
+            terms,
+        })
+    }
+
+    pub fn matches(&self, host: &Host) -> bool {
+        self.terms.iter().all(|term| match term {
+            Term::Up => host.is_alive(),
+            Term::Down => !host.is_alive(),
+            Term::Port(start, end) => host
+                .tcp_ports
+                .iter()
+                .chain(host.udp_ports.keys())
+                .any(|port| (start..=end).contains(&port)),
+            Term::Text(Some(field), regex) => regex.is_match(&field.text(host)),
+            Term::Text(None, regex) => FILTER_FIELDS
+                .iter()
+                .any(|field| regex.is_match(&field.text(host))),
+        })
+    }
+
+    // Byte ranges of the field's text matched by the query, for highlighting
+    pub fn highlights(&self, field: FilterField, text: &str) -> Vec<Range<usize>> {
+        let mut ranges = vec![];
+        for term in &self.terms {
+            match term {
+                Term::Text(term_field, regex)
+                    if term_field.is_none() || *term_field == Some(field) =>
+                {
+                    ranges.extend(
+                        regex
+                            .find_iter(text)
+                            .filter(|found| !found.as_str().is_empty())
+                            .map(|found| found.range()),
+                    )
+                }
+                // Whole port numbers in the port list
+                Term::Port(start, end) if field == FilterField::Ports => {
+                    let mut offset = 0;
+                    for item in text.split(',') {
+                        let port = item.trim_end_matches("/udp").parse::<u16>();
+                        if matches!(port, Ok(port) if (start..=end).contains(&&port)) {
+                            ranges.push(offset..offset + item.len());
+                        }
+                        offset += item.len() + 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        ranges.sort_by_key(|range| range.start);
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    fn hosts() -> Vec<Host> {
+        let mut printer = Host::new(Ipv4Addr::new(10, 0, 0, 5));
+        printer.host_name = Some(Ok("Office-Printer.local".to_owned()));
+        printer.ping_res = Some(Duration::from_millis(3));
+        printer.tcp_ports.insert(631);
+
+        let mut nas = Host::new(Ipv4Addr::new(10, 0, 0, 22));
+        nas.host_name = Some(Ok("nas-2.local".to_owned()));
+        nas.ping_res = Some(Duration::from_millis(1));
+        nas.tcp_ports.insert(22);
+
+        let quiet = Host::new(Ipv4Addr::new(10, 0, 0, 99));
+        vec![printer, nas, quiet]
+    }
+
+    fn matching(query: &str) -> Vec<String> {
+        let filter = HostFilter::parse(query).unwrap();
+        hosts()
+            .iter()
+            .filter(|host| filter.matches(host))
+            .map(|host| host.ip.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_host_filter_terms() {
+        assert_eq!(matching("printer"), vec!["10.0.0.5"]);
+        assert_eq!(matching("port:22 up"), vec!["10.0.0.22"]);
+        assert_eq!(matching("port:600-700"), vec!["10.0.0.5"]);
+        assert_eq!(matching("down"), vec!["10.0.0.99"]);
+        // A bare number matches the IP or the ports
+        assert_eq!(matching("22"), vec!["10.0.0.22"]);
+        assert_eq!(matching("ip:22"), vec!["10.0.0.22"]);
+        assert_eq!(matching(r"name:/^nas-\d/"), vec!["10.0.0.22"]);
+        assert_eq!(matching("name:printer port:22").len(), 0);
+        assert_eq!(matching("").len(), 3);
+
+        assert!(HostFilter::parse("port:ssh").is_err());
+        assert!(HostFilter::parse("name:/(/").is_err());
+    }
+
+    #[test]
+    fn test_host_filter_highlights() {
+        let filter = HostFilter::parse("port:22 print").unwrap();
+        assert_eq!(
+            filter.highlights(FilterField::Name, "Office-Printer.local"),
+            vec![7..12]
+        );
+        assert_eq!(
+            filter.highlights(FilterField::Ports, "80,22,53/udp"),
+            vec![3..5]
+        );
+        assert!(filter.highlights(FilterField::Ip, "10.0.0.22").is_empty());
+    }
+}
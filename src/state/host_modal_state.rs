@@ -1,4 +1,11 @@
 use crate::network::host::Host;
+use crate::network::http::HttpInfo;
+use crate::network::port_list::get_port_desc;
+use crate::network::service_probes::{Protocol, ServiceId};
+use crate::network::session::ScanSession;
+use crate::network::ssh::SshInfo;
+use crate::network::tls::TlsCertInfo;
+use crate::network::udp_scan::UdpPortState;
 use crate::ui::event::Key;
 
 use std::time::Duration;
@@ -8,6 +15,12 @@ pub enum HostModalAction {
     SetSelected(usize),
     SetPortQueryInput(Key),
     SetPortScanResult(TcpPortScanResult),
+    SetUdpPortScanResult(u16, UdpPortState),
+    SetPortBanner(u16, String),
+    SetService(Protocol, u16, ServiceId),
+    SetTlsCert(u16, TlsCertInfo),
+    SetHttpInfo(u16, HttpInfo),
+    SetSshInfo(SshInfo),
     SetCommonPortsForScanning,
 }
 
@@ -18,6 +31,17 @@ pub struct TabsState {
 }
 
 pub type TcpPortScanResult = (u16, Option<Result<Duration, ()>>);
+pub type UdpPortScanResult = (u16, Option<UdpPortState>);
+
+// Index of the "TCP port scan" tab, its input edits the TCP port query
+pub const TCP_SCAN_TAB: usize = 2;
+// Index of the "UDP port scan" tab, its input edits the UDP port query
+pub const UDP_SCAN_TAB: usize = 4;
+
+// Characters of a port list like 22,80-90
+pub fn is_port_list_char(c: char) -> bool {
+    c.is_ascii_digit() || c == '-' || c == ','
+}
 
 #[derive(Clone, Debug)]
 pub struct HostModalState {
@@ -25,9 +49,26 @@ pub struct HostModalState {
     pub selected_host: Host,
     pub port_query: String,
     pub ports: Vec<TcpPortScanResult>,
+    pub udp_port_query: String,
+    pub udp_ports: Vec<UdpPortScanResult>,
+    // Scans started from the modal, cancelled when it closes
+    pub session: ScanSession,
 }
 
 impl HostModalState {
+    // The scan tabs with a port list input, keys typed there edit the list
+    pub fn port_input_focused(&self) -> bool {
+        matches!(self.tab_state.index, TCP_SCAN_TAB | UDP_SCAN_TAB)
+    }
+
+    // Label for an open port, the probe file's identification if there is one
+    pub fn port_label(&self, protocol: Protocol, port: u16) -> String {
+        match self.selected_host.services.get(&(protocol, port)) {
+            Some(service) => service.to_string(),
+            None => get_port_desc(&port).to_owned(),
+        }
+    }
+
     pub fn new(host: Host) -> Self {
         HostModalState {
             tab_state: TabsState {
@@ -35,12 +76,17 @@ impl HostModalState {
                     "Host info".to_owned(),
                     "Common TCP port scan".to_owned(),
                     "TCP port scan".to_owned(),
+                    "X.509 certificates".to_owned(),
+                    "UDP port scan".to_owned(),
                 ],
                 index: 0,
             },
             selected_host: host,
             port_query: String::new(),
             ports: Vec::new(),
+            udp_port_query: String::new(),
+            udp_ports: Vec::new(),
+            session: ScanSession::new(),
         }
     }
 }
@@ -1,5 +1,7 @@
 pub mod actions;
 pub mod application_state;
+pub mod host_filter;
 pub mod host_modal_state;
 pub mod reducers;
+pub mod settings_state;
 pub mod store;
@@ -1,13 +1,21 @@
 use super::actions::{Action, AppAction};
 use super::application_state::ApplicationState;
-use super::host_modal_state::{HostModalAction, HostModalState};
+use super::host_modal_state::{HostModalAction, HostModalState, UDP_SCAN_TAB};
+use super::settings_state::SETTING_FIELDS;
+use crate::network::arp::ArpConflict;
+use crate::network::dhcp::{describe_servers, server_count};
+use crate::network::os_fingerprint::classify;
 use crate::network::port_list::COMMON_PORTS;
+use crate::network::udp_scan::UdpPortState;
 use crate::network::{host::Host, tcp_ping::parse_portlist};
 
+use pnet::util::MacAddr;
+use std::net::Ipv4Addr;
+
 use crate::ui::{
     event::Key,
     modal::{Modal, ModalType},
-    notification::Notification,
+    notification::{Notification, NotificationLevel},
 };
 
 pub trait Reducer<T: Action> {
@@ -26,9 +34,29 @@ impl Reducer<AppAction> for AppReducer {
             }
 
             // TODO: O(n), use a hashed data structure?
-            AppAction::UpdateHost(host) => {
+            AppAction::UpdateHost(mut host) => {
                 if let Some(idx) = state.hosts.iter().position(|h| h.ip == host.ip) {
+                    // Keep a MAC we already learned from ARP traffic
+                    if host.mac.is_none() {
+                        host.mac = state.hosts[idx].mac;
+                    }
+                    // and an OS guess from sniffed replies
+                    if host.os.is_none() {
+                        host.os = state.hosts[idx].os.clone();
+                    }
+                    // A sniffed reply also tells the TTL the ping itself couldn't see,
+                    // if it's the one that proved the host alive
+                    if let (Some(liveness), Some(os)) = (host.liveness.as_mut(), host.os.as_ref()) {
+                        if os.traits.proves(liveness) {
+                            liveness.ttl.get_or_insert(os.traits.ttl);
+                        }
+                    }
+
+                    if let Some(mac) = host.mac {
+                        state = record_arp(state, host.ip, mac);
+                    }
                     state.hosts[idx] = host;
+                    state = check_ssh_keys(state, idx);
                 }
                 state
             }
@@ -49,6 +77,15 @@ impl Reducer<AppAction> for AppReducer {
                 state
             }
 
+            AppAction::SetUdpPortQuery(pquery) => {
+                match pquery.map(|pq| parse_portlist(&pq)) {
+                    Some(Ok(ports)) => state.udp_port_query = ports,
+                    Some(Err(_)) => {}
+                    None => state.udp_port_query.clear(),
+                }
+                state
+            }
+
             AppAction::SetInputErr(err) => {
                 state.input_err = err;
                 state
@@ -58,6 +95,11 @@ impl Reducer<AppAction> for AppReducer {
 
             AppAction::SetHostSearchRun(run) => {
                 state.search_run = run;
+                if !run {
+                    if let Some(session) = state.host_session.take() {
+                        session.cancel();
+                    }
+                }
                 if run {
                     let notif = Notification::info(
                         "Status",
@@ -68,6 +110,18 @@ impl Reducer<AppAction> for AppReducer {
                 state
             }
 
+            AppAction::StartHostSearch(session) => {
+                if let Some(old) = state.host_session.replace(session) {
+                    old.cancel();
+                }
+                AppReducer::reduce(AppAction::SetHostSearchRun(true), state)
+            }
+
+            AppAction::SessionResult(id, action) => match state.session_live(id) {
+                true => AppReducer::reduce(*action, state),
+                false => state,
+            },
+
             AppAction::NewQuery(hosts) => {
                 state.hosts = hosts.iter().map(|h| Host::new(*h)).collect();
                 state.search_run = true;
@@ -84,6 +138,12 @@ impl Reducer<AppAction> for AppReducer {
                 state
             }
 
+            AppAction::SetPage(page) => {
+                state.curr_page = page;
+                state.settings_state.input = None;
+                state
+            }
+
             AppAction::SetNotification(notif) => {
                 state.notification = notif;
                 state
@@ -92,7 +152,7 @@ impl Reducer<AppAction> for AppReducer {
             AppAction::SetModal(modal) => {
                 if modal.is_none() {
                     state.selected_host = None;
-                    state.modal_state = None
+                    state = close_modal_state(state);
                 }
                 state.modal = modal;
                 state
@@ -102,6 +162,7 @@ impl Reducer<AppAction> for AppReducer {
                 let notif = Notification::info("Status", "Host search complete");
                 state.query_state = true;
                 state.search_run = false;
+                state.host_session = None;
                 state.notification = Some(notif);
                 state
             }
@@ -111,6 +172,16 @@ impl Reducer<AppAction> for AppReducer {
                 state
             }
 
+            AppAction::SetHostFilter(filter) => {
+                state.host_filter = filter;
+                state
+            }
+
+            AppAction::SetFilterInput(input) => {
+                state.filter_input = input;
+                state
+            }
+
             AppAction::SetSelectedHost(host) => {
                 state.selected_host = host;
                 match state.selected_host {
@@ -125,14 +196,18 @@ impl Reducer<AppAction> for AppReducer {
                     }
                     None => {
                         state.modal = None;
-                        state.modal_state = None;
+                        state = close_modal_state(state);
                     }
                 }
                 state
             }
 
             AppAction::SetModalAction(action) => {
-                let mut modal_state = state.modal_state.clone().unwrap();
+                // The modal may have closed while the action was on its way
+                let mut modal_state = match state.modal_state.clone() {
+                    Some(modal_state) => modal_state,
+                    None => return state,
+                };
                 match action {
                     HostModalAction::SetSelected(idx) => {
                         modal_state.tab_state.index = idx;
@@ -140,22 +215,31 @@ impl Reducer<AppAction> for AppReducer {
                     }
 
                     HostModalAction::SetPortQueryInput(key) => {
+                        let udp = modal_state.tab_state.index == UDP_SCAN_TAB;
+                        let query = match udp {
+                            true => &mut modal_state.udp_port_query,
+                            false => &mut modal_state.port_query,
+                        };
                         match key {
-                            Key::Char(c) => modal_state.port_query.push(c),
+                            Key::Char(c) => query.push(c),
                             Key::Backspace => {
-                                let qlen = modal_state.port_query.len();
-                                if qlen > 0 {
-                                    modal_state.port_query =
-                                        modal_state.port_query[..qlen - 1].to_string()
-                                }
+                                query.pop();
                             }
                             _ => {}
                         }
 
                         // TODO: don't need to do this every time an input event happens?
-                        if let Ok(ports) = parse_portlist(&modal_state.port_query) {
+                        if let Ok(ports) = parse_portlist(query) {
                             // TODO: performance evaluation
-                            modal_state.ports = ports.iter().map(|p| (*p, None)).collect();
+                            match udp {
+                                true => {
+                                    modal_state.udp_ports =
+                                        ports.iter().map(|p| (*p, None)).collect()
+                                }
+                                false => {
+                                    modal_state.ports = ports.iter().map(|p| (*p, None)).collect()
+                                }
+                            }
                         }
 
                         state.modal_state = Some(modal_state);
@@ -183,6 +267,107 @@ impl Reducer<AppAction> for AppReducer {
                         state.modal_state = Some(modal_state);
                     }
 
+                    HostModalAction::SetUdpPortScanResult(port, port_state) => {
+                        if let Some(res) = modal_state.udp_ports.iter_mut().find(|p| p.0 == port) {
+                            res.1 = Some(port_state);
+                        }
+
+                        if port_state != UdpPortState::Closed {
+                            if let Some(idx) = state
+                                .hosts
+                                .iter()
+                                .position(|h| h.ip == modal_state.selected_host.ip)
+                            {
+                                state.hosts[idx].udp_ports.insert(port, port_state);
+                            }
+                            modal_state.selected_host.udp_ports.insert(port, port_state);
+                        }
+
+                        state.modal_state = Some(modal_state);
+                    }
+
+                    HostModalAction::SetService(protocol, port, service) => {
+                        if let Some(idx) = state
+                            .hosts
+                            .iter()
+                            .position(|h| h.ip == modal_state.selected_host.ip)
+                        {
+                            state.hosts[idx]
+                                .services
+                                .insert((protocol, port), service.clone());
+                        }
+                        modal_state
+                            .selected_host
+                            .services
+                            .insert((protocol, port), service);
+
+                        state.modal_state = Some(modal_state);
+                    }
+
+                    HostModalAction::SetPortBanner(port, banner) => {
+                        if let Some(idx) = state
+                            .hosts
+                            .iter()
+                            .position(|h| h.ip == modal_state.selected_host.ip)
+                        {
+                            state.hosts[idx].banners.insert(port, banner.clone());
+                        }
+                        modal_state.selected_host.banners.insert(port, banner);
+
+                        state.modal_state = Some(modal_state);
+                    }
+
+                    HostModalAction::SetTlsCert(port, tls_info) => {
+                        if let Some(warning) = tls_info.warning() {
+                            state.notification = Some(Notification::new(
+                                "TLS certificate",
+                                &format!(
+                                    "{}:{} certificate is {}",
+                                    modal_state.selected_host.ip, port, warning
+                                ),
+                                NotificationLevel::Warn,
+                            ));
+                        }
+
+                        if let Some(idx) = state
+                            .hosts
+                            .iter()
+                            .position(|h| h.ip == modal_state.selected_host.ip)
+                        {
+                            state.hosts[idx].tls_certs.insert(port, tls_info.clone());
+                        }
+                        modal_state.selected_host.tls_certs.insert(port, tls_info);
+
+                        state.modal_state = Some(modal_state);
+                    }
+
+                    HostModalAction::SetHttpInfo(port, http_info) => {
+                        if let Some(idx) = state
+                            .hosts
+                            .iter()
+                            .position(|h| h.ip == modal_state.selected_host.ip)
+                        {
+                            state.hosts[idx].http.insert(port, http_info.clone());
+                        }
+                        modal_state.selected_host.http.insert(port, http_info);
+
+                        state.modal_state = Some(modal_state);
+                    }
+
+                    HostModalAction::SetSshInfo(ssh) => {
+                        if let Some(idx) = state
+                            .hosts
+                            .iter()
+                            .position(|h| h.ip == modal_state.selected_host.ip)
+                        {
+                            state.hosts[idx].ssh = Some(ssh.clone());
+                            state = check_ssh_keys(state, idx);
+                        }
+                        modal_state.selected_host.ssh = Some(ssh);
+
+                        state.modal_state = Some(modal_state);
+                    }
+
                     // TODO use this for custom port query
                     HostModalAction::SetCommonPortsForScanning => {
                         for port in COMMON_PORTS.iter() {
@@ -209,19 +394,171 @@ impl Reducer<AppAction> for AppReducer {
                 state
             }
 
+            AppAction::SetConfigFile(path) => {
+                state.config_file = path;
+                state
+            }
+
+            AppAction::SetConfigProfile(profile) => {
+                state.config_profile = profile;
+                state
+            }
+
+            AppAction::SetSettingsSelected(idx) => {
+                state.settings_state.selected = idx.min(SETTING_FIELDS.len() - 1);
+                state
+            }
+
+            AppAction::SetSettingsInput(input) => {
+                state.settings_state.input = input;
+                state
+            }
+
+            AppAction::SettingEdited(field) => {
+                if !state.settings_state.edited.contains(&field) {
+                    state.settings_state.edited.push(field);
+                }
+                state
+            }
+
             AppAction::SetNetworkNameServer(name_server) => {
                 state.network_name_server = Some(name_server);
                 state
             }
+
+            AppAction::SetInterface(iface) => {
+                state.interface = iface;
+                state
+            }
+
+            // Keep the most confident guess, later replies of the same kind
+            // (e.g. more SYN-ACKs) only replace it if they're at least as good
+            AppAction::OsTraitsSeen(ip, traits) => {
+                if let Some(host) = state.hosts.iter_mut().find(|h| h.ip == ip) {
+                    if let Some(liveness) = host.liveness.as_mut() {
+                        if traits.proves(liveness) {
+                            liveness.ttl.get_or_insert(traits.ttl);
+                        }
+                    }
+                    if let Some(guess) = classify(&traits) {
+                        if !matches!(&host.os, Some(old) if guess.confidence < old.confidence) {
+                            host.os = Some(guess);
+                        }
+                    }
+                }
+                state
+            }
+
+            AppAction::ArpSeen(ip, mac) => {
+                if let Some(host) = state.hosts.iter_mut().find(|h| h.ip == ip) {
+                    if host.mac.is_none() {
+                        host.mac = Some(mac);
+                    }
+                }
+                record_arp(state, ip, mac)
+            }
+
+            AppAction::SetDhcpOffers(offers) => {
+                let servers = describe_servers(&offers);
+
+                let notif = match server_count(&offers) {
+                    0 => Notification::info("DHCP probe", "No DHCP servers answered"),
+                    1 => Notification::info("DHCP probe", &format!("DHCP server: {}", servers)),
+                    n => Notification::new(
+                        "DHCP probe",
+                        &format!("{} DHCP servers answered: {}", n, servers),
+                        NotificationLevel::Warn,
+                    ),
+                };
+
+                state.dhcp_offers = offers;
+                state.notification = Some(notif);
+                state
+            }
             _ => state,
         }
     }
 }
 
+// Stops the modal's scans along with it
+fn close_modal_state(mut state: ApplicationState) -> ApplicationState {
+    if let Some(modal_state) = state.modal_state.take() {
+        modal_state.session.cancel();
+    }
+    state
+}
+
+fn check_ssh_keys(mut state: ApplicationState, idx: usize) -> ApplicationState {
+    let host = &state.hosts[idx];
+    let (ip, key_changed) = match &host.ssh {
+        Some(ssh) => (host.ip, ssh.key_changed),
+        None => return state,
+    };
+
+    let shared = state.ssh_key_shared_with(host);
+    if key_changed {
+        state.notification = Some(Notification::new(
+            "SSH host key changed",
+            &format!("{} has a different host key than in the last scan", ip),
+            NotificationLevel::Warn,
+        ));
+    } else if !shared.is_empty() {
+        state.notification = Some(Notification::new(
+            "Duplicate SSH host key",
+            &format!(
+                "{} shares its host key with {}",
+                ip,
+                shared
+                    .iter()
+                    .map(|ip| ip.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            NotificationLevel::Warn,
+        ));
+    }
+    state
+}
+
+// Record an IP/MAC pairing and warn when it reveals an address conflict
+fn record_arp(mut state: ApplicationState, ip: Ipv4Addr, mac: MacAddr) -> ApplicationState {
+    let message = match state.arp_table.record(ip, mac) {
+        Some(ArpConflict::Ip) => format!(
+            "{} is claimed by {}",
+            ip,
+            join(&state.arp_table.macs_for(&ip))
+        ),
+        Some(ArpConflict::Mac) => format!(
+            "{} is seen on {}",
+            mac,
+            join(&state.arp_table.ips_for(&mac))
+        ),
+        None => return state,
+    };
+    state.notification = Some(Notification::new(
+        "ARP conflict",
+        &message,
+        NotificationLevel::Warn,
+    ));
+    state
+}
+
+fn join<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::net::Ipv4Addr;
+    use crate::network::dhcp::DhcpOffer;
+    use crate::network::host::PingType;
+    use crate::network::os_fingerprint::PacketTraits;
+    use crate::network::probe::{LivenessEvidence, ResponseKind};
+    use crate::network::session::ScanSession;
 
     const DEFAULT_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
 
@@ -301,6 +638,35 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_stale_session_results_dropped() {
+        let host_ip = Ipv4Addr::new(10, 0, 1, 1);
+        let init_state = ApplicationState {
+            hosts: vec![Host::new(host_ip)],
+            ..Default::default()
+        };
+        let old = ScanSession::new();
+        let new = ScanSession::new();
+
+        let state = AppReducer::reduce(AppAction::StartHostSearch(old.clone()), init_state);
+        let state = AppReducer::reduce(AppAction::StartHostSearch(new.clone()), state);
+        assert!(old.is_cancelled());
+
+        let mut host = Host::new(host_ip);
+        host.ping_done = true;
+        let state = AppReducer::reduce(old.result(AppAction::UpdateHost(host.clone())), state);
+        let state = AppReducer::reduce(old.result(AppAction::QueryComplete), state);
+        assert!(!state.hosts[0].ping_done);
+        assert!(state.search_run);
+
+        let state = AppReducer::reduce(new.result(AppAction::UpdateHost(host)), state);
+        assert!(state.hosts[0].ping_done);
+
+        // Stopping the search cancels its session too
+        let state = AppReducer::reduce(AppAction::SetHostSearchRun(false), state);
+        assert!(new.is_cancelled() && state.host_session.is_none());
+    }
+
     #[test]
     fn test_action_set_selected_host() {
         let host_ip = Ipv4Addr::new(10, 0, 1, 1);
@@ -323,6 +689,192 @@ mod test {
         assert_eq!(new_state.get_selected_host(), None);
     }
 
+    #[test]
+    fn test_action_modal_closed() {
+        // A late scan result for a closed modal is dropped
+        let action = AppAction::SetModalAction(HostModalAction::SetUdpPortScanResult(
+            53,
+            UdpPortState::Open,
+        ));
+        let new_state = test_helper_reduce_state(action, None);
+
+        assert!(new_state.modal_state.is_none());
+    }
+
+    #[test]
+    fn test_action_udp_port_scan() {
+        let init_state = ApplicationState {
+            hosts: vec![Host::new(DEFAULT_ADDR)],
+            ..Default::default()
+        };
+        let mut state =
+            test_helper_reduce_state(AppAction::SetSelectedHost(Some(0)), Some(init_state));
+
+        state = AppReducer::reduce(
+            AppAction::SetModalAction(HostModalAction::SetSelected(UDP_SCAN_TAB)),
+            state,
+        );
+        for c in "53,161".chars() {
+            state = AppReducer::reduce(
+                AppAction::SetModalAction(HostModalAction::SetPortQueryInput(Key::Char(c))),
+                state,
+            );
+        }
+        let modal_state = state.modal_state.as_ref().unwrap();
+        assert_eq!(modal_state.udp_ports, vec![(53, None), (161, None)]);
+        assert!(modal_state.port_query.is_empty());
+
+        state = AppReducer::reduce(
+            AppAction::SetModalAction(HostModalAction::SetUdpPortScanResult(
+                53,
+                UdpPortState::Open,
+            )),
+            state,
+        );
+        state = AppReducer::reduce(
+            AppAction::SetModalAction(HostModalAction::SetUdpPortScanResult(
+                161,
+                UdpPortState::Closed,
+            )),
+            state,
+        );
+
+        assert_eq!(
+            state.modal_state.unwrap().udp_ports[1],
+            (161, Some(UdpPortState::Closed))
+        );
+        assert_eq!(state.hosts[0].udp_ports.len(), 1);
+        assert_eq!(state.hosts[0].udp_ports[&53], UdpPortState::Open);
+    }
+
+    #[test]
+    fn test_action_set_dhcp_offers() {
+        let offer = DhcpOffer {
+            server_ip: Ipv4Addr::new(10, 0, 0, 1),
+            server_mac: None,
+            offered_ip: Ipv4Addr::new(10, 0, 0, 50),
+            subnet_mask: None,
+            lease_secs: Some(3600),
+            routers: vec![],
+            dns_servers: vec![],
+        };
+        let mut rogue = offer.clone();
+        rogue.server_ip = Ipv4Addr::new(10, 0, 0, 66);
+
+        let action = AppAction::SetDhcpOffers(vec![offer.clone()]);
+        let new_state = test_helper_reduce_state(action, None);
+        assert!(new_state.dhcp_offer_from(&offer.server_ip).is_some());
+        assert!(matches!(
+            new_state.notification.unwrap().level,
+            NotificationLevel::Info
+        ));
+
+        // Several offers from one server list it once
+        let action = AppAction::SetDhcpOffers(vec![offer.clone(), offer.clone()]);
+        let notif = test_helper_reduce_state(action, None).notification.unwrap();
+        assert_eq!(notif.message, "DHCP server: 10.0.0.1");
+
+        let action = AppAction::SetDhcpOffers(vec![offer, rogue]);
+        let new_state = test_helper_reduce_state(action, None);
+        let notif = new_state.notification.unwrap();
+        assert!(matches!(notif.level, NotificationLevel::Warn));
+        assert_eq!(
+            notif.message,
+            "2 DHCP servers answered: 10.0.0.1, 10.0.0.66"
+        );
+    }
+
+    #[test]
+    fn test_action_arp_seen_conflict() {
+        let init_state = ApplicationState {
+            hosts: vec![Host::new(DEFAULT_ADDR)],
+            ..Default::default()
+        };
+
+        let mac = MacAddr::new(0x02, 0, 0, 0, 0, 0x01);
+        let other_mac = MacAddr::new(0x02, 0, 0, 0, 0, 0x02);
+
+        let new_state =
+            test_helper_reduce_state(AppAction::ArpSeen(DEFAULT_ADDR, mac), Some(init_state));
+        assert_eq!(new_state.hosts[0].mac, Some(mac));
+        assert!(new_state.notification.is_none());
+
+        // A ping result without a MAC keeps the one learned from ARP
+        let new_state =
+            AppReducer::reduce(AppAction::UpdateHost(Host::new(DEFAULT_ADDR)), new_state);
+        assert_eq!(new_state.hosts[0].mac, Some(mac));
+
+        let new_state = AppReducer::reduce(AppAction::ArpSeen(DEFAULT_ADDR, other_mac), new_state);
+        assert!(new_state.arp_table.is_conflict(&DEFAULT_ADDR));
+        assert_eq!(new_state.hosts[0].mac, Some(mac));
+        assert_eq!(
+            new_state.notification.as_ref().unwrap().message,
+            "10.0.0.1 is claimed by 02:00:00:00:00:01, 02:00:00:00:00:02"
+        );
+
+        // A device hopping to another address
+        let other_addr = Ipv4Addr::new(10, 0, 0, 2);
+        let new_state = AppReducer::reduce(AppAction::ArpSeen(other_addr, mac), new_state);
+        assert_eq!(
+            new_state.notification.unwrap().message,
+            "02:00:00:00:00:01 is seen on 10.0.0.1, 10.0.0.2"
+        );
+    }
+
+    #[test]
+    fn test_action_os_traits_seen() {
+        let init_state = ApplicationState {
+            hosts: vec![Host::new(DEFAULT_ADDR)],
+            ..Default::default()
+        };
+
+        let icmp = PacketTraits {
+            ttl: 63,
+            ..Default::default()
+        };
+        let syn_ack = PacketTraits {
+            ttl: 63,
+            window: Some(65160),
+            mss: Some(1460),
+            options: Some("M,S,T,N,W".to_owned()),
+            reply: Some(ResponseKind::TcpSynAck),
+            port: Some(443),
+        };
+
+        let new_state = test_helper_reduce_state(
+            AppAction::OsTraitsSeen(DEFAULT_ADDR, syn_ack),
+            Some(init_state),
+        );
+        assert_eq!(
+            new_state.hosts[0].os.as_ref().unwrap().to_string(),
+            "Linux (90%)"
+        );
+
+        // A weaker ICMP-only guess doesn't replace it, and neither does a new ping result
+        let new_state = AppReducer::reduce(AppAction::OsTraitsSeen(DEFAULT_ADDR, icmp), new_state);
+        let new_state =
+            AppReducer::reduce(AppAction::UpdateHost(Host::new(DEFAULT_ADDR)), new_state);
+        assert_eq!(new_state.hosts[0].os.as_ref().unwrap().confidence, 90);
+
+        // The SYN-ACK's TTL only goes with the ping it answered
+        let ping = |ping_type, port, reason| Host {
+            liveness: Some(LivenessEvidence {
+                ping_type,
+                port: Some(port),
+                reason,
+                ttl: None,
+            }),
+            ..Host::new(DEFAULT_ADDR)
+        };
+        let udp_ping = ping(PingType::UDP, 9989, ResponseKind::IcmpPortUnreachable);
+        let new_state = AppReducer::reduce(AppAction::UpdateHost(udp_ping), new_state);
+        assert_eq!(new_state.hosts[0].liveness.as_ref().unwrap().ttl, None);
+
+        let tcp_ping = ping(PingType::TCP, 443, ResponseKind::TcpSynAck);
+        let new_state = AppReducer::reduce(AppAction::UpdateHost(tcp_ping), new_state);
+        assert_eq!(new_state.hosts[0].liveness.as_ref().unwrap().ttl, Some(63));
+    }
+
     #[test]
     fn test_action_set_config_from_cli() {
         let nworkers = 50;
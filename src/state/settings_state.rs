@@ -0,0 +1,288 @@
+use crate::network::host::parse_resolvers;
+use crate::network::probe::parse_ping_methods;
+use crate::network::tcp_ping::{format_portlist, parse_portlist};
+use crate::state::actions::AppAction;
+use crate::state::application_state::ApplicationState;
+
+use anyhow::{anyhow, Result};
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Config values editable from the settings page
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SettingField {
+    Workers,
+    TickLen,
+    MaxRttTimeout,
+    PingTimeout,
+    PingRetries,
+    ScanDelay,
+    PingMethods,
+    PingMode,
+    PingPolicy,
+    TcpPingPorts,
+    TcpPorts,
+    UdpPorts,
+    Resolvers,
+}
+
+pub const SETTING_FIELDS: [SettingField; 13] = [
+    SettingField::Workers,
+    SettingField::TickLen,
+    SettingField::MaxRttTimeout,
+    SettingField::PingTimeout,
+    SettingField::PingRetries,
+    SettingField::ScanDelay,
+    SettingField::PingMethods,
+    SettingField::PingMode,
+    SettingField::PingPolicy,
+    SettingField::TcpPingPorts,
+    SettingField::TcpPorts,
+    SettingField::UdpPorts,
+    SettingField::Resolvers,
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct SettingsState {
+    pub selected: usize,
+    // Text of the field being edited
+    pub input: Option<String>,
+    // Fields changed this session, the only ones saved
+    pub edited: Vec<SettingField>,
+}
+
+fn parse_number<T: FromStr + PartialOrd + fmt::Display>(input: &str, min: T) -> Result<T> {
+    match input.trim().parse::<T>() {
+        Ok(val) if val >= min => Ok(val),
+        _ => Err(anyhow!("Expected a number of at least {}", min)),
+    }
+}
+
+fn parse_ports(input: &str, allow_empty: bool) -> Result<Vec<u16>> {
+    let input: String = input.split_whitespace().collect();
+    match parse_portlist(&input) {
+        Ok(ports) if !ports.is_empty() => Ok(ports),
+        _ if allow_empty && input.is_empty() => Ok(vec![]),
+        _ => Err(anyhow!("Expected ports like 22,80,8000-8100")),
+    }
+}
+
+fn join<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+impl SettingField {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SettingField::Workers => "Workers",
+            SettingField::TickLen => "UI tick length (ms)",
+            SettingField::MaxRttTimeout => "Max RTT timeout (ms)",
+            SettingField::PingTimeout => "Ping timeout (ms)",
+            SettingField::PingRetries => "Ping retries",
+            SettingField::ScanDelay => "Scan delay (ms)",
+            SettingField::PingMethods => "Ping probes",
+            SettingField::PingMode => "Ping mode",
+            SettingField::PingPolicy => "Ping policy",
+            SettingField::TcpPingPorts => "TCP ping ports",
+            SettingField::TcpPorts => "TCP scan ports",
+            SettingField::UdpPorts => "UDP scan ports",
+            SettingField::Resolvers => "Name resolvers",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            SettingField::Workers => "Hosts scanned at once",
+            SettingField::TickLen => "Redraw interval without key presses",
+            SettingField::MaxRttTimeout => "Longest wait for a TCP connect or name lookup",
+            SettingField::PingTimeout => "Wait for each UDP, ICMP or ARP ping",
+            SettingField::PingRetries => "UDP ping resends",
+            SettingField::ScanDelay => "Pause between ports of a host",
+            SettingField::PingMethods => "udp, tcp, icmp, arp, mdns, in order",
+            SettingField::PingMode => "One after the other or all at once",
+            SettingField::PingPolicy => "Any answer or every probe answering",
+            SettingField::TcpPingPorts => "Ports the TCP ping connects to",
+            SettingField::TcpPorts => "Scanned on live hosts, empty for none",
+            SettingField::UdpPorts => "Scanned on live hosts, empty for none",
+            SettingField::Resolvers => "mdns, dns, nbns, in order, empty for none",
+        }
+    }
+
+    // Fields with a fixed set of values are cycled through instead of typed
+    pub fn choices(&self) -> Option<&'static [&'static str]> {
+        match self {
+            SettingField::PingMode => Some(&["sequential", "parallel"]),
+            SettingField::PingPolicy => Some(&["any", "all"]),
+            _ => None,
+        }
+    }
+
+    pub fn value(&self, state: &ApplicationState) -> String {
+        let config = &state.app_config;
+        match self {
+            SettingField::Workers => config.nworkers.to_string(),
+            SettingField::TickLen => config.tick_len.to_string(),
+            SettingField::MaxRttTimeout => config.timing.max_rtt_timeout.as_millis().to_string(),
+            SettingField::PingTimeout => config.timing.ping_timeout.as_millis().to_string(),
+            SettingField::PingRetries => config.timing.ping_retries.to_string(),
+            SettingField::ScanDelay => config.timing.port_delay.as_millis().to_string(),
+            SettingField::PingMethods => join(&config.ping_methods).to_lowercase(),
+            SettingField::PingMode => config.ping_mode.to_string(),
+            SettingField::PingPolicy => config.ping_policy.to_string(),
+            SettingField::TcpPingPorts => format_portlist(&config.tcp_ping_ports),
+            SettingField::TcpPorts => format_portlist(&state.port_query),
+            SettingField::UdpPorts => format_portlist(&state.udp_port_query),
+            SettingField::Resolvers => join(&config.resolvers),
+        }
+    }
+
+    // The value after the current one, for fields with choices
+    pub fn next_choice(&self, state: &ApplicationState) -> Option<String> {
+        let choices = self.choices()?;
+        let value = self.value(state);
+        let idx = choices
+            .iter()
+            .position(|&choice| choice == value)
+            .unwrap_or(0);
+        Some(choices[(idx + 1) % choices.len()].to_owned())
+    }
+
+    // Validates the input, and returns the action that applies it
+    pub fn apply(&self, input: &str, state: &ApplicationState) -> Result<AppAction> {
+        let mut config = state.app_config.clone();
+        let ms = |min| parse_number(input, min).map(Duration::from_millis);
+        match self {
+            SettingField::Workers => config.nworkers = parse_number(input, 1)?,
+            SettingField::TickLen => config.tick_len = parse_number(input, 10)?,
+            SettingField::MaxRttTimeout => config.timing.max_rtt_timeout = ms(1)?,
+            SettingField::PingTimeout => config.timing.ping_timeout = ms(1)?,
+            SettingField::PingRetries => config.timing.ping_retries = parse_number(input, 0)?,
+            SettingField::ScanDelay => config.timing.port_delay = ms(0)?,
+            SettingField::PingMethods => {
+                config.ping_methods = parse_ping_methods(&input.replace(' ', ""))?
+            }
+            SettingField::PingMode => config.ping_mode = input.parse()?,
+            SettingField::PingPolicy => config.ping_policy = input.parse()?,
+            SettingField::TcpPingPorts => config.tcp_ping_ports = parse_ports(input, false)?,
+            SettingField::TcpPorts => {
+                let ports = parse_ports(input, true)?;
+                return Ok(AppAction::SetPortQuery(match ports.is_empty() {
+                    true => None,
+                    false => Some(format_portlist(&ports)),
+                }));
+            }
+            SettingField::UdpPorts => {
+                let ports = parse_ports(input, true)?;
+                return Ok(AppAction::SetUdpPortQuery(match ports.is_empty() {
+                    true => None,
+                    false => Some(format_portlist(&ports)),
+                }));
+            }
+            SettingField::Resolvers => config.resolvers = parse_resolvers(&input.replace(' ', ""))?,
+        }
+        Ok(AppAction::SetConfig(config))
+    }
+
+    // Where the value goes in the config file
+    pub fn config_key(&self) -> (&'static str, &'static str) {
+        match self {
+            SettingField::Workers => ("scan", "workers"),
+            SettingField::TickLen => ("ui", "tick_len"),
+            SettingField::MaxRttTimeout => ("timing", "max_rtt_timeout"),
+            SettingField::PingTimeout => ("timing", "ping_timeout"),
+            SettingField::PingRetries => ("timing", "max_retries"),
+            SettingField::ScanDelay => ("timing", "scan_delay"),
+            SettingField::PingMethods => ("scan", "ping"),
+            SettingField::PingMode => ("scan", "ping_mode"),
+            SettingField::PingPolicy => ("scan", "ping_policy"),
+            SettingField::TcpPingPorts => ("scan", "tcp_ping_ports"),
+            SettingField::TcpPorts => ("ports", "tcp"),
+            SettingField::UdpPorts => ("ports", "udp"),
+            SettingField::Resolvers => ("resolve", "resolvers"),
+        }
+    }
+
+    pub fn config_value(&self, state: &ApplicationState) -> toml_edit::Value {
+        let value = self.value(state);
+        match self {
+            SettingField::PingMethods | SettingField::Resolvers => value
+                .split(',')
+                .filter(|item| !item.is_empty())
+                .collect::<toml_edit::Array>()
+                .into(),
+            SettingField::PingMode
+            | SettingField::PingPolicy
+            | SettingField::TcpPingPorts
+            | SettingField::TcpPorts
+            | SettingField::UdpPorts => value.into(),
+            // The rest are numbers
+            _ => value.parse::<i64>().unwrap_or_default().into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::network::probe::PingMode;
+
+    #[test]
+    fn test_setting_validation() {
+        let state = ApplicationState::default();
+
+        assert!(SettingField::Workers.apply("0", &state).is_err());
+        assert!(SettingField::Workers.apply("many", &state).is_err());
+        match SettingField::Workers.apply(" 25 ", &state).unwrap() {
+            AppAction::SetConfig(config) => assert_eq!(config.nworkers, 25),
+            _ => panic!("Expected SetConfig"),
+        }
+
+        assert!(SettingField::TcpPingPorts.apply("", &state).is_err());
+        assert!(matches!(
+            SettingField::TcpPorts.apply("", &state).unwrap(),
+            AppAction::SetPortQuery(None)
+        ));
+        assert!(SettingField::PingMethods
+            .apply("udp,smoke", &state)
+            .is_err());
+
+        assert_eq!(
+            SettingField::PingMode.next_choice(&state).as_deref(),
+            Some("parallel")
+        );
+        match SettingField::PingMode.apply("parallel", &state).unwrap() {
+            AppAction::SetConfig(config) => assert_eq!(config.ping_mode, PingMode::Parallel),
+            _ => panic!("Expected SetConfig"),
+        }
+    }
+
+    #[test]
+    fn test_setting_config_values() {
+        let state = ApplicationState {
+            port_query: vec![22, 80, 81],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            SettingField::Workers.config_value(&state).as_integer(),
+            Some(100)
+        );
+        assert_eq!(
+            SettingField::TcpPorts.config_value(&state).as_str(),
+            Some("22,80-81")
+        );
+        assert_eq!(
+            SettingField::PingMethods
+                .config_value(&state)
+                .as_array()
+                .map(|methods| methods.len()),
+            Some(2)
+        );
+    }
+}
@@ -3,7 +3,7 @@ use tui::{
     backend::Backend,
     layout::{Rect, Alignment},
     text::{Span},
-    style::{Color, Style},
+    style::Style,
     widgets::{Paragraph, Block, Borders},
 };
 
@@ -35,7 +35,8 @@ pub fn draw_search_filter<B: Backend>(store: &AppStateStore, rect: Rect, f: &mut
         SearchFilterOption::HasPort(idx) => format!("Port {} open", store.state.port_query[idx])
     };
 
-    let filter_style = Style::default().fg(Color::Green);
+    let theme = &store.state.app_config.theme;
+    let filter_style = Style::default().fg(theme.ok);
 
     let span = Span::styled(
         format!("{} ▼", filter_str), 
@@ -46,8 +47,8 @@ pub fn draw_search_filter<B: Backend>(store: &AppStateStore, rect: Rect, f: &mut
         .borders(Borders::ALL)
         .style(
             match store.state.curr_focus {
-                PageContent::SearchFilters => border_style(true),
-                _ => border_style(false)
+                PageContent::SearchFilters => border_style(theme, true),
+                _ => border_style(theme, false)
             }
         )
         .title(selectable_title("Filter/sort", Style::default()));
@@ -1,13 +1,21 @@
-use tui::style::{Style, Color};
+use tui::style::{Modifier, Style};
 
-// TODO: use config here for custom styles
-pub fn border_style(selected: bool) -> Style {
+use crate::ui::theme::Theme;
+
+pub fn border_style(theme: &Theme, selected: bool) -> Style {
     Style::default()
         .fg(
             if selected {
-                Color::Yellow
+                theme.focus
             } else {
-                Color::White
+                theme.border
             }
         )
 }
+
+pub fn selected_style(theme: &Theme) -> Style {
+    Style::default()
+        .bg(theme.selected_bg)
+        .fg(theme.focus)
+        .add_modifier(Modifier::REVERSED)
+}
@@ -1,5 +1,7 @@
+use crate::ui::theme::Theme;
+
 use tui::{
-    style::{Color, Style},
+    style::Style,
     text::{Span, Spans},
     widgets::{Block, Borders, Paragraph},
 };
@@ -12,8 +14,9 @@ pub fn text_input<'a, T: Into<Spans<'a>>>(
     title: T,
     input: &'a str,
     style_state: InputStyleState,
+    theme: &Theme,
 ) -> Paragraph<'a> {
-    let selected_border_style = Style::default().fg(Color::Yellow);
+    let selected_border_style = Style::default().fg(theme.focus);
 
     Paragraph::new(Span::from(input)).block(
         Block::default()
@@ -3,7 +3,13 @@ use crossterm::{
     event::{read, poll, KeyCode, KeyEvent},
 };
 
+use anyhow::{anyhow, Result};
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
@@ -138,12 +144,91 @@ impl From<KeyEvent> for Key {
     }
 }
 
+impl Key {
+    // Uppercase letters and symbols come with or without shift depending on
+    // the terminal, so both are treated as the plain character
+    pub fn normalize(self) -> Key {
+        match self {
+            Key::Shift(c) => Key::Char(c),
+            key => key,
+        }
+    }
+}
+
+// Names used in the config file and the help, like "j", "ctrl-c" or "pagedown"
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.normalize() {
+            Key::Char(' ') => write!(f, "space"),
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Ctrl(' ') => write!(f, "ctrl-space"),
+            Key::Ctrl(c) => write!(f, "ctrl-{}", c),
+            Key::Tab => write!(f, "tab"),
+            Key::BackTab => write!(f, "backtab"),
+            Key::Up => write!(f, "up"),
+            Key::Down => write!(f, "down"),
+            Key::Left => write!(f, "left"),
+            Key::Right => write!(f, "right"),
+            Key::PageUp => write!(f, "pageup"),
+            Key::PageDown => write!(f, "pagedown"),
+            Key::Backspace => write!(f, "backspace"),
+            Key::Esc => write!(f, "esc"),
+            Key::Enter => write!(f, "enter"),
+            _ => write!(f, "unknown"),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Key> {
+        let char_of = |name: &str| {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ if name.eq_ignore_ascii_case("space") => Some(' '),
+                _ => None,
+            }
+        };
+
+        if let Some(c) = char_of(s) {
+            return Ok(Key::Char(c));
+        }
+        let lower = s.to_ascii_lowercase();
+        if let Some(name) = lower.strip_prefix("ctrl-").or_else(|| lower.strip_prefix("c-")) {
+            return match char_of(name) {
+                Some(c) => Ok(Key::Ctrl(c)),
+                None => Err(anyhow!("Invalid key {}", s)),
+            };
+        }
+        match lower.as_str() {
+            "tab" => Ok(Key::Tab),
+            "backtab" | "shift-tab" => Ok(Key::BackTab),
+            "up" => Ok(Key::Up),
+            "down" => Ok(Key::Down),
+            "left" => Ok(Key::Left),
+            "right" => Ok(Key::Right),
+            "pageup" | "pgup" => Ok(Key::PageUp),
+            "pagedown" | "pgdn" => Ok(Key::PageDown),
+            "backspace" => Ok(Key::Backspace),
+            "esc" | "escape" => Ok(Key::Esc),
+            "enter" | "return" => Ok(Key::Enter),
+            _ => Err(anyhow!(
+                "Invalid key {}, use a character, ctrl-<char>, space, enter, tab, up, pagedown, ...",
+                s
+            )),
+        }
+    }
+}
+
 // TODO: this should use a findi event instead of key
 pub struct EventReader {
     pub recv: mpsc::Receiver<Event>
 }
 
-pub fn async_event_reader(poll_interval: usize) -> EventReader {
+// The tick length is read before each poll, so it can change while running
+pub fn async_event_reader(tick_len: Arc<AtomicUsize>) -> EventReader {
     let (tx, rx) = mpsc::channel();
 
     let evt_tx = tx;
@@ -152,7 +237,8 @@ pub fn async_event_reader(poll_interval: usize) -> EventReader {
             // Check for events at AppConfig.tick_len ms
             // if no events, send timer tick event
             // so the ui loop doesn't have to constantly spin
-            if poll(Duration::from_millis(poll_interval as u64)).unwrap() {
+            let poll_interval = tick_len.load(Ordering::Relaxed) as u64;
+            if poll(Duration::from_millis(poll_interval)).unwrap() {
                 let evt = read().unwrap();
                 if let event::Event::Key(kevt) = evt {
                     evt_tx.send(Event::Key(Key::from(kevt))).unwrap();
@@ -0,0 +1,419 @@
+// Named commands and the keys bound to them. Enter, tab, esc, backspace and
+// typing into inputs aren't commands, they always do the same thing.
+
+use crate::ui::event::Key;
+
+use anyhow::{anyhow, Result};
+
+use std::str::FromStr;
+
+// Where a command is handled, keys only need to be unique across contexts
+// that are active at the same time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum KeyContext {
+    // Everywhere, unless typing into an input
+    Global,
+    // The main page without a modal
+    Main,
+    // Moving through the hosts table or the settings
+    Lists,
+    // The focused hosts table
+    HostTable,
+    HostModal,
+    SettingsPage,
+}
+
+impl KeyContext {
+    fn overlaps(self, other: KeyContext) -> bool {
+        use KeyContext::*;
+        match (self, other) {
+            (a, b) if a == b => true,
+            (Global, _) | (_, Global) => true,
+            (Main, Lists) | (Lists, Main) => true,
+            (Main, HostTable) | (HostTable, Main) => true,
+            (Lists, HostTable) | (HostTable, Lists) => true,
+            (Lists, SettingsPage) | (SettingsPage, Lists) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    Quit,
+    Settings,
+    FocusHosts,
+    FocusSearch,
+    FocusFilters,
+    DhcpProbe,
+    Help,
+    QuickFilter,
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    First,
+    Last,
+    CopyIp,
+    CopyHostname,
+    WakeHost,
+    NextTab,
+    PrevTab,
+    TabHostInfo,
+    TabCommonPorts,
+    TabTcpPorts,
+    TabCertificates,
+    TabUdpPorts,
+    SaveSettings,
+}
+
+pub const COMMANDS: [Command; 25] = [
+    Command::Quit,
+    Command::Settings,
+    Command::FocusHosts,
+    Command::FocusSearch,
+    Command::FocusFilters,
+    Command::DhcpProbe,
+    Command::Help,
+    Command::QuickFilter,
+    Command::Down,
+    Command::Up,
+    Command::PageDown,
+    Command::PageUp,
+    Command::First,
+    Command::Last,
+    Command::CopyIp,
+    Command::CopyHostname,
+    Command::WakeHost,
+    Command::NextTab,
+    Command::PrevTab,
+    Command::TabHostInfo,
+    Command::TabCommonPorts,
+    Command::TabTcpPorts,
+    Command::TabCertificates,
+    Command::TabUdpPorts,
+    Command::SaveSettings,
+];
+
+impl Command {
+    // Name in the [keys] section of the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Quit => "quit",
+            Command::Settings => "settings",
+            Command::FocusHosts => "focus_hosts",
+            Command::FocusSearch => "focus_search",
+            Command::FocusFilters => "focus_filters",
+            Command::DhcpProbe => "dhcp",
+            Command::Help => "help",
+            Command::QuickFilter => "filter",
+            Command::Down => "down",
+            Command::Up => "up",
+            Command::PageDown => "page_down",
+            Command::PageUp => "page_up",
+            Command::First => "first",
+            Command::Last => "last",
+            Command::CopyIp => "copy_ip",
+            Command::CopyHostname => "copy_hostname",
+            Command::WakeHost => "wake",
+            Command::NextTab => "next_tab",
+            Command::PrevTab => "prev_tab",
+            Command::TabHostInfo => "tab_info",
+            Command::TabCommonPorts => "tab_common_ports",
+            Command::TabTcpPorts => "tab_tcp_ports",
+            Command::TabCertificates => "tab_certificates",
+            Command::TabUdpPorts => "tab_udp_ports",
+            Command::SaveSettings => "save_settings",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Command::Quit => "Quit",
+            Command::Settings => "Open or close the settings",
+            Command::FocusHosts => "Go to the hosts table",
+            Command::FocusSearch => "Go to the query search (enter begins the query)",
+            Command::FocusFilters => "Go to the filters",
+            Command::DhcpProbe => "Look for DHCP servers (hosts marked DHCP)",
+            Command::Help => "Show this help",
+            Command::QuickFilter => "Filter the hosts, like port:22 name:printer up",
+            Command::Down => "Move down one row",
+            Command::Up => "Move up one row",
+            Command::PageDown => "Move down 20 rows",
+            Command::PageUp => "Move up 20 rows",
+            Command::First => "Go to the first row",
+            Command::Last => "Go to the last row",
+            Command::CopyIp => "Copy the IP address of the host to the clipboard",
+            Command::CopyHostname => "Copy the host name to the clipboard",
+            Command::WakeHost => "Send a Wake-on-LAN packet to the host",
+            Command::NextTab => "Go to the next tab",
+            Command::PrevTab => "Go to the previous tab",
+            Command::TabHostInfo => "Go to the host info",
+            Command::TabCommonPorts => "Go to the common TCP port scan",
+            Command::TabTcpPorts => "Go to the TCP port scan",
+            Command::TabCertificates => "Go to the X.509 certificates",
+            Command::TabUdpPorts => "Go to the UDP port scan",
+            Command::SaveSettings => "Save the settings to the config file",
+        }
+    }
+
+    pub fn context(&self) -> KeyContext {
+        match self {
+            Command::Quit | Command::Settings => KeyContext::Global,
+            Command::FocusHosts
+            | Command::FocusSearch
+            | Command::FocusFilters
+            | Command::DhcpProbe
+            | Command::Help
+            | Command::QuickFilter => KeyContext::Main,
+            Command::Down
+            | Command::Up
+            | Command::PageDown
+            | Command::PageUp
+            | Command::First
+            | Command::Last => KeyContext::Lists,
+            Command::CopyIp | Command::CopyHostname => KeyContext::HostTable,
+            Command::WakeHost
+            | Command::NextTab
+            | Command::PrevTab
+            | Command::TabHostInfo
+            | Command::TabCommonPorts
+            | Command::TabTcpPorts
+            | Command::TabCertificates
+            | Command::TabUdpPorts => KeyContext::HostModal,
+            Command::SaveSettings => KeyContext::SettingsPage,
+        }
+    }
+
+    fn default_keys(&self) -> Vec<Key> {
+        match self {
+            Command::Quit => vec![Key::Char('q')],
+            Command::Settings => vec![Key::Char(',')],
+            Command::FocusHosts => vec![Key::Char('h')],
+            Command::FocusSearch => vec![Key::Char('s')],
+            Command::FocusFilters => vec![Key::Char('f')],
+            Command::DhcpProbe => vec![Key::Char('d')],
+            Command::Help => vec![Key::Char('?')],
+            Command::QuickFilter => vec![Key::Char('/')],
+            Command::Down => vec![Key::Char('j'), Key::Down],
+            Command::Up => vec![Key::Char('k'), Key::Up],
+            Command::PageDown => vec![Key::Char('J'), Key::Char(' '), Key::PageDown],
+            Command::PageUp => vec![Key::Char('K'), Key::Ctrl(' '), Key::PageUp],
+            Command::First => vec![Key::Char('g')],
+            Command::Last => vec![Key::Char('G')],
+            Command::CopyIp => vec![Key::Char('c')],
+            Command::CopyHostname => vec![Key::Char('C')],
+            Command::WakeHost => vec![Key::Char('w')],
+            Command::NextTab => vec![Key::Right],
+            Command::PrevTab => vec![Key::Left],
+            Command::TabHostInfo => vec![Key::Char('h')],
+            Command::TabCommonPorts => vec![Key::Char('c')],
+            Command::TabTcpPorts => vec![Key::Char('t')],
+            Command::TabCertificates => vec![Key::Char('x')],
+            Command::TabUdpPorts => vec![Key::Char('u')],
+            Command::SaveSettings => vec![Key::Char('w')],
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Command> {
+        COMMANDS
+            .iter()
+            .find(|command| command.name() == s)
+            .copied()
+            .ok_or_else(|| anyhow!("Unknown command {}", s))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+    bindings: Vec<(Key, Command)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let bindings = COMMANDS
+            .iter()
+            .flat_map(|&command| {
+                command
+                    .default_keys()
+                    .into_iter()
+                    .map(move |key| (key, command))
+            })
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    // The defaults with the keys of some commands replaced. A key taken from
+    // another command's defaults is unbound there.
+    pub fn with_bindings(overrides: Vec<(Command, Vec<Key>)>) -> Result<Keymap> {
+        let overrides: Vec<(Command, Vec<Key>)> = overrides
+            .into_iter()
+            .map(|(command, keys)| (command, keys.into_iter().map(Key::normalize).collect()))
+            .collect();
+
+        let mut keymap = Keymap::default();
+        keymap.bindings.retain(|&(key, command)| {
+            !overrides.iter().any(|(other, keys)| {
+                *other == command
+                    || (keys.contains(&key) && other.context().overlaps(command.context()))
+            })
+        });
+        for (command, keys) in overrides {
+            keymap
+                .bindings
+                .extend(keys.into_iter().map(|key| (key, command)));
+        }
+
+        for (idx, &(key, command)) in keymap.bindings.iter().enumerate() {
+            let clash = keymap.bindings[idx + 1..]
+                .iter()
+                .find(|&&(other_key, other)| {
+                    other_key == key && other.context().overlaps(command.context())
+                });
+            if let Some((_, other)) = clash {
+                return Err(anyhow!(
+                    "Key {} is bound to both {} and {}",
+                    key,
+                    command.name(),
+                    other.name()
+                ));
+            }
+        }
+        Ok(keymap)
+    }
+
+    // The command bound to the key among those handled in the given contexts
+    pub fn command(&self, key: Key, contexts: &[KeyContext]) -> Option<Command> {
+        let key = key.normalize();
+        self.bindings
+            .iter()
+            .find(|&&(bound, command)| bound == key && contexts.contains(&command.context()))
+            .map(|&(_, command)| command)
+    }
+
+    pub fn keys(&self, command: Command) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|&&(_, bound)| bound == command)
+            .map(|&(key, _)| key)
+            .collect()
+    }
+
+    // Like "j/down", for the help
+    pub fn key_names(&self, command: Command) -> String {
+        let keys = self.keys(command);
+        match keys.is_empty() {
+            true => "(unbound)".to_owned(),
+            false => keys
+                .iter()
+                .map(|key| key.to_string())
+                .collect::<Vec<String>>()
+                .join("/"),
+        }
+    }
+
+    // The first key bound to the command, for short hints
+    pub fn key_name(&self, command: Command) -> String {
+        self.keys(command)
+            .first()
+            .map_or_else(|| "(unbound)".to_owned(), |key| key.to_string())
+    }
+
+    // One help line per command in the context
+    pub fn help(&self, context: KeyContext) -> String {
+        COMMANDS
+            .iter()
+            .filter(|command| command.context() == context)
+            .map(|&command| format!("- {}: {}\n", self.key_names(command), command.description()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        for (name, key) in [
+            ("j", Key::Char('j')),
+            ("J", Key::Char('J')),
+            ("space", Key::Char(' ')),
+            ("ctrl-n", Key::Ctrl('n')),
+            ("C-space", Key::Ctrl(' ')),
+            ("PageDown", Key::PageDown),
+            ("shift-tab", Key::BackTab),
+        ] {
+            assert_eq!(name.parse::<Key>().unwrap(), key, "{}", name);
+        }
+        assert!("hyper-x".parse::<Key>().is_err());
+        assert!("jj".parse::<Key>().is_err());
+        assert_eq!(Key::Ctrl(' ').to_string(), "ctrl-space");
+        assert_eq!(Key::Shift('G').to_string(), "G");
+    }
+
+    #[test]
+    fn test_default_keymap() {
+        // The defaults don't clash
+        Keymap::with_bindings(vec![]).unwrap();
+
+        let keymap = Keymap::default();
+        let table = [KeyContext::Lists, KeyContext::HostTable];
+        assert_eq!(keymap.command(Key::Char('j'), &table), Some(Command::Down));
+        assert_eq!(
+            keymap.command(Key::Shift('J'), &table),
+            Some(Command::PageDown)
+        );
+        assert_eq!(
+            keymap.command(Key::Shift('C'), &table),
+            Some(Command::CopyHostname)
+        );
+        assert_eq!(keymap.command(Key::Char('w'), &table), None);
+        assert_eq!(
+            keymap.command(Key::Char('w'), &[KeyContext::HostModal]),
+            Some(Command::WakeHost)
+        );
+        assert_eq!(
+            keymap.command(Key::Char('x'), &[KeyContext::HostModal]),
+            Some(Command::TabCertificates)
+        );
+        assert_eq!(
+            keymap.command(Key::Right, &[KeyContext::HostModal]),
+            Some(Command::NextTab)
+        );
+        assert!(keymap
+            .help(KeyContext::Lists)
+            .contains("- j/down: Move down"));
+    }
+
+    #[test]
+    fn test_keymap_overrides() {
+        // Colemak style navigation, taking 'h' from the focus shortcut
+        let keymap = Keymap::with_bindings(vec![
+            (Command::Down, vec![Key::Char('n'), Key::Down]),
+            (Command::Up, vec![Key::Char('e'), Key::Up]),
+            (Command::First, vec![Key::Char('h')]),
+        ])
+        .unwrap();
+        let table = [KeyContext::Main, KeyContext::Lists, KeyContext::HostTable];
+        assert_eq!(keymap.command(Key::Char('n'), &table), Some(Command::Down));
+        assert_eq!(keymap.command(Key::Char('j'), &table), None);
+        assert_eq!(keymap.command(Key::Char('h'), &table), Some(Command::First));
+        assert_eq!(keymap.key_names(Command::FocusHosts), "(unbound)");
+        // Only overlapping contexts lose the key
+        assert_eq!(keymap.key_name(Command::SaveSettings), "w");
+
+        let err = Keymap::with_bindings(vec![
+            (Command::Down, vec![Key::Char('n')]),
+            (Command::Up, vec![Key::Char('n')]),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("Key n"), "{}", err);
+    }
+}
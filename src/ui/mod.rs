@@ -8,10 +8,13 @@ pub mod modal;
 pub mod notification;
 pub mod pages;
 pub mod event;
+pub mod keymap;
+pub mod theme;
 
-use pages::{Page, draw_page, handle_page_events};
+use pages::{draw_page, handle_page_events};
 
 use event::{Event, Key};
+use keymap::{Command, KeyContext};
 use crate::state::store::SharedAppStateStore;
 use crate::GLOBAL_RUN;
 
@@ -27,7 +30,8 @@ use tui::{
 use anyhow::Result;
 
 use std::io::{self, Write};
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::ops::DerefMut;
 
 pub fn ui_loop(store: SharedAppStateStore) -> Result<()> {
@@ -40,11 +44,11 @@ pub fn ui_loop(store: SharedAppStateStore) -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let tick_len = store.lock().unwrap().state.app_config.tick_len;
+    let tick_len = Arc::new(AtomicUsize::new(
+        store.lock().unwrap().state.app_config.tick_len,
+    ));
 
-    let evt_stream = event::async_event_reader(tick_len);
-
-    let curr_page = Page::MainPage;
+    let evt_stream = event::async_event_reader(tick_len.clone());
 
     terminal.clear()?;
 
@@ -57,6 +61,10 @@ pub fn ui_loop(store: SharedAppStateStore) -> Result<()> {
         let notif = lock_store.state.notification.clone();
         let modal = lock_store.state.modal.clone();
         let selected = lock_store.state.get_selected_host();
+        let curr_page = lock_store.state.curr_page;
+        let theme = lock_store.state.app_config.theme;
+        // The settings page may have changed it
+        tick_len.store(lock_store.state.app_config.tick_len, Ordering::Relaxed);
         drop(lock_store);
 
         // Main draw loop
@@ -67,7 +75,7 @@ pub fn ui_loop(store: SharedAppStateStore) -> Result<()> {
             // ie Modal, notification, etc
 
             if let Some(notif) = notif {
-                notification::draw_notification(notif, f)
+                notification::draw_notification(notif, &theme, f)
             }
 
             if let Some(modal) = modal {
@@ -75,7 +83,7 @@ pub fn ui_loop(store: SharedAppStateStore) -> Result<()> {
                 if let Some(sel_host) = selected {
                     modal::draw_host_modal(modal, &sel_host, store.clone(), f)
                 } else {
-                    modal::draw_modal(modal, f)
+                    modal::draw_modal(modal, &theme, f)
                 }
             }
         })?;
@@ -93,6 +101,9 @@ pub fn ui_loop(store: SharedAppStateStore) -> Result<()> {
                 Event::Key(key) => {
                     let mut lstore = store.lock().unwrap();
 
+                    // The quit key may be typed into an input
+                    let editing = lstore.state.types_key(key);
+
                     // TODO: use match
                     if lstore.state.modal.is_some() {
                         modal::handle_modal_event(key, lstore.deref_mut(), store.clone())
@@ -100,8 +111,15 @@ pub fn ui_loop(store: SharedAppStateStore) -> Result<()> {
                         handle_page_events(&curr_page, key, lstore.deref_mut(), store.clone());
                     }
 
+                    let keymap = &lstore.state.app_config.keymap;
                     match key {
-                        Key::Ctrl('c') | Key::Char('q') => GLOBAL_RUN.store(false, Ordering::Release),
+                        Key::Ctrl('c') => GLOBAL_RUN.store(false, Ordering::Release),
+                        _ if !editing
+                            && keymap.command(key, &[KeyContext::Global])
+                                == Some(Command::Quit) =>
+                        {
+                            GLOBAL_RUN.store(false, Ordering::Release)
+                        }
                         _ => {}
                     }
                 },
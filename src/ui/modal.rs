@@ -1,10 +1,10 @@
 // https://github.com/fdehau/tui-rs/blob/master/examples/popup.rs
 
 use crate::{
-    network::dispatch_common_port_scan,
+    network::{dispatch_common_port_scan, dispatch_wake_on_lan},
     state::{
         actions::AppAction,
-        host_modal_state::HostModalAction,
+        host_modal_state::{HostModalAction, TCP_SCAN_TAB, UDP_SCAN_TAB},
         store::{AppStateStore, SharedAppStateStore},
     },
 };
@@ -14,11 +14,14 @@ use super::components::selectable_title::selectable_title;
 use crate::ui::{
     components::text_input::{text_input, InputStyleState},
     event::Key,
+    keymap::{Command, KeyContext},
     pages::PageContent,
+    theme::Theme,
 };
 
 use crate::network::{
-    dispatch_port_scan, host::Host, init_host_search, input_parse, port_list::get_port_desc,
+    dispatch_port_scan, dispatch_udp_port_scan, host::Host, init_host_search, input_parse,
+    service_probes::Protocol, snmp::format_uptime, udp_scan::UdpPortState,
 };
 
 use std::convert::TryInto;
@@ -26,12 +29,21 @@ use std::convert::TryInto;
 use tui::{
     backend::Backend,
     layout::{Alignment, Constraint, Corner, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Span, Spans},
     widgets::{Block, Borders, Clear, Gauge, List, ListItem, Paragraph, Row, Table, Tabs, Wrap},
     Frame,
 };
 
+// The command going to each host modal tab, in the order of the titles
+const TAB_COMMANDS: [Command; 5] = [
+    Command::TabHostInfo,
+    Command::TabCommonPorts,
+    Command::TabTcpPorts,
+    Command::TabCertificates,
+    Command::TabUdpPorts,
+];
+
 #[derive(Clone)]
 pub enum ModalType {
     YesNo,
@@ -105,7 +117,7 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
 }
 
 // TODO: draw specific modal types here
-pub fn draw_modal<B: Backend>(modal: Modal, f: &mut Frame<B>) {
+pub fn draw_modal<B: Backend>(modal: Modal, theme: &Theme, f: &mut Frame<B>) {
     let block = Block::default().title(modal.title).borders(Borders::ALL);
     let area = match modal.modal_type {
         ModalType::YesNo | ModalType::Custom => centered_rect(40, 30, f.size()),
@@ -119,8 +131,8 @@ pub fn draw_modal<B: Backend>(modal: Modal, f: &mut Frame<B>) {
     let mut no_style = Style::default();
 
     match modal.selected {
-        ModalOpt::Yes => yes_style = yes_style.fg(Color::Green),
-        ModalOpt::No => no_style = no_style.fg(Color::Green),
+        ModalOpt::Yes => yes_style = yes_style.fg(theme.ok),
+        ModalOpt::No => no_style = no_style.fg(theme.ok),
     };
 
     let span = match modal.modal_type {
@@ -204,6 +216,8 @@ pub fn draw_host_modal<B: Backend>(
     let area = centered_rect(30, 70, f.size());
     let store = lstore.lock().unwrap();
     let modal_state = store.state.modal_state.as_ref().unwrap();
+    let dhcp_offer = store.state.dhcp_offer_from(&host.ip);
+    let theme = store.state.app_config.theme;
 
     f.render_widget(Clear, area);
     f.render_widget(block, area);
@@ -214,35 +228,52 @@ pub fn draw_host_modal<B: Backend>(
         .constraints([Constraint::Max(2), Constraint::Percentage(80)].as_ref())
         .split(area);
 
+    // The first character is underlined while it's the key going to the tab
+    let keymap = &store.state.app_config.keymap;
     let titles = modal_state
         .tab_state
         .titles
         .iter()
-        .map(|t| {
+        .zip(TAB_COMMANDS.iter())
+        .map(|(t, &command)| {
             let (first, rest) = t.split_at(1);
-            Spans::from(vec![
-                Span::styled(first, Style::default().add_modifier(Modifier::UNDERLINED)),
-                Span::styled(rest, Style::default().fg(Color::Green)),
-            ])
+            let shortcut = keymap.keys(command).first()
+                == Some(&Key::Char(
+                    first.to_ascii_lowercase().chars().next().unwrap(),
+                ));
+            match shortcut {
+                true => Spans::from(vec![
+                    Span::styled(first, Style::default().add_modifier(Modifier::UNDERLINED)),
+                    Span::styled(rest, Style::default().fg(theme.ok)),
+                ]),
+                false => Spans::from(Span::styled(t.as_str(), Style::default().fg(theme.ok))),
+            }
         })
         .collect();
 
     let tabs = Tabs::new(titles)
         .block(Block::default().borders(Borders::BOTTOM))
         .select(modal_state.tab_state.index)
-        .style(Style::default().fg(Color::Cyan))
+        .style(Style::default().fg(theme.accent))
         .highlight_style(
             Style::default()
                 .add_modifier(Modifier::BOLD)
-                .bg(Color::Black),
+                .bg(theme.selected_bg),
         );
     f.render_widget(tabs, btn_layout[0]);
 
     // TODO: extract out the modal rendering
     // TODO: don't use tab state index for this, use an enum
     if modal_state.tab_state.index == 0 {
-        let fields = vec![
+        let mut fields = vec![
             ("IP", host.ip.to_string()),
+            (
+                "MAC address",
+                match host.mac {
+                    Some(mac) => mac.to_string(),
+                    None => "--".to_owned(),
+                },
+            ),
             (
                 "Response time",
                 match host.ping_res {
@@ -252,11 +283,24 @@ pub fn draw_host_modal<B: Backend>(
             ),
             (
                 "Ping type",
-                match host.ping_type {
-                    Some(ptype) => ptype.to_string(),
-                    None => "--".to_owned(),
+                match (host.ping_type, host.ping_port) {
+                    (Some(ptype), Some(port)) => format!(
+                        "{} (port {} {})",
+                        ptype,
+                        port,
+                        match host.tcp_ports.contains(&port) {
+                            true => "open",
+                            false => "closed",
+                        }
+                    ),
+                    (Some(ptype), None) => ptype.to_string(),
+                    _ => "--".to_owned(),
                 },
             ),
+            (
+                "Alive because",
+                host.alive_reason().unwrap_or_else(|| "--".to_owned()),
+            ),
             (
                 "Hostanme",
                 match &host.host_name {
@@ -271,8 +315,124 @@ pub fn draw_host_modal<B: Backend>(
                     None => "--".to_owned(),
                 },
             ),
+            (
+                "OS guess",
+                match &host.os {
+                    Some(os) => format!("{}, {}", os, os.traits.evidence()),
+                    None => "--".to_owned(),
+                },
+            ),
+            (
+                "ARP conflict",
+                if store.state.arp_table.is_conflict(&host.ip) {
+                    format!(
+                        "⚠ Claimed by {}",
+                        store
+                            .state
+                            .arp_table
+                            .macs_for(&host.ip)
+                            .iter()
+                            .map(|mac| mac.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    )
+                } else {
+                    match store.state.arp_table.other_ips(&host.ip).as_slice() {
+                        [] => "--".to_owned(),
+                        others => format!(
+                            "⚠ MAC also seen on {}",
+                            others
+                                .iter()
+                                .map(|ip| ip.to_string())
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        ),
+                    }
+                },
+            ),
+            (
+                "DHCP server",
+                match dhcp_offer {
+                    Some(offer) => format!(
+                        "Yes, offers {} ({})",
+                        offer.offered_ip,
+                        match offer.lease_secs {
+                            Some(secs) => format!("{}s lease", secs),
+                            None => "no lease time".to_owned(),
+                        }
+                    ),
+                    None => "--".to_owned(),
+                },
+            ),
         ];
 
+        for (port, http_info) in &host.http {
+            let mut web = format!(
+                "{}/{} {}",
+                port,
+                if http_info.https { "https" } else { "http" },
+                http_info.status
+            );
+            if let Some(title) = &http_info.title {
+                web += &format!(" \"{}\"", title);
+            }
+            if let Some(server) = &http_info.server {
+                web += &format!(" ({})", server);
+            }
+            if let Some(location) = &http_info.location {
+                web += &format!(" → {}", location);
+            }
+            if let Some(hash) = http_info.favicon_hash {
+                web += &format!(" favicon {}", hash);
+            }
+            fields.push(("Web", web));
+        }
+
+        if let Some(ssh) = &host.ssh {
+            fields.push(("SSH banner", ssh.banner.to_owned()));
+            for key in &ssh.host_keys {
+                fields.push((
+                    "SSH host key",
+                    format!("{} {}", key.fingerprint, key.key_type),
+                ));
+            }
+
+            let shared = store.state.ssh_key_shared_with(host);
+            if ssh.key_changed {
+                fields.push((
+                    "SSH warning",
+                    "⚠ Host key changed since the last scan".to_owned(),
+                ));
+            } else if !shared.is_empty() {
+                fields.push((
+                    "SSH warning",
+                    format!(
+                        "⚠ Same host key as {}",
+                        shared
+                            .iter()
+                            .map(|ip| ip.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
+                ));
+            }
+        }
+
+        if let Some(snmp) = &host.snmp {
+            let or_none = |val: &Option<String>| val.clone().unwrap_or_else(|| "--".to_owned());
+            fields.extend(vec![
+                ("SNMP community", snmp.community.to_owned()),
+                ("SNMP name", or_none(&snmp.name)),
+                ("SNMP descr", or_none(&snmp.descr)),
+                ("SNMP object ID", or_none(&snmp.object_id)),
+                (
+                    "SNMP uptime",
+                    snmp.uptime.map_or_else(|| "--".to_owned(), format_uptime),
+                ),
+                ("SNMP contact", or_none(&snmp.contact)),
+            ]);
+        }
+
         const SPACING: usize = 15;
 
         // array_map is unstable as of now
@@ -301,6 +461,148 @@ pub fn draw_host_modal<B: Backend>(
             .split(btn_layout[1]);
 
         f.render_widget(host_list, text_layout[1]);
+    } else if modal_state.tab_state.index == 3 {
+        const SPACING: usize = 12;
+
+        let mut cert_items: Vec<ListItem> = vec![];
+        for (port, tls_info) in &host.tls_certs {
+            let (title, title_color) = match tls_info.warning() {
+                Some(warning) => (format!("Port {} ⚠ {}", port, warning), theme.alert),
+                None => (format!("Port {}", port), theme.ok),
+            };
+            cert_items.push(ListItem::new(Span::styled(
+                title,
+                Style::default()
+                    .fg(title_color)
+                    .add_modifier(Modifier::BOLD),
+            )));
+
+            let fields = vec![
+                ("Subject", tls_info.subject.to_owned()),
+                ("SANs", tls_info.sans.join(", ")),
+                ("Issuer", tls_info.issuer.to_owned()),
+                ("Not before", tls_info.not_before.to_owned()),
+                ("Not after", tls_info.not_after.to_owned()),
+                ("Key", tls_info.key_type.to_owned()),
+                ("Protocol", tls_info.protocol.to_owned()),
+                ("Cipher", tls_info.cipher.to_owned()),
+            ];
+            cert_items.extend(fields.into_iter().map(|(field, val)| {
+                ListItem::new(Spans::from(vec![
+                    Span::styled(field, Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" ".repeat(SPACING - field.len() + 3)),
+                    Span::from(val),
+                ]))
+            }));
+            cert_items.push(ListItem::new(""));
+        }
+
+        if cert_items.is_empty() {
+            cert_items.push(ListItem::new(
+                "No certificates yet, run a port scan that finds an open TLS port (e.g. 443, 993).",
+            ));
+        }
+
+        let cert_list = List::new(cert_items).start_corner(Corner::TopLeft);
+
+        let text_layout = Layout::default()
+            .margin(2)
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(5),
+                Constraint::Percentage(90),
+                Constraint::Percentage(5),
+            ])
+            .split(btn_layout[1]);
+
+        f.render_widget(cert_list, text_layout[1]);
+    } else if modal_state.tab_state.index == UDP_SCAN_TAB {
+        let layout = Layout::default()
+            .margin(2)
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Percentage(20),
+                Constraint::Length(3),
+                Constraint::Min(5),
+                Constraint::Length(1),
+            ])
+            .split(btn_layout[1]);
+
+        let instructions = Span::from(
+            r#"Enter UDP port numbers comma separated or as a range
+            e.g. "53,123,161,500""#,
+        );
+        let parag = Paragraph::new(instructions).wrap(Wrap { trim: false });
+        f.render_widget(parag, layout[0]);
+
+        let input_title = selectable_title("Range", Style::default());
+        let input_field = text_input(
+            input_title,
+            &modal_state.udp_port_query,
+            InputStyleState::Focused,
+            &theme,
+        );
+        f.render_widget(input_field, layout[1]);
+
+        f.set_cursor(
+            layout[1].x + modal_state.udp_port_query.len() as u16 + 1,
+            layout[1].y + 1,
+        );
+
+        // Closed ports are left out like in the TCP scan
+        let header = Row::new(vec!["Port", "Status", "Common use"]);
+        let rows: Vec<Row> = modal_state
+            .udp_ports
+            .iter()
+            .filter_map(|(port, stat)| match stat {
+                Some(UdpPortState::Closed) | None => None,
+                Some(port_state) => Some((port, port_state)),
+            })
+            .map(|(port, port_state)| {
+                let color = match port_state {
+                    UdpPortState::Open => theme.ok,
+                    _ => theme.warn,
+                };
+                Row::new(vec![
+                    port.to_string(),
+                    port_state.to_string(),
+                    modal_state.port_label(Protocol::Udp, *port),
+                ])
+                .style(Style::default().fg(color))
+            })
+            .collect();
+
+        let table_block = Block::default()
+            .borders(Borders::TOP | Borders::BOTTOM)
+            .title(selectable_title("Ports", Style::default()));
+
+        let table = Table::new(rows).header(header).block(table_block).widths(
+            [
+                Constraint::Length(7),
+                Constraint::Length(15),
+                Constraint::Percentage(50),
+            ]
+            .as_ref(),
+        );
+        f.render_widget(table, layout[2]);
+
+        let pcnt_done: u16 = (modal_state
+            .udp_ports
+            .iter()
+            .filter(|p| p.1.is_some())
+            .count()
+            * 100)
+            .checked_div(modal_state.udp_ports.len())
+            .unwrap_or(0)
+            .try_into()
+            .unwrap();
+
+        let gauge = Gauge::default()
+            .block(Block::default())
+            .gauge_style(Style::default().fg(theme.focus))
+            .percent(pcnt_done);
+
+        f.render_widget(gauge, layout[3]);
     } else {
         let layout = Layout::default()
             .margin(2)
@@ -335,6 +637,7 @@ pub fn draw_host_modal<B: Backend>(
                 input_title,
                 &modal_state.port_query,
                 InputStyleState::Focused,
+                &theme,
             );
             f.render_widget(input_field, layout[1]);
 
@@ -345,7 +648,7 @@ pub fn draw_host_modal<B: Backend>(
         }
 
         // Render port scan result table
-        let header = Row::new(vec!["Port", "Status", "Common use"]);
+        let header = Row::new(vec!["Port", "Status", "Common use", "Banner"]);
 
         // let rows: Vec<Row> = modal_state.ports.iter().map(|(port, stat)| {
         // TODO: make port lookup an optional feature
@@ -358,9 +661,15 @@ pub fn draw_host_modal<B: Backend>(
                 Row::new(vec![
                     port.to_string(),
                     format!("✓ ({:?})", stat.unwrap().unwrap()),
-                    get_port_desc(port).to_owned(),
+                    modal_state.port_label(Protocol::Tcp, *port),
+                    modal_state
+                        .selected_host
+                        .banners
+                        .get(port)
+                        .cloned()
+                        .unwrap_or_default(),
                 ])
-                .style(Style::default().fg(Color::Green))
+                .style(Style::default().fg(theme.ok))
             })
             .collect();
 
@@ -374,7 +683,8 @@ pub fn draw_host_modal<B: Backend>(
             [
                 Constraint::Length(7),
                 Constraint::Length(15),
-                Constraint::Percentage(40),
+                Constraint::Percentage(30),
+                Constraint::Percentage(50),
             ]
             .as_ref(),
         );
@@ -390,7 +700,7 @@ pub fn draw_host_modal<B: Backend>(
 
         let gauge = Gauge::default()
             .block(Block::default())
-            .gauge_style(Style::default().fg(Color::Yellow))
+            .gauge_style(Style::default().fg(theme.focus))
             .percent(pcnt_done);
 
         f.render_widget(gauge, layout[3]);
@@ -412,8 +722,10 @@ pub fn handle_modal_event(key: Key, store: &mut AppStateStore, lstore: SharedApp
                 Some(modal_state) => {
                     if modal_state.tab_state.index == 1 {
                         dispatch_common_port_scan(lstore);
-                    } else if modal_state.tab_state.index == 2 {
+                    } else if modal_state.tab_state.index == TCP_SCAN_TAB {
                         dispatch_port_scan(lstore)
+                    } else if modal_state.tab_state.index == UDP_SCAN_TAB {
+                        dispatch_udp_port_scan(lstore)
                     }
                 }
                 None => {
@@ -449,34 +761,40 @@ pub fn handle_modal_event(key: Key, store: &mut AppStateStore, lstore: SharedApp
 
         Key::Esc => store.dispatch(AppAction::SetModal(None)),
 
-        Key::Char(c) => {
-            match &mut store.state.modal_state {
-                Some(modal_state) => {
-                    let mut idx: usize = modal_state.tab_state.index;
-                    for (i, title) in modal_state.tab_state.titles.iter().enumerate() {
-                        if title.to_ascii_lowercase().starts_with(c) {
-                            idx = i
-                        }
-                    }
-                    store.dispatch(AppAction::SetModalAction(HostModalAction::SetSelected(idx)));
-
-                    // TODO: clean up selected modal logic and use a match here
-                    if c.is_ascii_digit() || c == '-' || c == ',' {
-                        store.dispatch(AppAction::SetModalAction(
-                            HostModalAction::SetPortQueryInput(key),
-                        ))
-                    }
-                }
-
-                None => {}
-            }
-        }
-
         Key::Backspace => store.dispatch(AppAction::SetModalAction(
             HostModalAction::SetPortQueryInput(key),
         )),
 
-        _ => {}
+        // Typed into the port list of a scan tab, even if bound to a command
+        _ if store.state.types_key(key) => store.dispatch(AppAction::SetModalAction(
+            HostModalAction::SetPortQueryInput(key),
+        )),
+
+        _ => {
+            let modal_state = match &store.state.modal_state {
+                Some(modal_state) => modal_state,
+                None => return,
+            };
+            let tabs = modal_state.tab_state.titles.len();
+            let idx = modal_state.tab_state.index;
+            let tab = match store
+                .state
+                .app_config
+                .keymap
+                .command(key, &[KeyContext::HostModal])
+            {
+                // Wake the selected host
+                Some(Command::WakeHost) => return dispatch_wake_on_lan(lstore),
+                Some(Command::NextTab) => (idx + 1) % tabs,
+                Some(Command::PrevTab) => (idx + tabs - 1) % tabs,
+                Some(command) => match TAB_COMMANDS.iter().position(|&c| c == command) {
+                    Some(tab) => tab,
+                    None => return,
+                },
+                None => return,
+            };
+            store.dispatch(AppAction::SetModalAction(HostModalAction::SetSelected(tab)))
+        }
     }
 }
 
@@ -484,7 +802,11 @@ pub fn handle_modal_event(key: Key, store: &mut AppStateStore, lstore: SharedApp
 mod test {
     use super::*;
     use crate::network::host::Host;
-    use crate::state::{application_state::ApplicationState, store::SharedAppStateStore};
+    use crate::state::{
+        application_state::ApplicationState, host_modal_state::HostModalState,
+        store::SharedAppStateStore,
+    };
+    use crate::ui::keymap::Keymap;
     use std::net::Ipv4Addr;
     use std::ops::DerefMut;
     use std::sync::{Arc, Mutex};
@@ -553,6 +875,39 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_modal_event_tab_commands() {
+        // Emacs style tab keys, and wake on a digit typed into the port list
+        let mut store = AppStateStore::new();
+        store.state.app_config.keymap = Keymap::with_bindings(vec![
+            (Command::NextTab, vec![Key::Ctrl('f')]),
+            (Command::PrevTab, vec![Key::Ctrl('b')]),
+            (Command::WakeHost, vec![Key::Char('1')]),
+        ])
+        .unwrap();
+        store.state.modal = Some(Modal::new("Host", "", ModalType::Custom));
+        store.state.modal_state = Some(HostModalState::new(Host::new(Ipv4Addr::new(10, 0, 0, 1))));
+
+        let events: [(Key, (usize, String)); 7] = [
+            (Key::Ctrl('f'), (1, "".to_owned())),
+            (Key::Ctrl('b'), (0, "".to_owned())),
+            (Key::Ctrl('b'), (UDP_SCAN_TAB, "".to_owned())),
+            (Key::Char('t'), (TCP_SCAN_TAB, "".to_owned())),
+            (Key::Char('1'), (TCP_SCAN_TAB, "1".to_owned())),
+            (Key::Char(','), (TCP_SCAN_TAB, "1,".to_owned())),
+            (Key::Char('x'), (3, "1,".to_owned())),
+        ];
+
+        page_event_assertion(
+            &events,
+            Arc::new(Mutex::new(store)),
+            |state: &ApplicationState| {
+                let modal_state = state.modal_state.as_ref().unwrap();
+                (modal_state.tab_state.index, modal_state.port_query.clone())
+            },
+        )
+    }
+
     // TODO consolidate this with main_page assertion function
     fn page_event_assertion<
         AssertResult: PartialEq + std::fmt::Debug,
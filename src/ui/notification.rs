@@ -4,11 +4,13 @@ use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
     widgets::{Block, Borders, Clear, Paragraph, Wrap},
-    style::{Color, Style},
+    style::Style,
     text::{Spans, Span},
     Frame,
 };
 
+use crate::ui::theme::Theme;
+
 #[derive(Copy, Clone)]
 pub enum NotificationLevel {
     Info,
@@ -76,7 +78,7 @@ fn cornered_rect(r: Rect) -> Rect {
         .split(popup_layout[0])[1]
 }
 
-pub fn draw_notification<B: Backend>(notif: Notification, f: &mut Frame<B>) {
+pub fn draw_notification<B: Backend>(notif: Notification, theme: &Theme, f: &mut Frame<B>) {
     let area = cornered_rect(f.size());
 
     let msg_span = Spans::from(Span::from(notif.message));
@@ -89,8 +91,8 @@ pub fn draw_notification<B: Backend>(notif: Notification, f: &mut Frame<B>) {
                     Style::default()
                         .fg(
                             match notif.level {
-                                NotificationLevel::Info => Color::LightBlue,
-                                NotificationLevel::Warn => Color::LightRed,
+                                NotificationLevel::Info => theme.info,
+                                NotificationLevel::Warn => theme.alert,
                             }
                         )
                 )
@@ -2,21 +2,25 @@ use clipboard::{ClipboardContext, ClipboardProvider};
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
-    text::Span,
+    style::{Modifier, Style},
+    text::{Span, Spans},
     widgets::{Block, Borders, Cell, Gauge, Paragraph, Row, Table, TableState},
     Frame,
 };
 
-use crate::network::{host::Host, input_parse};
+use crate::network::rate_limit::RATE_LIMITER;
+use crate::network::{dispatch_dhcp_probe, host::Host, input_parse};
 use crate::state::actions::AppAction;
+use crate::state::application_state::{is_query_char, ApplicationState};
+use crate::state::host_filter::{FilterField, HostFilter};
 use crate::state::store::{AppStateStore, SharedAppStateStore};
 use crate::ui::modal::{Modal, ModalType};
-use crate::ui::pages::PageContent;
+use crate::ui::pages::{Page, PageContent};
 use crate::ui::{
     components::{
         search_filter::{draw_search_filter, SearchFilterOption},
         selectable_title::selectable_title,
+        styles::{border_style, selected_style},
     },
     notification::{Notification, NotificationLevel},
 };
@@ -24,6 +28,8 @@ use crate::ui::{
 use std::convert::TryInto;
 
 use crate::ui::event::Key;
+use crate::ui::keymap::{Command, KeyContext, Keymap};
+use crate::ui::theme::Theme;
 
 const JUMP_LEN: usize = 20;
 
@@ -110,6 +116,7 @@ pub fn draw_main_page<B: Backend>(store: SharedAppStateStore, f: &mut Frame<B>)
     let query = lstore.state.query.clone();
     let parse_err = lstore.state.input_err;
     let curr_focus = lstore.state.curr_focus;
+    let theme = lstore.state.app_config.theme;
 
     let rects = Layout::default()
         .direction(Direction::Vertical)
@@ -124,8 +131,8 @@ pub fn draw_main_page<B: Backend>(store: SharedAppStateStore, f: &mut Frame<B>)
         )
         .split(f.size());
 
-    let selected_border_style = Style::default().fg(Color::Yellow);
-    let default_border_style = Style::default().fg(Color::White);
+    let selected_border_style = border_style(&theme, true);
+    let default_border_style = border_style(&theme, false);
 
     let first_row = Layout::default()
         .direction(Direction::Horizontal)
@@ -138,7 +145,7 @@ pub fn draw_main_page<B: Backend>(store: SharedAppStateStore, f: &mut Frame<B>)
             .border_style(match curr_focus {
                 PageContent::QueryInput => {
                     if parse_err {
-                        selected_border_style.fg(Color::Red)
+                        selected_border_style.fg(theme.alert)
                     } else {
                         selected_border_style
                     }
@@ -163,40 +170,61 @@ pub fn draw_main_page<B: Backend>(store: SharedAppStateStore, f: &mut Frame<B>)
 
     let pcnt_done = (num_done * 100 / hosts_len) as u16;
 
+    // The probe rate, against the limit if there is one
+    let rate = RATE_LIMITER.effective_rate();
+    let gauge_title = match RATE_LIMITER.max_rate() {
+        Some(max_rate) => format!("Hosts scanned ({:.0}/{} pkt/s)", rate, max_rate),
+        None if rate > 0.0 => format!("Hosts scanned ({:.0} pkt/s)", rate),
+        None => "Hosts scanned".to_owned(),
+    };
     let gauge = Gauge::default()
-        .block(
-            Block::default()
-                .title("Hosts scanned")
-                .borders(Borders::ALL),
-        )
-        .gauge_style(Style::default().fg(Color::Yellow))
+        .block(Block::default().title(gauge_title).borders(Borders::ALL))
+        .gauge_style(Style::default().fg(theme.focus))
         .percent(pcnt_done);
 
     f.render_widget(gauge, rects[2]);
 
     // Render host table //
-    let selected_style = Style::default()
-        .bg(Color::Black)
-        .fg(Color::Yellow)
-        .add_modifier(Modifier::REVERSED);
-
-    let normal_style = Style::default().bg(Color::Rgb(23, 112, 191));
-
-    let header_cells = ["Host IP", "Hostname", "Status", "Ping type", "Ports open"]
-        .iter()
-        .map(|h| Cell::from(*h));
+    let selected_style = selected_style(&theme);
+
+    let normal_style = Style::default().bg(theme.header_bg);
+
+    let web_column = lstore.state.app_config.http_fingerprint;
+
+    let mut header_cells = vec![
+        "Host IP",
+        "Hostname",
+        "Status",
+        "Ping type",
+        "Ports open",
+        "OS",
+        "Flags",
+    ];
+    if web_column {
+        header_cells.push("Web");
+    }
+    let header_cells = header_cells.into_iter().map(Cell::from);
 
     let header = Row::new(header_cells)
         .style(normal_style)
         .height(1)
         .bottom_margin(1);
 
+    let filter = lstore.state.host_filter.as_ref();
+    let match_style = Style::default()
+        .fg(theme.accent)
+        .add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+    let cell = |field: FilterField, text: String| highlighted(text, field, filter, match_style);
+
     let rows = lstore.state.filtered_hosts().map(|host| {
         let mut style = Style::default();
         let mut status_cell = Cell::from("?");
         if let Some(dur) = host.ping_res {
             status_cell = Cell::from(format!("✓ ({:?} ms)", dur.as_millis()));
-            style = style.fg(Color::Green);
+            style = style.fg(theme.ok);
+        } else if host.alive_by_name {
+            status_cell = Cell::from("✓ (name only)");
+            style = style.fg(theme.warn);
         }
 
         let mut ping_cell = Cell::from("--");
@@ -205,32 +233,50 @@ pub fn draw_main_page<B: Backend>(store: SharedAppStateStore, f: &mut Frame<B>)
         if let Some(ping_type) = host.ping_type {
             ping_cell = Cell::from(ping_type.to_string());
 
-            port_cell = Cell::from(
-                host.tcp_ports
-                    .iter()
-                    .map(|p| p.to_string())
-                    .collect::<Vec<String>>()
-                    .join(","),
-            );
+            port_cell = cell(FilterField::Ports, FilterField::Ports.text(host));
         }
 
         if let Some(host_name) = &host.host_name {
             match host_name {
                 Ok(hn) => {
-                    style = style.fg(Color::Green);
-                    host_cell = Cell::from(hn.to_string())
+                    style = style.fg(theme.ok);
+                    host_cell = cell(FilterField::Name, hn.to_string())
                 }
                 Err(_) => host_cell = Cell::from("x"),
             }
         }
 
-        let cells = vec![
-            Cell::from(host.ip.to_string()),
+        let mut flags: Vec<&str> = vec![];
+        if lstore.state.dhcp_offer_from(&host.ip).is_some() {
+            flags.push("DHCP");
+        }
+        if lstore.state.arp_table.is_conflict(&host.ip) {
+            flags.push("⚠ IP");
+            style = style.fg(theme.alert);
+        } else if !lstore.state.arp_table.other_ips(&host.ip).is_empty() {
+            flags.push("⚠ MAC");
+        }
+
+        let mut cells = vec![
+            cell(FilterField::Ip, host.ip.to_string()),
             host_cell,
             status_cell,
             ping_cell,
             port_cell,
+            match &host.os {
+                Some(os) => cell(FilterField::Os, os.to_string()),
+                None => Cell::from("--"),
+            },
+            Cell::from(flags.join(" ")),
         ];
+        if web_column {
+            cells.push(Cell::from(
+                host.http
+                    .values()
+                    .next()
+                    .map_or_else(|| "--".to_owned(), |http_info| http_info.summary()),
+            ));
+        }
         Row::new(cells).style(style)
     });
 
@@ -240,54 +286,195 @@ pub fn draw_main_page<B: Backend>(store: SharedAppStateStore, f: &mut Frame<B>)
             PageContent::HostTable => selected_border_style,
             _ => default_border_style,
         })
-        .title(selectable_title("Hosts", Style::default()));
+        .title(hosts_title(&lstore.state, &theme));
+
+    let mut widths = vec![
+        Constraint::Length(18),
+        Constraint::Percentage(30),
+        Constraint::Length(15),
+        Constraint::Length(10),
+        Constraint::Max(10),
+        Constraint::Length(18),
+        Constraint::Length(10),
+    ];
+    if web_column {
+        widths[1] = Constraint::Percentage(20);
+        widths.push(Constraint::Percentage(25));
+    }
 
     let t = Table::new(rows)
         .header(header)
         .block(table_block)
         .highlight_style(selected_style)
-        .widths(&[
-            Constraint::Length(18),
-            Constraint::Percentage(30),
-            Constraint::Length(15),
-            Constraint::Length(10),
-            Constraint::Max(10),
-        ]);
+        .widths(&widths);
 
     f.render_stateful_widget(t, rects[1], &mut lstore.state.table_state);
 }
 
+// The quick filter is shown after the title, with the number of hosts left
+fn hosts_title<'a>(state: &ApplicationState, theme: &Theme) -> Spans<'a> {
+    let mut title = selectable_title("Hosts", Style::default()).0;
+    let query = match (&state.filter_input, &state.host_filter) {
+        (Some(input), _) => format!(" /{}▏", input),
+        (None, Some(filter)) => format!(" /{}", filter.query),
+        (None, None) => return Spans::from(title),
+    };
+    // Typed text that doesn't parse yet leaves the last filter in place
+    let invalid = matches!(&state.filter_input, Some(input) if HostFilter::parse(input).is_err());
+    title.push(Span::styled(
+        query,
+        Style::default().fg(if invalid { theme.alert } else { theme.focus }),
+    ));
+    title.push(Span::from(format!(
+        " ({} of {})",
+        state.filtered_hosts().count(),
+        state.hosts.len()
+    )));
+    Spans::from(title)
+}
+
+// Text with the parts matched by the quick filter highlighted
+fn highlighted<'a>(
+    text: String,
+    field: FilterField,
+    filter: Option<&HostFilter>,
+    style: Style,
+) -> Cell<'a> {
+    let ranges = match filter {
+        Some(filter) => filter.highlights(field, &text),
+        None => vec![],
+    };
+    if ranges.is_empty() {
+        return Cell::from(text);
+    }
+
+    let mut spans = vec![];
+    let mut pos = 0;
+    for range in ranges {
+        // Overlapping matches
+        if range.end <= pos {
+            continue;
+        }
+        let start = range.start.max(pos);
+        if start > pos {
+            spans.push(Span::raw(text[pos..start].to_owned()));
+        }
+        spans.push(Span::styled(text[start..range.end].to_owned(), style));
+        pos = range.end;
+    }
+    if pos < text.len() {
+        spans.push(Span::raw(text[pos..].to_owned()));
+    }
+    Cell::from(Spans::from(spans))
+}
+
+// Filters as it's typed, enter keeps the filter and esc drops it
+fn handle_filter_input(key: Key, mut input: String, store: &mut AppStateStore) {
+    match key {
+        Key::Enter => return store.dispatch(AppAction::SetFilterInput(None)),
+        Key::Esc => {
+            return store.dispatches(vec![
+                AppAction::SetFilterInput(None),
+                AppAction::SetHostFilter(None),
+                AppAction::TableSelect(None),
+            ])
+        }
+        Key::Backspace => {
+            input.pop();
+        }
+        Key::Char(c) | Key::Shift(c) => input.push(c),
+        _ => return,
+    }
+
+    let filter = match input.trim().is_empty() {
+        true => None,
+        false => match HostFilter::parse(&input) {
+            Ok(filter) => Some(filter),
+            Err(_) => return store.dispatch(AppAction::SetFilterInput(Some(input))),
+        },
+    };
+    store.dispatches(vec![
+        AppAction::SetFilterInput(Some(input)),
+        AppAction::SetHostFilter(filter),
+        AppAction::TableSelect(None),
+    ])
+}
+
+// The help is built from the keymap so it shows the keys actually bound
+fn help_message(keymap: &Keymap) -> String {
+    format!(
+        "Keyboard shortcuts:\n\
+        {}{}\
+        (Shortcuts are indicated by an underline character)\n\n\
+        Filter controls:\n\
+        - Use left/right arrows or space bar to cycle through filters\n\
+        - Use Down arrow to go back to host table\n\
+        - Quick filter terms: up, down, port:22, ip:, name:, mac:, os: or any text\n\
+        - Wrap text in slashes for a regex, enter keeps the filter and esc drops it\n\n\
+        Hosts table:\n\
+        {}{}\
+        - Press enter to see more information on that host\n\n\
+        In the Host Info modal:\n\
+        {}",
+        keymap.help(KeyContext::Main),
+        keymap.help(KeyContext::Global),
+        keymap.help(KeyContext::Lists),
+        keymap.help(KeyContext::HostTable),
+        keymap.help(KeyContext::HostModal),
+    )
+}
+
 // Page events handler
 // TODO: use keycode or key event here?
-pub fn handle_main_page_event(key: Key, store: &mut AppStateStore, _: SharedAppStateStore) {
+pub fn handle_main_page_event(key: Key, store: &mut AppStateStore, store_mtx: SharedAppStateStore) {
     // let mut store = store.lock().unwrap();
 
-    // Components selection shortcuts
-    match key {
-        Key::Char('h') => store.dispatch(AppAction::ShiftFocus(PageContent::HostTable)),
-        Key::Char('s') => store.dispatch(AppAction::ShiftFocus(PageContent::QueryInput)),
-        Key::Char('f') => store.dispatch(AppAction::ShiftFocus(PageContent::SearchFilters)),
-        Key::Char('?') => {
+    // Keys go to the quick filter while it's typed
+    if let Some(input) = store.state.filter_input.clone() {
+        return handle_filter_input(key, input, store);
+    }
+
+    // Components selection shortcuts, unless the key is typed into the query
+    let command = match store.state.types_key(key) {
+        true => None,
+        false => store
+            .state
+            .app_config
+            .keymap
+            .command(key, &[KeyContext::Global, KeyContext::Main]),
+    };
+    match command {
+        Some(Command::FocusHosts) => store.dispatch(AppAction::ShiftFocus(PageContent::HostTable)),
+        Some(Command::FocusSearch) => {
+            store.dispatch(AppAction::ShiftFocus(PageContent::QueryInput))
+        }
+        Some(Command::FocusFilters) => {
+            store.dispatch(AppAction::ShiftFocus(PageContent::SearchFilters))
+        }
+        Some(Command::Settings) => store.dispatch(AppAction::SetPage(Page::SettingsPage)),
+        Some(Command::DhcpProbe) => {
+            store.dispatch(AppAction::SetNotification(Some(Notification::info(
+                "DHCP probe",
+                "Looking for DHCP servers...",
+            ))));
+            dispatch_dhcp_probe(store_mtx)
+        }
+        Some(Command::QuickFilter) => {
+            let input = store
+                .state
+                .host_filter
+                .as_ref()
+                .map(|filter| filter.query.clone())
+                .unwrap_or_default();
+            store.dispatches(vec![
+                AppAction::SetFilterInput(Some(input)),
+                AppAction::ShiftFocus(PageContent::HostTable),
+            ])
+        }
+        Some(Command::Help) => {
             let modal = Modal::new(
                 "Help",
-                "Keyboard shortcuts:\n\
-                - Press 'h' to go to hosts table\n\
-                - Press 's' to go to query search (return to begin query)\n\
-                - Press 'f' to go to query filter\n\
-                (Shortcuts are indicated by an underline character)\n\n\
-                Filter controls:\n\
-                - Use left/right arrows or space bar to cycle through filters\n\
-                - Use Down arrow to go back to host table\n\n\
-                Navigate the hosts table with arrows or similar to Vim: \n\
-                - To go down one list item use down arrow or 'j' key\n\
-                - To go up one list item use down arrow or 'k' key\n\
-                - Use shift with the 'j' or 'k' key (or Page Up/Page Down) to move 20 items\n\
-                - Use the space bar to jump 20 items down\n\
-                - Press enter to see more information on that host\n\n\
-                In the Host Info modal: \n\
-                - Press 't' to go to TCP Port scan\n\
-                - Press 'h' to go to host info
-                ",
+                &help_message(&store.state.app_config.keymap),
                 ModalType::Ok,
             );
             store.dispatch(AppAction::SetModal(Some(modal)))
@@ -300,47 +487,29 @@ pub fn handle_main_page_event(key: Key, store: &mut AppStateStore, _: SharedAppS
         PageContent::HostTable => {
             let s_hosts: Vec<&Host> = store.state.filtered_hosts().collect();
             let s_table = StatefulTable::new(&store.state.table_state, &s_hosts);
-            if let Some(table_idx) = match key {
-                // Char inputs
-                Key::Down | Key::Char('j') => s_table.next(),
-                Key::Up | Key::Char('k') => s_table.prev(),
-                Key::Char(' ') | Key::Shift('J') | Key::PageDown => s_table.pgdn(),
-                Key::Ctrl(' ') | Key::Shift('K') | Key::PageUp => s_table.pgup(),
-                Key::Shift('G') => s_table.last(),
-                Key::Char('g') => s_table.first(),
-
-                // Focus shift
-                Key::Tab => {
-                    if store.state.modal.is_none() {
-                        store.dispatch(AppAction::ShiftFocus(PageContent::QueryInput))
-                    }
-                    None
-                }
-                Key::BackTab => {
-                    if store.state.modal.is_none() {
-                        store.dispatch(AppAction::ShiftFocus(PageContent::SearchFilters))
-                    }
-                    None
-                }
-
-                // Host drill down
-                Key::Enter => {
-                    if let Some(idx) = store.state.table_state.selected() {
-                        store.dispatch(AppAction::SetSelectedHost(Some(idx)));
-                    }
-                    None
-                }
-
-                // Copy host IP to clipboard
-                Key::Char('c') | Key::Shift('C') => {
+            let command = store
+                .state
+                .app_config
+                .keymap
+                .command(key, &[KeyContext::Lists, KeyContext::HostTable]);
+            if let Some(table_idx) = match (command, key) {
+                (Some(Command::Down), _) => s_table.next(),
+                (Some(Command::Up), _) => s_table.prev(),
+                (Some(Command::PageDown), _) => s_table.pgdn(),
+                (Some(Command::PageUp), _) => s_table.pgup(),
+                (Some(Command::Last), _) => s_table.last(),
+                (Some(Command::First), _) => s_table.first(),
+
+                // Copy host IP or name to clipboard
+                (Some(command @ (Command::CopyIp | Command::CopyHostname)), _) => {
                     if let Some(host_idx) = store.state.table_state.selected() {
                         let mut notif = Notification::default();
 
                         match ClipboardProvider::new() {
                             Ok::<ClipboardContext, Box<dyn std::error::Error>>(mut ctx) => {
                                 let hosts: Vec<&Host> = store.state.filtered_hosts().collect();
-                                match key {
-                                    Key::Char('c') => {
+                                match command {
+                                    Command::CopyIp => {
                                         let host_ip = hosts[host_idx].ip;
                                         notif.message = format!(
                                             "Address {} copied to clipboard",
@@ -348,7 +517,7 @@ pub fn handle_main_page_event(key: Key, store: &mut AppStateStore, _: SharedAppS
                                         );
                                         ctx.set_contents(host_ip.to_string()).unwrap();
                                     }
-                                    Key::Shift('C') => {
+                                    _ => {
                                         if let Some(Ok(hostname)) =
                                             hosts[host_idx].host_name.as_ref()
                                         {
@@ -359,7 +528,6 @@ pub fn handle_main_page_event(key: Key, store: &mut AppStateStore, _: SharedAppS
                                             ctx.set_contents(hostname.to_owned()).unwrap();
                                         }
                                     }
-                                    _ => {}
                                 }
                             }
                             Err(_) => {
@@ -374,6 +542,39 @@ pub fn handle_main_page_event(key: Key, store: &mut AppStateStore, _: SharedAppS
                     None
                 }
 
+                // Focus shift
+                (_, Key::Tab) => {
+                    if store.state.modal.is_none() {
+                        store.dispatch(AppAction::ShiftFocus(PageContent::QueryInput))
+                    }
+                    None
+                }
+                (_, Key::BackTab) => {
+                    if store.state.modal.is_none() {
+                        store.dispatch(AppAction::ShiftFocus(PageContent::SearchFilters))
+                    }
+                    None
+                }
+
+                // Drop the quick filter
+                (_, Key::Esc) => {
+                    if store.state.host_filter.is_some() {
+                        store.dispatches(vec![
+                            AppAction::SetHostFilter(None),
+                            AppAction::TableSelect(None),
+                        ])
+                    }
+                    None
+                }
+
+                // Host drill down
+                (_, Key::Enter) => {
+                    if let Some(idx) = store.state.table_state.selected() {
+                        store.dispatch(AppAction::SetSelectedHost(Some(idx)));
+                    }
+                    None
+                }
+
                 _ => None,
             } {
                 store.dispatch(AppAction::TableSelect(Some(table_idx)))
@@ -514,7 +715,7 @@ pub fn handle_main_page_event(key: Key, store: &mut AppStateStore, _: SharedAppS
                 }
 
                 Key::Char(c) => {
-                    if c.is_numeric() || c == '.' || c == '/' {
+                    if is_query_char(c) {
                         let mut q = store.state.query.to_owned();
                         q.push(c);
                         store.dispatch(AppAction::SetQuery(q));
@@ -618,6 +819,51 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_main_page_query_input_cidr() {
+        // '/' is typed into the query, not the quick filter shortcut
+        let mut store = AppStateStore::new();
+        store.state.curr_focus = PageContent::QueryInput;
+        let lstore = Arc::new(Mutex::new(store));
+
+        for c in "10.0.0.0/24".chars() {
+            handle_main_page_event(
+                Key::Char(c),
+                lstore.lock().unwrap().deref_mut(),
+                lstore.clone(),
+            );
+        }
+
+        let store = lstore.lock().unwrap();
+        assert_eq!(store.state.query, "10.0.0.0/24");
+        assert!(store.state.filter_input.is_none());
+        assert_eq!(store.state.curr_focus, PageContent::QueryInput);
+    }
+
+    #[test]
+    fn test_main_page_query_input_bound_keys() {
+        // Shortcuts bound to keys of a query are typed while the query has focus
+        let mut store = AppStateStore::new();
+        store.state.app_config.keymap = Keymap::with_bindings(vec![
+            (Command::FocusHosts, vec![Key::Char('1')]),
+            (Command::Help, vec![Key::Char('.')]),
+        ])
+        .unwrap();
+        store.state.curr_focus = PageContent::QueryInput;
+
+        let events: [(Key, (String, PageContent)); 3] = [
+            (Key::Char('1'), ("1".to_owned(), PageContent::QueryInput)),
+            (Key::Char('.'), ("1.".to_owned(), PageContent::QueryInput)),
+            (Key::Char('s'), ("1.".to_owned(), PageContent::QueryInput)),
+        ];
+
+        main_page_event_assertion(
+            &events,
+            Arc::new(Mutex::new(store)),
+            |state: &ApplicationState| (state.query.to_owned(), state.curr_focus),
+        );
+    }
+
     #[test]
     fn test_main_page_query_input_enter_ok() {
         let mut store = AppStateStore::new();
@@ -762,6 +1008,32 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_main_page_quick_filter() {
+        // 10.0.0.2, 10.0.0.12 and 10.0.0.20-29 contain a 2
+        let events: [(Key, (usize, bool)); 7] = [
+            (Key::Char('/'), (30, true)),
+            (Key::Char('2'), (12, true)),
+            (Key::Char('9'), (1, true)),
+            (Key::Backspace, (12, true)),
+            // Keys are typed, not run as commands
+            (Key::Char('j'), (0, true)),
+            (Key::Backspace, (12, true)),
+            (Key::Enter, (12, false)),
+        ];
+
+        let store = Arc::new(Mutex::new(AppStateStore::new()));
+        main_page_event_assertion(&events, store.clone(), |state: &ApplicationState| {
+            (state.filtered_hosts().count(), state.filter_input.is_some())
+        });
+
+        let mut store = store.lock().unwrap();
+        assert_eq!(store.state.host_filter.as_ref().unwrap().query, "2");
+        let other = Arc::new(Mutex::new(AppStateStore::new()));
+        handle_main_page_event(Key::Esc, store.deref_mut(), other);
+        assert!(store.state.host_filter.is_none());
+    }
+
     fn main_page_event_assertion<
         AssertResult: PartialEq + std::fmt::Debug,
         F: Fn(&ApplicationState) -> AssertResult,
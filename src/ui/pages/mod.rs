@@ -8,20 +8,26 @@ use crate::ui::event::Key;
 use crate::state::store::{SharedAppStateStore, AppStateStore};
 
 pub mod main_page;
+pub mod settings_page;
 
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
 pub enum Page {
+    #[default]
     MainPage,
+    SettingsPage,
 }
 
 pub fn draw_page<B: Backend>(curr_page: &Page, store: SharedAppStateStore, f: &mut Frame<B>) {
     match curr_page {
-        Page::MainPage => main_page::draw_main_page(store, f)
+        Page::MainPage => main_page::draw_main_page(store, f),
+        Page::SettingsPage => settings_page::draw_settings_page(store, f),
     }
 }
 
 pub fn handle_page_events(curr_page: &Page, key: Key, store: &mut AppStateStore, store_mtx: SharedAppStateStore) {
     match curr_page {
-        Page::MainPage => main_page::handle_main_page_event(key, store, store_mtx)
+        Page::MainPage => main_page::handle_main_page_event(key, store, store_mtx),
+        Page::SettingsPage => settings_page::handle_settings_page_event(key, store),
     }
 }
 
@@ -0,0 +1,302 @@
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout},
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
+    Frame,
+};
+
+use crate::config::file::write_settings;
+use crate::state::actions::AppAction;
+use crate::state::settings_state::SETTING_FIELDS;
+use crate::state::store::{AppStateStore, SharedAppStateStore};
+use crate::ui::components::styles::{border_style, selected_style};
+use crate::ui::event::Key;
+use crate::ui::keymap::{Command, KeyContext};
+use crate::ui::notification::{Notification, NotificationLevel};
+use crate::ui::pages::Page;
+
+pub fn draw_settings_page<B: Backend>(store: SharedAppStateStore, f: &mut Frame<B>) {
+    let lstore = store.lock().unwrap();
+    let state = &lstore.state;
+    let settings_state = &state.settings_state;
+    let theme = &state.app_config.theme;
+
+    let rects = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Min(10), Constraint::Length(3)].as_ref())
+        .split(f.size());
+
+    let selected_style = selected_style(theme);
+    let editing_style = Style::default().fg(theme.focus);
+
+    let header = Row::new(vec!["Setting", "Value", ""])
+        .style(Style::default().add_modifier(Modifier::BOLD))
+        .bottom_margin(1);
+
+    let rows = SETTING_FIELDS.iter().enumerate().map(|(idx, field)| {
+        let value = match &settings_state.input {
+            Some(input) if idx == settings_state.selected => {
+                Cell::from(format!("{}▏", input)).style(editing_style)
+            }
+            _ => Cell::from(field.value(state)),
+        };
+        Row::new(vec![
+            Cell::from(field.name()),
+            value,
+            Cell::from(field.description()).style(Style::default().fg(theme.muted)),
+        ])
+    });
+
+    let title = match &state.config_file {
+        Some(path) => format!("Settings ({})", path.display()),
+        None => "Settings".to_owned(),
+    };
+    let table = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(border_style(theme, true))
+                .title(title),
+        )
+        .highlight_style(match settings_state.input {
+            Some(_) => Style::default(),
+            None => selected_style,
+        })
+        .widths(&[
+            Constraint::Length(22),
+            Constraint::Percentage(40),
+            Constraint::Percentage(50),
+        ]);
+
+    let mut table_state = TableState::default();
+    table_state.select(Some(settings_state.selected));
+    f.render_stateful_widget(table, rects[0], &mut table_state);
+
+    let keymap = &state.app_config.keymap;
+    let keys = match settings_state.input {
+        Some(_) => "Enter: apply | Esc: cancel".to_owned(),
+        None => format!(
+            "{}/{}: select | Enter: edit (or next choice) | {}: save to config file | Esc/{}: back",
+            keymap.key_name(Command::Down),
+            keymap.key_name(Command::Up),
+            keymap.key_name(Command::SaveSettings),
+            keymap.key_name(Command::Settings),
+        ),
+    };
+    let help = Paragraph::new(Spans::from(Span::from(keys)))
+        .block(Block::default().borders(Borders::ALL).title("Keys"));
+    f.render_widget(help, rects[1]);
+}
+
+// Changes apply as soon as they're entered, scans already running keep their
+// settings
+pub fn handle_settings_page_event(key: Key, store: &mut AppStateStore) {
+    let selected = store.state.settings_state.selected;
+    let field = SETTING_FIELDS[selected];
+
+    match store.state.settings_state.input.clone() {
+        Some(mut input) => match key {
+            Key::Enter => match field.apply(&input, &store.state) {
+                Ok(action) => {
+                    store.dispatch(action);
+                    store.dispatch(AppAction::SettingEdited(field));
+                    store.dispatch(AppAction::SetSettingsInput(None));
+                    store.dispatch(AppAction::SetNotification(None))
+                }
+                Err(err) => store.dispatch(AppAction::SetNotification(Some(Notification::new(
+                    field.name(),
+                    &format!("{}", err),
+                    NotificationLevel::Warn,
+                )))),
+            },
+            Key::Esc => {
+                store.dispatch(AppAction::SetSettingsInput(None));
+                store.dispatch(AppAction::SetNotification(None))
+            }
+            Key::Backspace => {
+                input.pop();
+                store.dispatch(AppAction::SetSettingsInput(Some(input)))
+            }
+            Key::Char(c) | Key::Shift(c) => {
+                input.push(c);
+                store.dispatch(AppAction::SetSettingsInput(Some(input)))
+            }
+            _ => {}
+        },
+
+        None => match (
+            store.state.app_config.keymap.command(
+                key,
+                &[
+                    KeyContext::Global,
+                    KeyContext::Lists,
+                    KeyContext::SettingsPage,
+                ],
+            ),
+            key,
+        ) {
+            (Some(Command::Down), _) => store.dispatch(AppAction::SetSettingsSelected(
+                (selected + 1) % SETTING_FIELDS.len(),
+            )),
+            (Some(Command::Up), _) => store.dispatch(AppAction::SetSettingsSelected(
+                (selected + SETTING_FIELDS.len() - 1) % SETTING_FIELDS.len(),
+            )),
+            (_, Key::Enter) => match field.next_choice(&store.state) {
+                // Choices can't be invalid
+                Some(choice) => {
+                    if let Ok(action) = field.apply(&choice, &store.state) {
+                        store.dispatch(action);
+                        store.dispatch(AppAction::SettingEdited(field))
+                    }
+                }
+                None => {
+                    let value = field.value(&store.state);
+                    store.dispatch(AppAction::SetSettingsInput(Some(value)))
+                }
+            },
+            (Some(Command::SaveSettings), _) => save_settings(store),
+            (Some(Command::Settings), _) | (_, Key::Esc) => {
+                store.dispatch(AppAction::SetNotification(None));
+                store.dispatch(AppAction::SetPage(Page::MainPage))
+            }
+            _ => {}
+        },
+    }
+}
+
+// Only what was changed here, values from flags or the file's timing template
+// stay out of the file
+fn save_settings(store: &mut AppStateStore) {
+    let state = &store.state;
+    let notification = match &state.config_file {
+        Some(_) if state.settings_state.edited.is_empty() => {
+            Notification::info("Settings", "No changes to save")
+        }
+        Some(path) => {
+            let values = state
+                .settings_state
+                .edited
+                .iter()
+                .map(|field| {
+                    let (section, key) = field.config_key();
+                    (section, key, field.config_value(state))
+                })
+                .collect();
+            let target = match &state.config_profile {
+                Some(profile) => format!("profile {} in {}", profile, path.display()),
+                None => path.display().to_string(),
+            };
+            match write_settings(path, state.config_profile.as_deref(), values) {
+                Ok(_) => Notification::info("Settings", &format!("Saved to {}", target)),
+                Err(err) => Notification::new(
+                    "Settings",
+                    &format!("Could not save to {}: {}", path.display(), err),
+                    NotificationLevel::Warn,
+                ),
+            }
+        }
+        None => Notification::new(
+            "Settings",
+            "No config file location, set $HOME or use --config",
+            NotificationLevel::Warn,
+        ),
+    };
+    store.dispatch(AppAction::SetNotification(Some(notification)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{file::Settings, AppConfig};
+    use crate::state::settings_state::SettingField;
+
+    #[test]
+    fn test_settings_page_edit() {
+        let mut store = AppStateStore::new();
+        store.dispatch(AppAction::SetPage(Page::SettingsPage));
+
+        // Workers is first, replace its value
+        handle_settings_page_event(Key::Enter, &mut store);
+        assert_eq!(store.state.settings_state.input.as_deref(), Some("100"));
+        for key in [
+            Key::Backspace,
+            Key::Backspace,
+            Key::Backspace,
+            Key::Char('x'),
+        ] {
+            handle_settings_page_event(key, &mut store);
+        }
+
+        // Invalid input is kept for fixing
+        handle_settings_page_event(Key::Enter, &mut store);
+        assert!(store.state.notification.is_some());
+        assert_eq!(store.state.app_config.nworkers, 100);
+
+        for key in [Key::Backspace, Key::Char('4'), Key::Char('0'), Key::Enter] {
+            handle_settings_page_event(key, &mut store);
+        }
+        assert_eq!(store.state.app_config.nworkers, 40);
+        assert!(store.state.settings_state.input.is_none());
+
+        // Choices are cycled
+        let mode_idx = SETTING_FIELDS
+            .iter()
+            .position(|&field| field == SettingField::PingMode)
+            .unwrap();
+        store.dispatch(AppAction::SetSettingsSelected(mode_idx));
+        handle_settings_page_event(Key::Enter, &mut store);
+        assert_eq!(SettingField::PingMode.value(&store.state), "parallel");
+
+        handle_settings_page_event(Key::Esc, &mut store);
+        assert_eq!(store.state.curr_page, Page::MainPage);
+    }
+
+    #[test]
+    fn test_save_settings() {
+        let path = std::env::temp_dir().join(format!("findi-save-{}.toml", std::process::id()));
+        let mut store = AppStateStore::new();
+        store.dispatch(AppAction::SetConfigFile(Some(path.clone())));
+        store.dispatch(AppAction::SetConfigProfile(Some("lan".to_owned())));
+
+        save_settings(&mut store);
+        assert!(!path.exists());
+
+        // Only the edited field is written, under the profile
+        for key in [Key::Enter, Key::Backspace, Key::Char('5'), Key::Enter] {
+            handle_settings_page_event(key, &mut store);
+        }
+        save_settings(&mut store);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents.trim(), "[profiles.lan.scan]\nworkers = 105");
+    }
+
+    #[test]
+    fn test_save_empty_ports() {
+        let path = std::env::temp_dir().join(format!("findi-ports-{}.toml", std::process::id()));
+        let mut store = AppStateStore::new();
+        store.dispatch(AppAction::SetConfigFile(Some(path.clone())));
+        store.dispatch(AppAction::SetPortQuery(Some("22".to_owned())));
+        store.state.settings_state.selected = SETTING_FIELDS
+            .iter()
+            .position(|&field| field == SettingField::TcpPorts)
+            .unwrap();
+
+        // Cleared on the page, the list is saved empty and loads as no ports
+        for key in [Key::Enter, Key::Backspace, Key::Backspace, Key::Enter] {
+            handle_settings_page_event(key, &mut store);
+        }
+        save_settings(&mut store);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents.trim(), "[ports]\ntcp = \"\"");
+
+        let settings = Settings::parse(&contents, None).unwrap();
+        settings.apply(&mut AppConfig::default()).unwrap();
+        assert_eq!(settings.ports.tcp.as_deref(), Some(""));
+    }
+}
@@ -0,0 +1,173 @@
+// Colors used by all drawing code, picked by role rather than hue
+// A few themes are built in, custom ones are defined in the config file on
+// top of one of them.
+
+use anyhow::{anyhow, Result};
+use tui::style::Color;
+
+pub const BUILTIN_THEMES: [&str; 4] = ["default", "light", "high-contrast", "monochrome"];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Theme {
+    // Borders of unfocused boxes
+    pub border: Color,
+    // Focused boxes, inputs, gauges and the selected row
+    pub focus: Color,
+    pub selected_bg: Color,
+    pub header_bg: Color,
+    // Live hosts, open ports, the selected modal option
+    pub ok: Color,
+    // Partial results, like hosts only alive by name
+    pub warn: Color,
+    // Conflicts, bad input and warnings
+    pub alert: Color,
+    pub info: Color,
+    // Tab bar of the host modal, quick filter matches
+    pub accent: Color,
+    // Secondary text
+    pub muted: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            border: Color::White,
+            focus: Color::Yellow,
+            selected_bg: Color::Black,
+            header_bg: Color::Rgb(23, 112, 191),
+            ok: Color::Green,
+            warn: Color::Yellow,
+            alert: Color::LightRed,
+            info: Color::LightBlue,
+            accent: Color::Cyan,
+            muted: Color::Gray,
+        }
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::default()),
+            // For terminals with a light background
+            "light" => Some(Theme {
+                border: Color::Black,
+                focus: Color::Blue,
+                selected_bg: Color::White,
+                header_bg: Color::Rgb(173, 205, 235),
+                ok: Color::Rgb(0, 120, 0),
+                warn: Color::Rgb(160, 110, 0),
+                alert: Color::Red,
+                info: Color::Blue,
+                accent: Color::Magenta,
+                muted: Color::DarkGray,
+            }),
+            "high-contrast" => Some(Theme {
+                border: Color::White,
+                focus: Color::LightYellow,
+                selected_bg: Color::Black,
+                header_bg: Color::Blue,
+                ok: Color::LightGreen,
+                warn: Color::LightYellow,
+                alert: Color::LightRed,
+                info: Color::LightCyan,
+                accent: Color::LightCyan,
+                muted: Color::White,
+            }),
+            // The terminal's own colors, selection is still shown reversed
+            "monochrome" => Some(Theme {
+                border: Color::Reset,
+                focus: Color::Reset,
+                selected_bg: Color::Reset,
+                header_bg: Color::Reset,
+                ok: Color::Reset,
+                warn: Color::Reset,
+                alert: Color::Reset,
+                info: Color::Reset,
+                accent: Color::Reset,
+                muted: Color::Reset,
+            }),
+            _ => None,
+        }
+    }
+
+    // Sets a color by its role name, as used in the config file
+    pub fn set(&mut self, role: &str, color: Color) -> Result<()> {
+        let slot = match role {
+            "border" => &mut self.border,
+            "focus" => &mut self.focus,
+            "selected_bg" => &mut self.selected_bg,
+            "header_bg" => &mut self.header_bg,
+            "ok" => &mut self.ok,
+            "warn" => &mut self.warn,
+            "alert" => &mut self.alert,
+            "info" => &mut self.info,
+            "accent" => &mut self.accent,
+            "muted" => &mut self.muted,
+            _ => return Err(anyhow!("Unknown theme color {}", role)),
+        };
+        *slot = color;
+        Ok(())
+    }
+}
+
+// A color name like "lightred", or "#rrggbb"
+pub fn parse_color(s: &str) -> Result<Color> {
+    let name = s.trim().to_lowercase().replace(['_', '-', ' '], "");
+    let color = match name.as_str() {
+        "reset" | "none" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        hex if hex.starts_with('#') && hex.len() == 7 => {
+            let rgb =
+                u32::from_str_radix(&hex[1..], 16).map_err(|_| anyhow!("Invalid color {}", s))?;
+            Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+        }
+        _ => {
+            return Err(anyhow!(
+                "Invalid color {}, use a name like lightblue or #rrggbb",
+                s
+            ))
+        }
+    };
+    Ok(color)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("LightRed").unwrap(), Color::LightRed);
+        assert_eq!(parse_color("dark_gray").unwrap(), Color::DarkGray);
+        assert_eq!(parse_color("#1770bf").unwrap(), Theme::default().header_bg);
+        assert!(parse_color("#17").is_err());
+        assert!(parse_color("ultraviolet").is_err());
+    }
+
+    #[test]
+    fn test_builtin_themes() {
+        for name in BUILTIN_THEMES.iter() {
+            assert!(Theme::builtin(name).is_some(), "{}", name);
+        }
+        let mut theme = Theme::builtin("monochrome").unwrap();
+        theme.set("focus", Color::Magenta).unwrap();
+        assert_eq!(theme.focus, Color::Magenta);
+        assert!(theme.set("background", Color::Black).is_err());
+    }
+}